@@ -0,0 +1,238 @@
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::ext::IdentExt;
+use syn::{parse_macro_input, Data, DeriveInput, Error, Fields, LitStr, Result};
+
+/// Derives `mongo_tracing::typed::Fields`, generating a `<Name>Fields` struct with one method
+/// per field returning its typed path.
+///
+/// Field names follow `#[serde(rename = "...")]`, `#[serde(rename_all = "...")]` and
+/// `#[serde(skip)]`; `#[serde(flatten)]` fields and fields marked `#[fields(nested)]` return the
+/// paths of their own type, which must derive `Fields` too.
+#[proc_macro_derive(Fields, attributes(fields))]
+pub fn derive_fields(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+fn expand(input: DeriveInput) -> Result<TokenStream2> {
+    if !input.generics.params.is_empty() {
+        return Err(Error::new_spanned(
+            &input.generics,
+            "Fields cannot be derived for generic types",
+        ));
+    }
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(Error::new_spanned(
+                    &input.ident,
+                    "Fields can only be derived for structs with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(Error::new_spanned(
+                &input.ident,
+                "Fields can only be derived for structs",
+            ))
+        }
+    };
+
+    let rename_all = serde_rename_all(&input.attrs)?;
+    let name = &input.ident;
+    let vis = &input.vis;
+    let paths = format_ident!("{}Fields", name);
+
+    let mut methods = Vec::new();
+    for field in fields {
+        let options = FieldOptions::parse(field)?;
+        if options.skip {
+            continue;
+        }
+        let ident = field.ident.as_ref().expect("named field");
+        let ty = &field.ty;
+        let key = match options.rename {
+            Some(rename) => rename,
+            None => rename(&ident.unraw().to_string(), rename_all.as_deref())?,
+        };
+        let doc = format!("The path of `{}`.", ident.unraw());
+        methods.push(if options.flatten {
+            quote! {
+                #[doc = #doc]
+                pub fn #ident(&self) -> <#ty as ::mongo_tracing::typed::Fields>::Paths<R> {
+                    <#ty as ::mongo_tracing::typed::Fields>::paths(&self.prefix)
+                }
+            }
+        } else if options.nested {
+            quote! {
+                #[doc = #doc]
+                pub fn #ident(&self) -> <#ty as ::mongo_tracing::typed::Fields>::Paths<R> {
+                    <#ty as ::mongo_tracing::typed::Fields>::paths(
+                        &::std::format!("{}{}.", self.prefix, #key),
+                    )
+                }
+            }
+        } else {
+            quote! {
+                #[doc = #doc]
+                pub fn #ident(&self) -> ::mongo_tracing::typed::Field<R, #ty> {
+                    ::mongo_tracing::typed::Field::new(::std::format!("{}{}", self.prefix, #key))
+                }
+            }
+        });
+    }
+
+    let doc = format!(
+        "The field paths of [`{}`], rooted at documents of type `R`.",
+        name
+    );
+    Ok(quote! {
+        #[doc = #doc]
+        #vis struct #paths<R> {
+            prefix: ::std::string::String,
+            root: ::std::marker::PhantomData<fn() -> R>,
+        }
+
+        impl<R> #paths<R> {
+            #(#methods)*
+        }
+
+        impl ::mongo_tracing::typed::Fields for #name {
+            type Paths<R> = #paths<R>;
+
+            fn paths<R>(prefix: &str) -> #paths<R> {
+                #paths {
+                    prefix: prefix.to_string(),
+                    root: ::std::marker::PhantomData,
+                }
+            }
+        }
+    })
+}
+
+#[derive(Default)]
+struct FieldOptions {
+    rename: Option<String>,
+    skip: bool,
+    flatten: bool,
+    nested: bool,
+}
+
+impl FieldOptions {
+    fn parse(field: &syn::Field) -> Result<Self> {
+        let mut options = FieldOptions::default();
+        for attr in &field.attrs {
+            if attr.path().is_ident("serde") {
+                attr.parse_nested_meta(|meta| {
+                    if meta.path.is_ident("rename") {
+                        if let Ok(value) = meta.value() {
+                            options.rename = Some(value.parse::<LitStr>()?.value());
+                        } else {
+                            // `rename(serialize = "...", deserialize = "...")`: queries match
+                            // the stored, serialized name.
+                            meta.parse_nested_meta(|inner| {
+                                let value = inner.value()?.parse::<LitStr>()?.value();
+                                if inner.path.is_ident("serialize") {
+                                    options.rename = Some(value);
+                                }
+                                Ok(())
+                            })?;
+                        }
+                    } else if meta.path.is_ident("skip") || meta.path.is_ident("skip_serializing") {
+                        options.skip = true;
+                    } else if meta.path.is_ident("flatten") {
+                        options.flatten = true;
+                    } else {
+                        skip_value(&meta)?;
+                    }
+                    Ok(())
+                })?;
+            } else if attr.path().is_ident("fields") {
+                attr.parse_nested_meta(|meta| {
+                    if meta.path.is_ident("nested") {
+                        options.nested = true;
+                        Ok(())
+                    } else {
+                        Err(meta.error("expected `nested`"))
+                    }
+                })?;
+            }
+        }
+        Ok(options)
+    }
+}
+
+fn serde_rename_all(attrs: &[syn::Attribute]) -> Result<Option<String>> {
+    let mut rename_all = None;
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("serde")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("rename_all") {
+                if let Ok(value) = meta.value() {
+                    rename_all = Some(value.parse::<LitStr>()?.value());
+                } else {
+                    meta.parse_nested_meta(|inner| {
+                        let value = inner.value()?.parse::<LitStr>()?.value();
+                        if inner.path.is_ident("serialize") {
+                            rename_all = Some(value);
+                        }
+                        Ok(())
+                    })?;
+                }
+            } else {
+                skip_value(&meta)?;
+            }
+            Ok(())
+        })?;
+    }
+    Ok(rename_all)
+}
+
+/// Consumes the value or nested list of a serde option this macro ignores.
+fn skip_value(meta: &syn::meta::ParseNestedMeta) -> Result<()> {
+    if let Ok(value) = meta.value() {
+        value.parse::<syn::Expr>()?;
+    } else if meta.input.peek(syn::token::Paren) {
+        meta.parse_nested_meta(|inner| skip_value(&inner))?;
+    }
+    Ok(())
+}
+
+/// Applies a serde `rename_all` rule to a snake_case field name.
+fn rename(field: &str, rule: Option<&str>) -> Result<String> {
+    let words = field.split('_').filter(|word| !word.is_empty());
+    let capitalized = |word: &str| {
+        let mut chars = word.chars();
+        chars
+            .next()
+            .map(|first| first.to_uppercase().chain(chars).collect::<String>())
+            .unwrap_or_default()
+    };
+    Ok(match rule {
+        None | Some("snake_case") => field.to_string(),
+        Some("lowercase") => field.to_lowercase(),
+        Some("UPPERCASE") => field.to_uppercase(),
+        Some("PascalCase") => words.map(capitalized).collect(),
+        Some("camelCase") => {
+            let pascal: String = words.map(capitalized).collect();
+            let mut chars = pascal.chars();
+            chars
+                .next()
+                .map(|first| first.to_lowercase().chain(chars).collect())
+                .unwrap_or_default()
+        }
+        Some("SCREAMING_SNAKE_CASE") => field.to_uppercase(),
+        Some("kebab-case") => field.replace('_', "-"),
+        Some("SCREAMING-KEBAB-CASE") => field.replace('_', "-").to_uppercase(),
+        Some(other) => {
+            return Err(Error::new(
+                proc_macro2::Span::call_site(),
+                format!("unknown rename_all rule `{}`", other),
+            ))
+        }
+    })
+}
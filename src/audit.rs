@@ -0,0 +1,609 @@
+#[cfg(feature = "subscriber")]
+use std::fmt;
+use std::future::Future;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use mongodb::bson::{Bson, DateTime, Document};
+use mongodb::error::Result;
+use mongodb::options::UpdateModifications;
+use mongodb::ClientSession;
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "subscriber")]
+use tracing::field::{Field, Visit};
+#[cfg(feature = "subscriber")]
+use tracing::span::{Attributes, Id};
+#[cfg(feature = "subscriber")]
+use tracing_subscriber::layer::Context;
+#[cfg(feature = "subscriber")]
+use tracing_subscriber::registry::{LookupSpan, Registry};
+
+use crate::database::InstrumentedDatabase;
+use crate::interceptor::{Operation, Outcome};
+use crate::mongo_tracing::InstrumentedCollection;
+use crate::shape;
+
+tokio::task_local! {
+    static ACTOR: String;
+}
+
+/// Runs `future` with `actor` as the author of the audited writes it makes.
+pub async fn with_actor<F: Future>(actor: impl Into<String>, future: F) -> F::Output {
+    ACTOR.scope(actor.into(), future).await
+}
+
+/// The actor set by the enclosing [`with_actor`], if any.
+pub fn current_actor() -> Option<String> {
+    ACTOR.try_with(Clone::clone).ok()
+}
+
+/// A [`Layer`](tracing_subscriber::Layer) keeping the value of a span field, `actor` unless set
+/// with [`ActorLayer::field`], as the author of the audited writes made inside the span.
+///
+/// Tracing only hands field values to the subscriber, so the layer stores them in the span's
+/// extensions; it must sit on a [`Registry`].
+#[cfg(feature = "subscriber")]
+#[derive(Clone, Debug)]
+pub struct ActorLayer {
+    field: &'static str,
+}
+
+#[cfg(feature = "subscriber")]
+impl Default for ActorLayer {
+    fn default() -> Self {
+        ActorLayer { field: "actor" }
+    }
+}
+
+#[cfg(feature = "subscriber")]
+impl ActorLayer {
+    pub fn new() -> Self {
+        ActorLayer::default()
+    }
+
+    pub fn field(mut self, field: &'static str) -> Self {
+        self.field = field;
+        self
+    }
+
+    fn keep<S>(&self, id: &Id, values: &tracing::span::Record<'_>, ctx: Context<'_, S>)
+    where
+        S: tracing::Subscriber + for<'a> LookupSpan<'a>,
+    {
+        let mut visitor = ActorField {
+            field: self.field,
+            actor: None,
+        };
+        values.record(&mut visitor);
+        if let (Some(actor), Some(span)) = (visitor.actor, ctx.span(id)) {
+            span.extensions_mut().replace(SpanActor(actor));
+        }
+    }
+}
+
+#[cfg(feature = "subscriber")]
+impl<S> tracing_subscriber::Layer<S> for ActorLayer
+where
+    S: tracing::Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attributes: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        self.keep(id, &tracing::span::Record::new(attributes.values()), ctx);
+    }
+
+    fn on_record(&self, id: &Id, values: &tracing::span::Record<'_>, ctx: Context<'_, S>) {
+        self.keep(id, values, ctx);
+    }
+}
+
+#[cfg(feature = "subscriber")]
+struct SpanActor(String);
+
+#[cfg(feature = "subscriber")]
+struct ActorField {
+    field: &'static str,
+    actor: Option<String>,
+}
+
+#[cfg(feature = "subscriber")]
+impl Visit for ActorField {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == self.field {
+            self.actor = Some(value.to_string());
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == self.field {
+            self.actor = Some(format!("{:?}", value));
+        }
+    }
+}
+
+/// The actor an [`ActorLayer`] kept on the current span or its closest ancestor with one.
+#[cfg(feature = "subscriber")]
+fn span_actor() -> Option<String> {
+    let id = tracing::Span::current().id()?;
+    tracing::dispatcher::get_default(|dispatch| {
+        let registry = dispatch.downcast_ref::<Registry>()?;
+        let span = registry.span(&id)?;
+        span.scope().find_map(|span| {
+            let extensions = span.extensions();
+            extensions.get::<SpanActor>().map(|actor| actor.0.clone())
+        })
+    })
+}
+
+#[cfg(not(feature = "subscriber"))]
+fn span_actor() -> Option<String> {
+    None
+}
+
+/// One audited write.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditRecord {
+    pub actor: Option<String>,
+    pub operation: String,
+    pub namespace: String,
+    pub filter: Option<Document>,
+    pub update: Option<Bson>,
+    pub affected_ids: Vec<Bson>,
+    pub trace_id: Option<String>,
+    pub timestamp: DateTime,
+}
+
+/// Where audit records go; `session` is set when the write ran in a session and the
+/// [`Audit`] was configured with [`Audit::in_session`].
+#[async_trait]
+pub trait AuditSink: Send + Sync {
+    async fn write(&self, record: AuditRecord, session: Option<&mut ClientSession>) -> Result<()>;
+}
+
+/// Writes audit records to a collection.
+pub struct CollectionSink {
+    collection: InstrumentedCollection<AuditRecord>,
+}
+
+impl CollectionSink {
+    pub fn new(database: impl Into<InstrumentedDatabase>, name: &str) -> Self {
+        CollectionSink {
+            collection: database.into().collection(name),
+        }
+    }
+}
+
+#[async_trait]
+impl AuditSink for CollectionSink {
+    async fn write(&self, record: AuditRecord, session: Option<&mut ClientSession>) -> Result<()> {
+        match session {
+            Some(session) => self
+                .collection
+                .insert_one_with_session(record, None, session)
+                .await
+                .map(|_| ()),
+            None => self.collection.insert_one(record, None).await.map(|_| ()),
+        }
+    }
+}
+
+type Provider = Arc<dyn Fn() -> Option<String> + Send + Sync>;
+
+/// Audit configuration of a collection, see [`InstrumentedCollection::with_audit`].
+///
+/// The actor comes from [`with_actor`], then from the span field kept by an `ActorLayer` (with
+/// the `subscriber` feature), then from [`Audit::actor_provider`]. The trace id only comes from
+/// [`Audit::trace_id_provider`], since spans do not know the id their exporter assigns. Filters
+/// and updates are recorded with their values redacted unless [`Audit::keep_values`] is set.
+#[derive(Clone)]
+pub struct Audit {
+    sink: Arc<dyn AuditSink>,
+    in_session: bool,
+    keep_values: bool,
+    actor: Option<Provider>,
+    trace_id: Option<Provider>,
+}
+
+impl Audit {
+    pub fn new(sink: impl AuditSink + 'static) -> Self {
+        Audit {
+            sink: Arc::new(sink),
+            in_session: false,
+            keep_values: false,
+            actor: None,
+            trace_id: None,
+        }
+    }
+
+    pub fn to_collection(database: impl Into<InstrumentedDatabase>, name: &str) -> Self {
+        Audit::new(CollectionSink::new(database, name))
+    }
+
+    /// Writes the record in the same session, so in the same transaction, as the audited write;
+    /// a failure to write it is then returned as the write's error, aborting the transaction.
+    pub fn in_session(mut self, in_session: bool) -> Self {
+        self.in_session = in_session;
+        self
+    }
+
+    pub fn keep_values(mut self, keep_values: bool) -> Self {
+        self.keep_values = keep_values;
+        self
+    }
+
+    pub fn actor_provider(
+        mut self,
+        provider: impl Fn() -> Option<String> + Send + Sync + 'static,
+    ) -> Self {
+        self.actor = Some(Arc::new(provider));
+        self
+    }
+
+    pub fn trace_id_provider(
+        mut self,
+        provider: impl Fn() -> Option<String> + Send + Sync + 'static,
+    ) -> Self {
+        self.trace_id = Some(Arc::new(provider));
+        self
+    }
+
+    pub(crate) async fn record(
+        &self,
+        operation: &Operation<'_>,
+        outcome: &Outcome,
+        session: Option<&mut ClientSession>,
+    ) -> Result<()> {
+        let record = AuditRecord {
+            actor: current_actor()
+                .or_else(span_actor)
+                .or_else(|| self.actor.as_ref().and_then(|actor| actor())),
+            operation: operation.kind().name().to_string(),
+            namespace: operation.namespace().to_string(),
+            filter: operation.filter().map(|filter| self.sanitize(filter)),
+            update: operation.update().map(|update| match update {
+                UpdateModifications::Document(update) => Bson::Document(self.sanitize(update)),
+                UpdateModifications::Pipeline(stages) => Bson::Array(
+                    stages
+                        .iter()
+                        .map(|stage| Bson::Document(self.sanitize(stage)))
+                        .collect(),
+                ),
+                _ => Bson::Null,
+            }),
+            affected_ids: affected_ids(operation, outcome),
+            trace_id: self.trace_id.as_ref().and_then(|trace_id| trace_id()),
+            timestamp: DateTime::now(),
+        };
+        let session = if self.in_session { session } else { None };
+        // Outside the operation's session the write is already committed: failing it would have
+        // the caller retry a write that happened.
+        let transactional = session.is_some();
+        match self.sink.write(record, session).await {
+            Err(error) if !transactional => {
+                tracing::warn!(%error, "audit record not written");
+                Ok(())
+            }
+            written => written,
+        }
+    }
+
+    fn sanitize(&self, document: &Document) -> Document {
+        if self.keep_values {
+            document.clone()
+        } else {
+            shape::redact(document)
+        }
+    }
+}
+
+/// Ids reported by the server, or else the `_id`s the filter selects by equality.
+fn affected_ids(operation: &Operation<'_>, outcome: &Outcome) -> Vec<Bson> {
+    match outcome {
+        Outcome::Inserted { ids } => return ids.clone(),
+        Outcome::Updated {
+            upserted_id: Some(id),
+            ..
+        } => return vec![id.clone()],
+        Outcome::Found { id: Some(id), .. } => return vec![id.clone()],
+        _ => {}
+    }
+    operation.filter().map(filter_ids).unwrap_or_default()
+}
+
+/// The `_id`s a filter, or one of the clauses of its `$and`, selects by equality.
+fn filter_ids(filter: &Document) -> Vec<Bson> {
+    match filter.get("_id") {
+        Some(Bson::Document(condition)) => match condition.get("$in") {
+            Some(Bson::Array(ids)) => ids.clone(),
+            _ => match condition.get("$eq") {
+                Some(id) => vec![id.clone()],
+                None => Vec::new(),
+            },
+        },
+        Some(id) => vec![id.clone()],
+        None => filter
+            .get_array("$and")
+            .map(|clauses| {
+                clauses
+                    .iter()
+                    .filter_map(Bson::as_document)
+                    .map(filter_ids)
+                    .find(|ids| !ids.is_empty())
+                    .unwrap_or_default()
+            })
+            .unwrap_or_default(),
+    }
+}
+
+impl<T> InstrumentedCollection<T> {
+    /// Records an [`AuditRecord`] after every successful insert, update, replace, delete and
+    /// `find_one_and_*`. A failure to record is returned as the operation's error when the record
+    /// is written in the operation's session, see [`Audit::in_session`]; otherwise the write has
+    /// committed, so the failure is logged as a warning on the span and the write's result
+    /// returned.
+    pub fn with_audit(mut self, audit: Audit) -> Self {
+        self.audit = Some(audit);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use mongodb::bson::{doc, RawDocumentBuf};
+    use mongodb::options::ClientOptions;
+    use mongodb::{Client, Namespace};
+
+    use super::*;
+    use crate::interceptor::{OperationKind, ToOutcome};
+
+    /// Keeps the records written, with whether they came with a session.
+    #[derive(Clone, Default)]
+    struct Written(Arc<Mutex<Vec<(AuditRecord, bool)>>>);
+
+    #[async_trait]
+    impl AuditSink for Written {
+        async fn write(
+            &self,
+            record: AuditRecord,
+            session: Option<&mut ClientSession>,
+        ) -> Result<()> {
+            self.0.lock().unwrap().push((record, session.is_some()));
+            Ok(())
+        }
+    }
+
+    impl Written {
+        fn take(&self) -> Vec<(AuditRecord, bool)> {
+            std::mem::take(&mut *self.0.lock().unwrap())
+        }
+    }
+
+    fn namespace() -> Namespace {
+        Namespace {
+            db: "app".to_string(),
+            coll: "orders".to_string(),
+        }
+    }
+
+    /// Audits an `update_one` of order 1 setting its status to `shipped`.
+    async fn audited(audit: &Audit, session: Option<&mut ClientSession>) {
+        let filter = doc! { "_id": 1, "status": "open" };
+        let update = UpdateModifications::Document(doc! { "$set": { "status": "shipped" } });
+        let operation = Operation::new(OperationKind::UpdateOne, namespace())
+            .with_filter(Some(&filter))
+            .with_update(&update);
+        let outcome = Outcome::Updated {
+            matched: 1,
+            modified: 1,
+            upserted_id: None,
+        };
+        audit.record(&operation, &outcome, session).await.unwrap();
+    }
+
+    fn ids(filter: Document, outcome: Outcome) -> Vec<Bson> {
+        let namespace = Namespace {
+            db: "app".to_string(),
+            coll: "orders".to_string(),
+        };
+        let operation =
+            Operation::new(OperationKind::UpdateOne, namespace).with_filter(Some(&filter));
+        affected_ids(&operation, &outcome)
+    }
+
+    fn updated(upserted_id: Option<Bson>) -> Outcome {
+        Outcome::Updated {
+            matched: 1,
+            modified: 1,
+            upserted_id,
+        }
+    }
+
+    #[test]
+    fn affected_ids_come_from_the_filter() {
+        assert_eq!(ids(doc! { "_id": 1 }, updated(None)), vec![Bson::Int32(1)]);
+        assert_eq!(
+            ids(doc! { "_id": { "$in": [1, 2] } }, updated(None)),
+            vec![Bson::Int32(1), Bson::Int32(2)]
+        );
+        assert_eq!(
+            ids(doc! { "_id": { "$eq": 3 } }, updated(None)),
+            vec![Bson::Int32(3)]
+        );
+        assert_eq!(
+            ids(doc! { "_id": { "$gt": 3 } }, updated(None)),
+            Vec::<Bson>::new()
+        );
+        assert_eq!(
+            ids(doc! { "status": "open" }, updated(None)),
+            Vec::<Bson>::new()
+        );
+    }
+
+    #[test]
+    fn affected_ids_look_into_and_clauses() {
+        let filter = doc! { "$and": [{ "_id": 7 }, { "tenantId": "acme" }] };
+        assert_eq!(ids(filter, updated(None)), vec![Bson::Int32(7)]);
+        let filter = doc! { "$and": [{ "tenantId": "acme" }, { "$and": [{ "_id": 8 }] }] };
+        assert_eq!(ids(filter, updated(None)), vec![Bson::Int32(8)]);
+    }
+
+    #[test]
+    fn affected_ids_prefer_the_outcome() {
+        assert_eq!(
+            ids(doc! { "_id": 1 }, updated(Some(Bson::Int32(2)))),
+            vec![Bson::Int32(2)]
+        );
+        let found = Outcome::Found {
+            found: true,
+            id: Some(Bson::Int32(4)),
+        };
+        assert_eq!(ids(doc! { "status": "open" }, found), vec![Bson::Int32(4)]);
+        let inserted = Outcome::Inserted {
+            ids: vec![Bson::Int32(5), Bson::Int32(6)],
+        };
+        assert_eq!(ids(doc! {}, inserted), vec![Bson::Int32(5), Bson::Int32(6)]);
+    }
+
+    #[test]
+    fn found_outcome_carries_the_returned_id() {
+        let document = RawDocumentBuf::from_document(&doc! { "_id": 9, "a": 1 }).unwrap();
+        assert_eq!(
+            Some(document).to_outcome(),
+            Outcome::Found {
+                found: true,
+                id: Some(Bson::Int32(9)),
+            }
+        );
+        assert_eq!(
+            None::<RawDocumentBuf>.to_outcome(),
+            Outcome::Found {
+                found: false,
+                id: None,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn records_redact_values_unless_kept() {
+        let written = Written::default();
+        let audit = Audit::new(written.clone()).trace_id_provider(|| Some("trace".to_string()));
+        audited(&audit, None).await;
+        let (record, _) = written.take().remove(0);
+        assert_eq!(record.operation, "update_one");
+        assert_eq!(record.namespace, "app.orders");
+        assert_eq!(record.filter, Some(doc! { "_id": "?", "status": "?" }));
+        assert_eq!(
+            record.update,
+            Some(Bson::Document(doc! { "$set": { "status": "?" } }))
+        );
+        assert_eq!(record.affected_ids, vec![Bson::Int32(1)]);
+        assert_eq!(record.trace_id.as_deref(), Some("trace"));
+        assert_eq!(record.actor, None);
+
+        audited(&audit.clone().keep_values(true), None).await;
+        let (record, _) = written.take().remove(0);
+        assert_eq!(record.filter, Some(doc! { "_id": 1, "status": "open" }));
+        assert_eq!(
+            record.update,
+            Some(Bson::Document(doc! { "$set": { "status": "shipped" } }))
+        );
+    }
+
+    #[tokio::test]
+    async fn with_actor_takes_precedence_over_the_provider() {
+        let written = Written::default();
+        let audit = Audit::new(written.clone()).actor_provider(|| Some("provided".to_string()));
+        audited(&audit, None).await;
+        with_actor("alice", audited(&audit, None)).await;
+        let actors: Vec<_> = written
+            .take()
+            .into_iter()
+            .map(|(record, _)| record.actor)
+            .collect();
+        assert_eq!(
+            actors,
+            [Some("provided".to_string()), Some("alice".to_string())]
+        );
+    }
+
+    #[tokio::test]
+    async fn sessions_reach_the_sink_only_in_session() {
+        // Starting a session does not reach the server.
+        let client = Client::with_options(ClientOptions::default()).unwrap();
+        let mut session = client.start_session(None).await.unwrap();
+        let written = Written::default();
+        let audit = Audit::new(written.clone());
+        audited(&audit, Some(&mut session)).await;
+        audited(&audit.clone().in_session(true), Some(&mut session)).await;
+        audited(&audit.in_session(true), None).await;
+        let sessions: Vec<_> = written
+            .take()
+            .into_iter()
+            .map(|(_, session)| session)
+            .collect();
+        assert_eq!(sessions, [false, true, false]);
+    }
+
+    struct Failing;
+
+    #[async_trait]
+    impl AuditSink for Failing {
+        async fn write(&self, _: AuditRecord, _: Option<&mut ClientSession>) -> Result<()> {
+            Err(mongodb::error::Error::custom("sink unavailable"))
+        }
+    }
+
+    #[tokio::test]
+    async fn sink_failures_fail_only_writes_recorded_in_their_session() {
+        let client = Client::with_options(ClientOptions::default()).unwrap();
+        let mut session = client.start_session(None).await.unwrap();
+        let filter = doc! { "_id": 1 };
+        let operation =
+            Operation::new(OperationKind::DeleteOne, namespace()).with_filter(Some(&filter));
+        let outcome = Outcome::Deleted { count: 1 };
+
+        let audit = Audit::new(Failing);
+        assert!(audit.record(&operation, &outcome, None).await.is_ok());
+        assert!(audit
+            .record(&operation, &outcome, Some(&mut session))
+            .await
+            .is_ok());
+        let in_session = audit.in_session(true);
+        assert!(in_session.record(&operation, &outcome, None).await.is_ok());
+        assert!(in_session
+            .record(&operation, &outcome, Some(&mut session))
+            .await
+            .is_err());
+    }
+
+    #[cfg(feature = "subscriber")]
+    #[tokio::test]
+    async fn span_fields_name_the_actor() {
+        use tracing::Instrument;
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let subscriber = Registry::default().with(ActorLayer::new());
+        let _default = tracing::subscriber::set_default(subscriber);
+        let written = Written::default();
+        let audit = Audit::new(written.clone()).actor_provider(|| Some("provided".to_string()));
+
+        let request = tracing::info_span!("request", actor = "bob");
+        let write = request.in_scope(|| tracing::info_span!("update_one"));
+        audited(&audit, None).instrument(write).await;
+        let later = tracing::info_span!("request", actor = tracing::field::Empty);
+        later.record("actor", "carol");
+        audited(&audit, None).instrument(later).await;
+        with_actor("alice", audited(&audit, None))
+            .instrument(request)
+            .await;
+        audited(&audit, None).await;
+
+        let actors: Vec<_> = written
+            .take()
+            .into_iter()
+            .filter_map(|(record, _)| record.actor)
+            .collect();
+        assert_eq!(actors, ["bob", "carol", "alice", "provided"]);
+    }
+}
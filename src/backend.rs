@@ -0,0 +1,206 @@
+use async_trait::async_trait;
+use futures_util::stream::BoxStream;
+use futures_util::StreamExt;
+use mongodb::bson::{Bson, Document};
+use mongodb::error::Result;
+use mongodb::options::{
+    CountOptions, DeleteOptions, DistinctOptions, FindOneOptions, FindOptions, InsertManyOptions,
+    InsertOneOptions, ReplaceOptions, UpdateModifications, UpdateOptions,
+};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::mongo_tracing::InstrumentedCollection;
+use crate::results::{DeleteResult, InsertManyResult, InsertOneResult, UpdateResult};
+
+/// The collection operations repositories rely on, so they can be generic over
+/// [`InstrumentedCollection`] and [`MemoryCollection`](crate::memory::MemoryCollection).
+#[async_trait]
+pub trait CollectionBackend<T>: Send + Sync
+where
+    T: Serialize + DeserializeOwned + Unpin + Send + Sync + 'static,
+{
+    async fn find(
+        &self,
+        filter: Option<Document>,
+        options: Option<FindOptions>,
+    ) -> Result<BoxStream<'static, Result<T>>>;
+
+    async fn find_one(
+        &self,
+        filter: Option<Document>,
+        options: Option<FindOneOptions>,
+    ) -> Result<Option<T>>;
+
+    async fn insert_one(
+        &self,
+        document: &T,
+        options: Option<InsertOneOptions>,
+    ) -> Result<InsertOneResult>;
+
+    async fn insert_many(
+        &self,
+        documents: &[T],
+        options: Option<InsertManyOptions>,
+    ) -> Result<InsertManyResult>;
+
+    async fn replace_one(
+        &self,
+        query: Document,
+        replacement: &T,
+        options: Option<ReplaceOptions>,
+    ) -> Result<UpdateResult>;
+
+    async fn update_one(
+        &self,
+        query: Document,
+        update: UpdateModifications,
+        options: Option<UpdateOptions>,
+    ) -> Result<UpdateResult>;
+
+    async fn update_many(
+        &self,
+        query: Document,
+        update: UpdateModifications,
+        options: Option<UpdateOptions>,
+    ) -> Result<UpdateResult>;
+
+    async fn delete_one(
+        &self,
+        query: Document,
+        options: Option<DeleteOptions>,
+    ) -> Result<DeleteResult>;
+
+    async fn delete_many(
+        &self,
+        query: Document,
+        options: Option<DeleteOptions>,
+    ) -> Result<DeleteResult>;
+
+    async fn count_documents(
+        &self,
+        filter: Option<Document>,
+        options: Option<CountOptions>,
+    ) -> Result<u64>;
+
+    async fn distinct(
+        &self,
+        field_name: &str,
+        filter: Option<Document>,
+        options: Option<DistinctOptions>,
+    ) -> Result<Vec<Bson>>;
+}
+
+#[async_trait]
+impl<T> CollectionBackend<T> for InstrumentedCollection<T>
+where
+    T: Serialize + DeserializeOwned + Unpin + Send + Sync + 'static,
+{
+    async fn find(
+        &self,
+        filter: Option<Document>,
+        options: Option<FindOptions>,
+    ) -> Result<BoxStream<'static, Result<T>>> {
+        Ok(InstrumentedCollection::find(self, filter, options)
+            .await?
+            .boxed())
+    }
+
+    async fn find_one(
+        &self,
+        filter: Option<Document>,
+        options: Option<FindOneOptions>,
+    ) -> Result<Option<T>> {
+        InstrumentedCollection::find_one(self, filter, options).await
+    }
+
+    async fn insert_one(
+        &self,
+        document: &T,
+        options: Option<InsertOneOptions>,
+    ) -> Result<InsertOneResult> {
+        InstrumentedCollection::insert_one(self, document, options)
+            .await
+            .map(Into::into)
+    }
+
+    async fn insert_many(
+        &self,
+        documents: &[T],
+        options: Option<InsertManyOptions>,
+    ) -> Result<InsertManyResult> {
+        InstrumentedCollection::insert_many(self, documents, options)
+            .await
+            .map(Into::into)
+    }
+
+    async fn replace_one(
+        &self,
+        query: Document,
+        replacement: &T,
+        options: Option<ReplaceOptions>,
+    ) -> Result<UpdateResult> {
+        InstrumentedCollection::replace_one(self, query, replacement, options)
+            .await
+            .map(Into::into)
+    }
+
+    async fn update_one(
+        &self,
+        query: Document,
+        update: UpdateModifications,
+        options: Option<UpdateOptions>,
+    ) -> Result<UpdateResult> {
+        InstrumentedCollection::update_one(self, query, update, options)
+            .await
+            .map(Into::into)
+    }
+
+    async fn update_many(
+        &self,
+        query: Document,
+        update: UpdateModifications,
+        options: Option<UpdateOptions>,
+    ) -> Result<UpdateResult> {
+        InstrumentedCollection::update_many(self, query, update, options)
+            .await
+            .map(Into::into)
+    }
+
+    async fn delete_one(
+        &self,
+        query: Document,
+        options: Option<DeleteOptions>,
+    ) -> Result<DeleteResult> {
+        InstrumentedCollection::delete_one(self, query, options)
+            .await
+            .map(Into::into)
+    }
+
+    async fn delete_many(
+        &self,
+        query: Document,
+        options: Option<DeleteOptions>,
+    ) -> Result<DeleteResult> {
+        InstrumentedCollection::delete_many(self, query, options)
+            .await
+            .map(Into::into)
+    }
+
+    async fn count_documents(
+        &self,
+        filter: Option<Document>,
+        options: Option<CountOptions>,
+    ) -> Result<u64> {
+        InstrumentedCollection::count_documents(self, filter, options).await
+    }
+
+    async fn distinct(
+        &self,
+        field_name: &str,
+        filter: Option<Document>,
+        options: Option<DistinctOptions>,
+    ) -> Result<Vec<Bson>> {
+        InstrumentedCollection::distinct(self, field_name, filter, options).await
+    }
+}
@@ -0,0 +1,556 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use futures_util::StreamExt;
+use mongodb::bson::{Bson, Document};
+use mongodb::change_stream::event::OperationType;
+use mongodb::error::Result;
+use mongodb::options::TransactionOptions;
+use mongodb::{ClientSession, Namespace};
+use tokio::task::JoinHandle;
+
+use crate::mongo_tracing::InstrumentedCollection;
+
+/// The server's default `transactionLifetimeLimitSeconds`: a transaction older than this was
+/// aborted by the server or ended without [`Cache::commit_transaction`].
+const TRANSACTION_LIFETIME: Duration = Duration::from_secs(60);
+
+/// An in-process cache of `find_one` results, see [`InstrumentedCollection::with_cache`].
+///
+/// Entries are keyed on the namespace and the filter with its top-level fields sorted, expire
+/// after the TTL and are evicted oldest first beyond the capacity. A cache can be shared by
+/// several collection handles, of one or several collections; writes through any of them clear
+/// it.
+///
+/// A document read while a write runs is only cached when no write cleared the cache, and no
+/// change invalidated it, since the read started. A write in a transaction only shows once the
+/// transaction commits, so for a transaction started with [`Cache::start_transaction`], no read
+/// is cached from its first write until it ends through [`Cache::commit_transaction`] or
+/// [`Cache::abort_transaction`], or at the latest after the server's 60 seconds transaction
+/// lifetime. Other writes in a session clear the cache like writes without one.
+#[derive(Clone)]
+pub struct Cache {
+    inner: Arc<CacheInner>,
+}
+
+struct CacheInner {
+    capacity: usize,
+    ttl: Duration,
+    entries: Mutex<Entries>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+#[derive(Default)]
+struct Entries {
+    values: HashMap<String, (Instant, Option<Document>)>,
+    order: VecDeque<(String, Instant)>,
+    /// Bumped on every clear and invalidation, see [`Cache::generation`].
+    generation: u64,
+    /// The ids of the sessions whose transaction was started through the cache, with when.
+    started: Vec<(Document, Instant)>,
+    /// The ids of the sessions that wrote in such a transaction, with when they first did.
+    transactions: Vec<(Document, Instant)>,
+}
+
+impl Entries {
+    fn clear(&mut self) {
+        self.values.clear();
+        self.order.clear();
+        self.generation += 1;
+    }
+}
+
+impl Cache {
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        Cache {
+            inner: Arc::new(CacheInner {
+                capacity,
+                ttl,
+                entries: Mutex::default(),
+                hits: AtomicU64::new(0),
+                misses: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    pub fn hits(&self) -> u64 {
+        self.inner.hits.load(Ordering::Relaxed)
+    }
+
+    pub fn misses(&self) -> u64 {
+        self.inner.misses.load(Ordering::Relaxed)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries().values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn clear(&self) {
+        self.entries().clear();
+    }
+
+    /// Starts a transaction on `session`; the driver does not tell whether a session is in a
+    /// transaction, so only the writes of transactions started here hold the cache.
+    pub async fn start_transaction(
+        &self,
+        session: &mut ClientSession,
+        options: impl Into<Option<TransactionOptions>>,
+    ) -> Result<()> {
+        session.start_transaction(options).await?;
+        self.started(session.id());
+        Ok(())
+    }
+
+    /// Commits the transaction of `session`, then clears the cache and caches reads again.
+    pub async fn commit_transaction(&self, session: &mut ClientSession) -> Result<()> {
+        session.commit_transaction().await?;
+        self.release(session.id());
+        Ok(())
+    }
+
+    /// Aborts the transaction of `session`, then caches reads again.
+    pub async fn abort_transaction(&self, session: &mut ClientSession) -> Result<()> {
+        let aborted = session.abort_transaction().await;
+        self.release(session.id());
+        aborted
+    }
+
+    pub(crate) fn started(&self, id: &Document) {
+        let mut entries = self.entries();
+        entries
+            .started
+            .retain(|(started, since)| started != id && since.elapsed() < TRANSACTION_LIFETIME);
+        entries.started.push((id.clone(), Instant::now()));
+    }
+
+    /// Clears the cache for a write in session `id` and, when the session is in a transaction
+    /// started through the cache, stops caching reads until the transaction ends: they could
+    /// read the documents it wrote as they were before.
+    pub(crate) fn hold(&self, id: &Document) {
+        let mut entries = self.entries();
+        entries.clear();
+        let in_transaction = entries
+            .started
+            .iter()
+            .any(|(started, since)| started == id && since.elapsed() < TRANSACTION_LIFETIME);
+        if in_transaction && !entries.transactions.iter().any(|(held, _)| held == id) {
+            entries.transactions.push((id.clone(), Instant::now()));
+        }
+    }
+
+    pub(crate) fn release(&self, id: &Document) {
+        let mut entries = self.entries();
+        entries.started.retain(|(started, _)| started != id);
+        entries.transactions.retain(|(held, _)| held != id);
+        entries.clear();
+    }
+
+    /// Drops the entries holding the document with `_id` `id`, and the cached misses, which
+    /// a new document could now match.
+    pub fn invalidate(&self, id: &Bson) {
+        let mut entries = self.entries();
+        entries.values.retain(|_, (_, document)| {
+            document
+                .as_ref()
+                .is_some_and(|document| document.get("_id") != Some(id))
+        });
+        entries.generation += 1;
+    }
+
+    /// Taken before reading from the server, to pass to [`Cache::insert`] with what was read.
+    pub(crate) fn generation(&self) -> u64 {
+        self.entries().generation
+    }
+
+    fn entries(&self) -> std::sync::MutexGuard<'_, Entries> {
+        self.inner
+            .entries
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub(crate) fn get(&self, key: &str) -> Option<Option<Document>> {
+        let found =
+            self.entries().values.get(key).and_then(|(stored, value)| {
+                (stored.elapsed() < self.inner.ttl).then(|| value.clone())
+            });
+        let (counter, name) = match found {
+            Some(_) => (&self.inner.hits, "hit"),
+            None => (&self.inner.misses, "miss"),
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        tracing::debug!(
+            monotonic_counter.db.cache.lookups = 1_u64,
+            cache.result = name
+        );
+        found
+    }
+
+    /// Stores `value` unless the cache was cleared or invalidated since `generation`, in which
+    /// case it may predate a write.
+    pub(crate) fn insert(&self, key: String, value: Option<Document>, generation: u64) {
+        if self.inner.capacity == 0 {
+            return;
+        }
+        let stored = Instant::now();
+        let mut entries = self.entries();
+        let held = entries.transactions.len();
+        entries
+            .transactions
+            .retain(|(_, since)| since.elapsed() < TRANSACTION_LIFETIME);
+        if entries.transactions.len() != held {
+            entries.clear();
+        }
+        if entries.generation != generation || !entries.transactions.is_empty() {
+            return;
+        }
+        while entries.values.len() >= self.inner.capacity {
+            let Some((oldest, at)) = entries.order.pop_front() else {
+                break;
+            };
+            if entries
+                .values
+                .get(&oldest)
+                .is_some_and(|(stored, _)| *stored == at)
+            {
+                entries.values.remove(&oldest);
+            }
+        }
+        if entries.order.len() > 2 * self.inner.capacity {
+            let Entries { values, order, .. } = &mut *entries;
+            order.retain(|(key, at)| values.get(key).is_some_and(|(stored, _)| stored == at));
+        }
+        entries.order.push_back((key.clone(), stored));
+        entries.values.insert(key, (stored, value));
+    }
+}
+
+/// The cache key of a filter: the namespace, then the filter's canonical extended JSON with its
+/// top-level fields sorted, followed by the projection applied, if any.
+///
+/// Embedded documents keep their field order: the server compares them field by field, so
+/// `{ a: { b: 1, c: 2 } }` and `{ a: { c: 2, b: 1 } }` match different documents.
+pub(crate) fn key(
+    namespace: &Namespace,
+    filter: Option<&Document>,
+    projection: Option<&Document>,
+) -> String {
+    let filter = filter.map(normalize).unwrap_or_default();
+    // Namespaces cannot contain a NUL, so the key cannot be mistaken for another namespace's.
+    let mut key = format!("{}\0", namespace);
+    key.push_str(&Bson::Document(filter).into_canonical_extjson().to_string());
+    if let Some(projection) = projection {
+        key.push_str(
+            &Bson::Document(normalize(projection))
+                .into_canonical_extjson()
+                .to_string(),
+        );
+    }
+    key
+}
+
+fn normalize(document: &Document) -> Document {
+    let mut fields: Vec<(&String, &Bson)> = document.iter().collect();
+    fields.sort_by_key(|(key, _)| *key);
+    fields
+        .into_iter()
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect()
+}
+
+impl<T> InstrumentedCollection<T> {
+    /// Serves `find_one` calls without options or session from `cache`, recording `cache.hit`
+    /// on their span.
+    pub fn with_cache(mut self, cache: Cache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Watches the collection in the background, dropping the cache entries of changed
+    /// documents; the whole cache is cleared when the stream fails.
+    pub async fn invalidate_cache_on_changes(&self) -> Result<Option<JoinHandle<()>>> {
+        let cache = match &self.cache {
+            Some(cache) => cache.clone(),
+            None => return Ok(None),
+        };
+        let mut changes = self.clone_with_type::<Document>().watch(None, None).await?;
+        Ok(Some(tokio::spawn(async move {
+            while let Some(change) = changes.next().await {
+                let change = match change {
+                    Ok(change) => change,
+                    Err(error) => {
+                        tracing::warn!(%error, "cache invalidation stream failed");
+                        cache.clear();
+                        break;
+                    }
+                };
+                match change.document_key.as_ref().and_then(|key| key.get("_id")) {
+                    Some(id) => cache.invalidate(id),
+                    None if change.operation_type == OperationType::Invalidate => {
+                        cache.clear();
+                        break;
+                    }
+                    None => cache.clear(),
+                }
+            }
+        })))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mongodb::bson::doc;
+    use mongodb::options::ClientOptions;
+    use mongodb::Client;
+
+    use super::*;
+    use crate::database::InstrumentedDatabase;
+    use crate::error::Error;
+    use crate::interceptor::{Interceptor, Operation, OperationKind, Outcome};
+    use crate::testing::test_database;
+
+    fn namespace(coll: &str) -> Namespace {
+        Namespace {
+            db: "app".to_string(),
+            coll: coll.to_string(),
+        }
+    }
+
+    #[test]
+    fn keys_ignore_top_level_field_order() {
+        let users = namespace("users");
+        assert_eq!(
+            key(
+                &users,
+                Some(&doc! { "a": 1, "b": { "c": 3, "d": 2 } }),
+                None
+            ),
+            key(
+                &users,
+                Some(&doc! { "b": { "c": 3, "d": 2 }, "a": 1 }),
+                None
+            )
+        );
+        assert_eq!(key(&users, None, None), key(&users, Some(&doc! {}), None));
+    }
+
+    #[test]
+    fn keys_keep_embedded_field_order() {
+        // The server matches embedded documents field by field, in order.
+        let users = namespace("users");
+        assert_ne!(
+            key(
+                &users,
+                Some(&doc! { "addr": { "city": "Paris", "zip": "75001" } }),
+                None
+            ),
+            key(
+                &users,
+                Some(&doc! { "addr": { "zip": "75001", "city": "Paris" } }),
+                None
+            )
+        );
+    }
+
+    #[test]
+    fn keys_keep_array_order_and_types() {
+        let users = namespace("users");
+        assert_ne!(
+            key(&users, Some(&doc! { "a": [1, 2] }), None),
+            key(&users, Some(&doc! { "a": [2, 1] }), None)
+        );
+        assert_ne!(
+            key(&users, Some(&doc! { "a": 1 }), None),
+            key(&users, Some(&doc! { "a": 1_i64 }), None)
+        );
+    }
+
+    #[test]
+    fn keys_differ_by_namespace_and_projection() {
+        let filter = doc! { "_id": 1 };
+        assert_ne!(
+            key(&namespace("users"), Some(&filter), None),
+            key(&namespace("orders"), Some(&filter), None)
+        );
+        assert_ne!(
+            key(&namespace("users"), Some(&filter), None),
+            key(
+                &namespace("users"),
+                Some(&filter),
+                Some(&doc! { "name": 1 })
+            )
+        );
+    }
+
+    #[test]
+    fn entries_expire_and_are_evicted_oldest_first() {
+        let cache = Cache::new(2, Duration::from_secs(60));
+        for key in ["a", "b", "c"] {
+            cache.insert(
+                key.to_string(),
+                Some(doc! { "_id": key }),
+                cache.generation(),
+            );
+        }
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get("a"), None);
+        assert_eq!(cache.get("c"), Some(Some(doc! { "_id": "c" })));
+        assert_eq!((cache.hits(), cache.misses()), (1, 1));
+
+        let expired = Cache::new(2, Duration::ZERO);
+        expired.insert("a".to_string(), None, expired.generation());
+        assert_eq!(expired.get("a"), None);
+    }
+
+    #[test]
+    fn invalidation_keeps_other_documents() {
+        let cache = Cache::new(10, Duration::from_secs(60));
+        cache.insert("a".to_string(), Some(doc! { "_id": 1 }), cache.generation());
+        cache.insert("b".to_string(), Some(doc! { "_id": 2 }), cache.generation());
+        cache.insert("c".to_string(), None, cache.generation());
+        cache.invalidate(&Bson::Int32(1));
+        assert_eq!(cache.get("a"), None);
+        assert_eq!(cache.get("b"), Some(Some(doc! { "_id": 2 })));
+        // A cached miss could now be matched by a new document.
+        assert_eq!(cache.get("c"), None);
+    }
+
+    #[test]
+    fn reads_overtaken_by_a_write_are_not_cached() {
+        let cache = Cache::new(10, Duration::from_secs(60));
+        let generation = cache.generation();
+        cache.clear();
+        cache.insert("a".to_string(), Some(doc! { "_id": 1 }), generation);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn reads_are_not_cached_while_a_transaction_wrote() {
+        let cache = Cache::new(10, Duration::from_secs(60));
+        let (first, second) = (doc! { "id": 1 }, doc! { "id": 2 });
+        cache.insert("a".to_string(), Some(doc! { "_id": 1 }), cache.generation());
+        cache.started(&first);
+        cache.started(&second);
+        cache.hold(&first);
+        cache.hold(&second);
+        assert!(cache.is_empty());
+
+        cache.insert("b".to_string(), Some(doc! { "_id": 2 }), cache.generation());
+        assert!(cache.is_empty());
+        // A read that started before the commit may hold the previous version.
+        let generation = cache.generation();
+        cache.release(&first);
+        cache.insert("b".to_string(), Some(doc! { "_id": 2 }), generation);
+        assert!(cache.is_empty());
+
+        cache.release(&second);
+        cache.insert("b".to_string(), Some(doc! { "_id": 2 }), cache.generation());
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn session_writes_hold_the_cache_only_in_started_transactions() {
+        // Starting a session does not reach the server.
+        let client = Client::with_options(ClientOptions::default()).unwrap();
+        let cache = Cache::new(10, Duration::from_secs(60));
+        let users = InstrumentedDatabase::from(client.database("app"))
+            .collection::<Document>("users")
+            .with_cache(cache.clone());
+        let mut session = client.start_session(None).await.unwrap();
+        let filter = doc! { "_id": 1 };
+        let operation = Operation::new(OperationKind::FindOneAndDelete, namespace("users"))
+            .with_filter(Some(&filter));
+
+        cache.insert("a".to_string(), Some(doc! { "_id": 1 }), cache.generation());
+        let deleted = users
+            .written(&operation, Ok(None::<Document>), Some(&mut session))
+            .await;
+        assert_eq!(deleted.unwrap(), None);
+        assert!(cache.is_empty());
+        cache.insert("b".to_string(), Some(doc! { "_id": 2 }), cache.generation());
+        assert_eq!(cache.len(), 1);
+
+        cache.started(session.id());
+        let deleted = users
+            .written(&operation, Ok(None::<Document>), Some(&mut session))
+            .await;
+        assert_eq!(deleted.unwrap(), None);
+        cache.insert("b".to_string(), Some(doc! { "_id": 2 }), cache.generation());
+        assert!(cache.is_empty());
+        cache.release(session.id());
+        cache.insert("b".to_string(), Some(doc! { "_id": 2 }), cache.generation());
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[derive(Default)]
+    struct Seen {
+        outcomes: Mutex<Vec<Outcome>>,
+        refuse: bool,
+    }
+
+    impl Interceptor for Arc<Seen> {
+        fn before(&self, _operation: &Operation<'_>) -> Result<()> {
+            match self.refuse {
+                true => Err(Error::Unsupported {
+                    feature: "refused".to_string(),
+                }
+                .into()),
+                false => Ok(()),
+            }
+        }
+
+        fn after(&self, _operation: &Operation<'_>, outcome: &Outcome) {
+            self.outcomes.lock().unwrap().push(outcome.clone());
+        }
+    }
+
+    #[tokio::test]
+    async fn cache_hits_go_through_the_interceptors() {
+        // The cache answers before the server is reached.
+        let cache = Cache::new(10, Duration::from_secs(60));
+        let filter = doc! { "_id": 1 };
+        let users = test_database("app")
+            .collection::<Document>("users")
+            .with_cache(cache.clone());
+        cache.insert(
+            key(&namespace("users"), Some(&filter), None),
+            Some(doc! { "_id": 1, "name": "ada" }),
+            cache.generation(),
+        );
+
+        let seen = Arc::new(Seen::default());
+        let found = users
+            .clone()
+            .with_interceptor(seen.clone())
+            .find_one(filter.clone(), None)
+            .await
+            .unwrap();
+        assert_eq!(found, Some(doc! { "_id": 1, "name": "ada" }));
+        assert_eq!(
+            *seen.outcomes.lock().unwrap(),
+            vec![Outcome::Found {
+                found: true,
+                id: Some(Bson::Int32(1)),
+            }]
+        );
+
+        let refusing = Arc::new(Seen {
+            refuse: true,
+            ..Seen::default()
+        });
+        let refused = users
+            .with_interceptor(refusing.clone())
+            .find_one(filter, None)
+            .await;
+        assert!(refused.is_err());
+        assert!(refusing.outcomes.lock().unwrap().is_empty());
+        assert_eq!(cache.hits(), 2);
+    }
+}
@@ -0,0 +1,567 @@
+use std::fmt;
+use std::future::{poll_fn, Future};
+use std::marker::PhantomData;
+use std::pin::{pin, Pin};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::task::{Context, Poll};
+use std::time::Instant;
+
+use futures_util::Stream;
+use mongodb::bson::{de, from_slice, Bson, Document, RawDocument, RawDocumentBuf};
+use mongodb::error::Result;
+use mongodb::{ClientSession, Cursor, Namespace, SessionCursor};
+use serde::de::DeserializeOwned;
+use tracing::Span;
+
+use crate::interceptor::{CursorRead, InterceptorChain, Operation, OperationKind};
+use crate::size;
+
+/// A cursor over the results of `find` or `aggregate` that adds the size of every document it
+/// receives to `db.response.size` on the operation's span.
+///
+/// The span stays open until the cursor is dropped, so its duration covers every batch.
+/// Documents that fail to deserialize end the stream with an error unless the cursor is made
+/// [`lenient`](InstrumentedCursor::lenient). On drop, the documents and batches read are
+/// passed to the collection's interceptors through [`Interceptor::after_cursor`].
+///
+/// [`Interceptor::after_cursor`]: crate::interceptor::Interceptor::after_cursor
+#[derive(Debug)]
+pub struct InstrumentedCursor<T> {
+    inner: Cursor<RawDocumentBuf>,
+    reads: Reads,
+    document_type: PhantomData<fn() -> T>,
+}
+
+impl<T> InstrumentedCursor<T> {
+    /// Wraps `inner`, recording on the current span.
+    pub(crate) fn new(inner: Cursor<RawDocumentBuf>) -> Self {
+        InstrumentedCursor {
+            inner,
+            reads: Reads::new(),
+            document_type: PhantomData,
+        }
+    }
+
+    /// Reports what is read to `interceptors` once dropped, as reads of `operation`.
+    pub(crate) fn reporting_to(
+        mut self,
+        interceptors: &InterceptorChain,
+        operation: &Operation<'_>,
+    ) -> Self {
+        self.reads.report_to(interceptors, operation);
+        self
+    }
+
+    /// Moves to the next document, see [`Cursor::advance`].
+    pub async fn advance(&mut self) -> Result<bool> {
+        let advanced = self.reads.waited_for(self.inner.advance()).await?;
+        if advanced {
+            self.reads.received(self.inner.current().as_bytes().len());
+        }
+        Ok(advanced)
+    }
+
+    pub fn current(&self) -> &RawDocument {
+        self.inner.current()
+    }
+
+    pub fn deserialize_current(&self) -> Result<T>
+    where
+        T: DeserializeOwned,
+    {
+        self.reads.decode(self.current())
+    }
+
+    /// Bytes received so far.
+    pub fn received_size(&self) -> u64 {
+        self.reads.received
+    }
+
+    /// Documents that failed to deserialize so far.
+    pub fn decode_failures(&self) -> u64 {
+        self.reads.failures.load(Ordering::Relaxed)
+    }
+
+    /// Yields documents that fail to deserialize as [`DecodeFailure`]s and keeps iterating.
+    pub fn lenient(self) -> LenientCursor<T> {
+        LenientCursor { inner: self }
+    }
+}
+
+impl<T> Stream for InstrumentedCursor<T>
+where
+    T: DeserializeOwned,
+{
+    type Item = Result<T>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let cursor = &mut *self;
+        let poll = Pin::new(&mut cursor.inner).poll_next(cx);
+        cursor.reads.next(poll)
+    }
+}
+
+/// The cursor of `find_with_session` or `aggregate_with_session`, recording like an
+/// [`InstrumentedCursor`]; it is iterated with the session, see [`SessionCursor`].
+#[derive(Debug)]
+pub struct InstrumentedSessionCursor<T> {
+    inner: SessionCursor<RawDocumentBuf>,
+    reads: Reads,
+    document_type: PhantomData<fn() -> T>,
+}
+
+impl<T> InstrumentedSessionCursor<T> {
+    /// Wraps `inner`, recording on the current span.
+    pub(crate) fn new(inner: SessionCursor<RawDocumentBuf>) -> Self {
+        InstrumentedSessionCursor {
+            inner,
+            reads: Reads::new(),
+            document_type: PhantomData,
+        }
+    }
+
+    /// Reports what is read to `interceptors` once dropped, as reads of `operation`.
+    pub(crate) fn reporting_to(
+        mut self,
+        interceptors: &InterceptorChain,
+        operation: &Operation<'_>,
+    ) -> Self {
+        self.reads.report_to(interceptors, operation);
+        self
+    }
+
+    /// Moves to the next document, see [`SessionCursor::advance`].
+    pub async fn advance(&mut self, session: &mut ClientSession) -> Result<bool> {
+        let advanced = self.reads.waited_for(self.inner.advance(session)).await?;
+        if advanced {
+            self.reads.received(self.inner.current().as_bytes().len());
+        }
+        Ok(advanced)
+    }
+
+    /// The next document, deserialized; see [`SessionCursor::next`].
+    pub async fn next(&mut self, session: &mut ClientSession) -> Option<Result<T>>
+    where
+        T: DeserializeOwned,
+    {
+        match self.advance(session).await {
+            Ok(true) => Some(self.deserialize_current()),
+            Ok(false) => None,
+            Err(error) => Some(Err(error)),
+        }
+    }
+
+    pub fn current(&self) -> &RawDocument {
+        self.inner.current()
+    }
+
+    pub fn deserialize_current(&self) -> Result<T>
+    where
+        T: DeserializeOwned,
+    {
+        self.reads.decode(self.current())
+    }
+
+    /// Bytes received so far.
+    pub fn received_size(&self) -> u64 {
+        self.reads.received
+    }
+
+    /// Documents that failed to deserialize so far.
+    pub fn decode_failures(&self) -> u64 {
+        self.reads.failures.load(Ordering::Relaxed)
+    }
+}
+
+/// What a cursor has read, recorded on the operation's span and reported to the interceptors
+/// of the operation on drop.
+#[derive(Debug)]
+struct Reads {
+    span: Span,
+    received: u64,
+    failures: AtomicU64,
+    read: CursorRead,
+    waiting: bool,
+    origin: Option<Origin>,
+}
+
+/// The operation that opened a cursor, kept to report to its interceptors on drop.
+struct Origin {
+    interceptors: InterceptorChain,
+    kind: OperationKind,
+    namespace: Namespace,
+    filter: Option<Document>,
+    pipeline: Option<Vec<Document>>,
+    in_session: bool,
+    started: Instant,
+}
+
+impl fmt::Debug for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Origin")
+            .field("kind", &self.kind)
+            .field("namespace", &self.namespace)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Reads {
+    fn new() -> Self {
+        Reads {
+            span: Span::current(),
+            received: 0,
+            failures: AtomicU64::new(0),
+            read: CursorRead {
+                documents: 0,
+                batches: 1,
+            },
+            waiting: false,
+            origin: None,
+        }
+    }
+
+    fn report_to(&mut self, interceptors: &InterceptorChain, operation: &Operation<'_>) {
+        if interceptors.is_empty() {
+            return;
+        }
+        self.origin = Some(Origin {
+            interceptors: interceptors.clone(),
+            kind: operation.kind(),
+            namespace: operation.namespace().clone(),
+            filter: operation.filter().cloned(),
+            pipeline: operation.pipeline().map(<[Document]>::to_vec),
+            in_session: operation.is_in_session(),
+            started: operation.started(),
+        });
+    }
+
+    /// Deserializes `document`, reporting a failure with the document's `_id` in the span.
+    fn decode<T>(&self, document: &RawDocument) -> Result<T>
+    where
+        T: DeserializeOwned,
+    {
+        from_slice(document.as_bytes()).map_err(|error| {
+            self.failed(document, &error);
+            error.into()
+        })
+    }
+
+    /// Counts a decode failure on the span and in metrics, returning the document's `_id`.
+    fn failed(&self, document: &RawDocument, error: &de::Error) -> Option<Bson> {
+        let failures = self.failures.fetch_add(1, Ordering::Relaxed) + 1;
+        self.span.record("db.decode.failures", failures);
+        let id = match document.get("_id") {
+            Ok(Some(id)) => Bson::try_from(id.to_raw_bson()).ok(),
+            _ => None,
+        };
+        tracing::warn!(
+            parent: &self.span,
+            error = %error,
+            db.document.id = %id.clone().map(Bson::into_relaxed_extjson).unwrap_or_default(),
+            monotonic_counter.db.decode.failures = 1_u64,
+            "could not deserialize a document"
+        );
+        id
+    }
+
+    /// Awaits `advance`, counting a batch when it had to wait for the server.
+    async fn waited_for<R>(&mut self, advance: impl Future<Output = R>) -> R {
+        let mut advance = pin!(advance);
+        poll_fn(|cx| self.polled(advance.as_mut().poll(cx))).await
+    }
+
+    /// Counts a batch when `poll` completes after the cursor had to wait.
+    ///
+    /// This is an estimate of the `getMore` round trips: the driver does not say when it fetches
+    /// a batch, and any wait, such as for a connection, counts as one.
+    fn polled<R>(&mut self, poll: Poll<R>) -> Poll<R> {
+        match poll {
+            Poll::Pending => self.waiting = true,
+            Poll::Ready(_) if self.waiting => {
+                self.waiting = false;
+                self.read.batches += 1;
+            }
+            Poll::Ready(_) => {}
+        }
+        poll
+    }
+
+    /// The next document of the cursor polled, counted and deserialized.
+    fn next<T>(&mut self, poll: Poll<Option<Result<RawDocumentBuf>>>) -> Poll<Option<Result<T>>>
+    where
+        T: DeserializeOwned,
+    {
+        match self.polled(poll) {
+            Poll::Ready(Some(Ok(document))) => {
+                self.received(document.as_bytes().len());
+                Poll::Ready(Some(self.decode(&document)))
+            }
+            Poll::Ready(Some(Err(error))) => Poll::Ready(Some(Err(error))),
+            Poll::Ready(None) => Poll::Ready(None),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    /// Like [`Reads::next`], turning deserialization errors into [`DecodeFailure`]s.
+    fn next_lenient<T>(
+        &mut self,
+        poll: Poll<Option<Result<RawDocumentBuf>>>,
+    ) -> Poll<Option<Lenient<T>>>
+    where
+        T: DeserializeOwned,
+    {
+        match self.polled(poll) {
+            Poll::Ready(Some(Ok(document))) => {
+                self.received(document.as_bytes().len());
+                let decoded = match from_slice(document.as_bytes()) {
+                    Ok(value) => Ok(value),
+                    Err(error) => Err(DecodeFailure {
+                        id: self.failed(&document, &error),
+                        document,
+                        error,
+                    }),
+                };
+                Poll::Ready(Some(Ok(decoded)))
+            }
+            Poll::Ready(Some(Err(error))) => Poll::Ready(Some(Err(error))),
+            Poll::Ready(None) => Poll::Ready(None),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    fn received(&mut self, size: usize) {
+        self.read.documents += 1;
+        self.received += size as u64;
+        self.span.record("db.response.size", self.received);
+        self.span.in_scope(|| size::check(size));
+    }
+}
+
+impl Drop for Reads {
+    fn drop(&mut self) {
+        if let Some(origin) = &self.origin {
+            let mut operation = Operation::new(origin.kind, origin.namespace.clone())
+                .with_filter(origin.filter.as_ref())
+                .started_at(origin.started);
+            if let Some(pipeline) = &origin.pipeline {
+                operation = operation.with_pipeline(pipeline);
+            }
+            if origin.in_session {
+                operation = operation.in_session();
+            }
+            self.span
+                .in_scope(|| origin.interceptors.after_cursor(&operation, &self.read));
+        }
+    }
+}
+
+/// A document a [`LenientCursor`] could not deserialize.
+#[derive(Debug)]
+pub struct DecodeFailure {
+    /// The document's `_id`, if it has one.
+    pub id: Option<Bson>,
+    pub document: RawDocumentBuf,
+    pub error: de::Error,
+}
+
+impl fmt::Display for DecodeFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.id {
+            Some(id) => write!(f, "could not deserialize document {}: {}", id, self.error),
+            None => write!(f, "could not deserialize document: {}", self.error),
+        }
+    }
+}
+
+impl std::error::Error for DecodeFailure {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+/// A document of a [`LenientCursor`], or the error of the server ending it.
+type Lenient<T> = Result<std::result::Result<T, DecodeFailure>>;
+
+/// An [`InstrumentedCursor`] that yields each document as `Ok(T)` or `Err(DecodeFailure)`;
+/// only errors from the server end the stream.
+#[derive(Debug)]
+pub struct LenientCursor<T> {
+    inner: InstrumentedCursor<T>,
+}
+
+impl<T> LenientCursor<T> {
+    pub fn received_size(&self) -> u64 {
+        self.inner.received_size()
+    }
+
+    pub fn decode_failures(&self) -> u64 {
+        self.inner.decode_failures()
+    }
+}
+
+impl<T> Stream for LenientCursor<T>
+where
+    T: DeserializeOwned,
+{
+    type Item = Lenient<T>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let cursor = &mut self.inner;
+        let poll = Pin::new(&mut cursor.inner).poll_next(cx);
+        cursor.reads.next_lenient(poll)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use mongodb::bson::{doc, to_raw_document_buf};
+    use serde::Deserialize;
+
+    use super::*;
+    use crate::testing::Spans;
+
+    /// Serves `batches` one after the other, waiting once before each batch after the first as
+    /// a cursor does for a `getMore`.
+    struct Batches {
+        batches: VecDeque<Vec<RawDocumentBuf>>,
+        current: VecDeque<RawDocumentBuf>,
+        started: bool,
+    }
+
+    impl Batches {
+        fn new(batches: Vec<Vec<Document>>) -> Self {
+            Batches {
+                batches: batches
+                    .into_iter()
+                    .map(|batch| batch.iter().map(raw).collect())
+                    .collect(),
+                current: VecDeque::new(),
+                started: false,
+            }
+        }
+    }
+
+    impl Stream for Batches {
+        type Item = Result<RawDocumentBuf>;
+
+        fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+            loop {
+                if let Some(document) = self.current.pop_front() {
+                    return Poll::Ready(Some(Ok(document)));
+                }
+                match self.batches.pop_front() {
+                    Some(batch) => self.current = batch.into(),
+                    None => return Poll::Ready(None),
+                }
+                if self.started {
+                    cx.waker().wake_by_ref();
+                    return Poll::Pending;
+                }
+                self.started = true;
+            }
+        }
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Item {
+        name: String,
+    }
+
+    fn raw(document: &Document) -> RawDocumentBuf {
+        to_raw_document_buf(document).unwrap()
+    }
+
+    fn cursor_span() -> Span {
+        tracing::info_span!(
+            "find",
+            db.response.size = tracing::field::Empty,
+            db.decode.failures = tracing::field::Empty,
+        )
+    }
+
+    #[tokio::test]
+    async fn reads_count_documents_sizes_and_batches() {
+        let spans = Spans::default();
+        let _default = tracing::subscriber::set_default(spans.clone());
+        let first = vec![doc! { "name": "ada" }, doc! { "name": "grace" }];
+        let second = vec![doc! { "name": "edsger" }];
+        let expected: usize = first
+            .iter()
+            .chain(&second)
+            .map(|document| raw(document).as_bytes().len())
+            .sum();
+        let mut batches = Batches::new(vec![first, second]);
+        let mut reads = cursor_span().in_scope(Reads::new);
+
+        let mut names = Vec::new();
+        while let Some(item) =
+            poll_fn(|cx| reads.next::<Item>(Pin::new(&mut batches).poll_next(cx))).await
+        {
+            names.push(item.unwrap().name);
+        }
+
+        assert_eq!(names, ["ada", "grace", "edsger"]);
+        assert_eq!(
+            reads.read,
+            CursorRead {
+                documents: 3,
+                batches: 2
+            }
+        );
+        assert_eq!(reads.received, expected as u64);
+        let spans = spans.spans.lock().unwrap();
+        assert_eq!(
+            spans[0].1["db.response.size"].as_deref(),
+            Some(expected.to_string().as_str())
+        );
+    }
+
+    #[test]
+    fn only_polls_completing_after_a_wait_count_as_batches() {
+        let mut reads = Reads::new();
+        assert_eq!(reads.polled(Poll::Ready(())), Poll::Ready(()));
+        assert_eq!(reads.polled(Poll::<()>::Pending), Poll::Pending);
+        assert_eq!(reads.polled(Poll::<()>::Pending), Poll::Pending);
+        assert_eq!(reads.polled(Poll::Ready(())), Poll::Ready(()));
+        assert_eq!(reads.polled(Poll::Ready(())), Poll::Ready(()));
+        assert_eq!(reads.read.batches, 2);
+        assert_eq!(reads.read.documents, 0);
+
+        reads.received(10);
+        reads.received(5);
+        assert_eq!(reads.read.documents, 2);
+        assert_eq!(reads.received, 15);
+    }
+
+    #[tokio::test]
+    async fn lenient_reads_continue_past_undecodable_documents() {
+        let spans = Spans::default();
+        let _default = tracing::subscriber::set_default(spans.clone());
+        let undecodable = doc! { "_id": 2, "name": 5 };
+        let mut batches = Batches::new(vec![
+            vec![doc! { "_id": 1, "name": "ada" }, undecodable.clone()],
+            vec![doc! { "_id": 3, "name": "grace" }],
+        ]);
+        let mut reads = cursor_span().in_scope(Reads::new);
+
+        let mut items = Vec::new();
+        while let Some(item) =
+            poll_fn(|cx| reads.next_lenient::<Item>(Pin::new(&mut batches).poll_next(cx))).await
+        {
+            items.push(item.unwrap());
+        }
+
+        assert_eq!(items.len(), 3);
+        assert_eq!(items[0].as_ref().unwrap().name, "ada");
+        let failure = items[1].as_ref().unwrap_err();
+        assert_eq!(failure.id, Some(Bson::Int32(2)));
+        assert_eq!(failure.document, raw(&undecodable));
+        assert_eq!(items[2].as_ref().unwrap().name, "grace");
+        assert_eq!(reads.failures.load(Ordering::Relaxed), 1);
+        assert_eq!(reads.read.documents, 3);
+        let spans = spans.spans.lock().unwrap();
+        assert_eq!(spans[0].1["db.decode.failures"].as_deref(), Some("1"));
+    }
+}
@@ -0,0 +1,82 @@
+use mongodb::bson::Document;
+use mongodb::error::Result;
+use mongodb::options::{CreateCollectionOptions, SelectionCriteria};
+use mongodb::Database;
+use tracing::instrument;
+
+use crate::mongo_tracing::InstrumentedCollection;
+
+#[derive(Clone, Debug)]
+pub struct InstrumentedDatabase {
+    inner: Database,
+}
+
+impl From<Database> for InstrumentedDatabase {
+    fn from(inner: Database) -> Self {
+        InstrumentedDatabase { inner }
+    }
+}
+
+impl InstrumentedDatabase {
+    pub fn new(inner: Database) -> Self {
+        InstrumentedDatabase { inner }
+    }
+
+    pub fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    pub fn inner(&self) -> &Database {
+        &self.inner
+    }
+
+    pub fn collection<T>(&self, name: &str) -> InstrumentedCollection<T> {
+        InstrumentedCollection::new(&self.inner, name)
+    }
+
+    #[instrument(
+    fields(
+    db.name = % self.inner.name(),
+    db.system = "mongodb",
+    otel.kind = "client",
+    ),
+    skip(self, command, selection_criteria)
+    )]
+    pub async fn run_command(
+        &self,
+        command: Document,
+        selection_criteria: impl Into<Option<SelectionCriteria>>,
+    ) -> Result<Document> {
+        self.inner.run_command(command, selection_criteria).await
+    }
+    #[instrument(
+    fields(
+    db.name = % self.inner.name(),
+    db.system = "mongodb",
+    otel.kind = "client",
+    ),
+    skip(self, filter)
+    )]
+    pub async fn list_collection_names(
+        &self,
+        filter: impl Into<Option<Document>>,
+    ) -> Result<Vec<String>> {
+        self.inner.list_collection_names(filter).await
+    }
+    #[instrument(
+    fields(
+    db.name = % self.inner.name(),
+    db.system = "mongodb",
+    db.collection = % name,
+    otel.kind = "client",
+    ),
+    skip(self, options)
+    )]
+    pub async fn create_collection(
+        &self,
+        name: &str,
+        options: impl Into<Option<CreateCollectionOptions>>,
+    ) -> Result<()> {
+        self.inner.create_collection(name, options).await
+    }
+}
@@ -0,0 +1,216 @@
+use mongodb::bson::{to_document, Bson, Document};
+use mongodb::error::Result;
+use mongodb::options::{ReplaceOptions, UpdateModifications, UpdateOptions};
+use mongodb::results::UpdateResult;
+use serde::Serialize;
+use tracing::instrument;
+
+use crate::mongo_tracing::InstrumentedCollection;
+
+/// The `$set`/`$unset` update turning `original` into `modified`, and the paths it changes;
+/// `None` when a top-level field name cannot be used in a path, so only a replacement can.
+///
+/// Embedded documents are compared field by field; arrays, and documents whose keys cannot be
+/// used in a path, are set whole when they differ.
+pub(crate) fn update(original: &Document, modified: &Document) -> Option<(Document, Vec<String>)> {
+    if !original
+        .keys()
+        .chain(modified.keys())
+        .all(|key| addressable_key(key))
+    {
+        return None;
+    }
+    let mut set = Document::new();
+    let mut unset = Document::new();
+    compare(original, modified, "", &mut set, &mut unset);
+
+    let changed = set.keys().chain(unset.keys()).cloned().collect();
+    let mut update = Document::new();
+    if !set.is_empty() {
+        update.insert("$set", set);
+    }
+    if !unset.is_empty() {
+        update.insert("$unset", unset);
+    }
+    Some((update, changed))
+}
+
+fn compare(
+    original: &Document,
+    modified: &Document,
+    prefix: &str,
+    set: &mut Document,
+    unset: &mut Document,
+) {
+    for (key, value) in modified {
+        let path = format!("{}{}", prefix, key);
+        match (original.get(key), value) {
+            (Some(before), after) if before == after => {}
+            (Some(Bson::Document(before)), Bson::Document(after))
+                if addressable(before) && addressable(after) =>
+            {
+                compare(before, after, &format!("{}.", path), set, unset);
+            }
+            _ => {
+                set.insert(path, value.clone());
+            }
+        }
+    }
+    for key in original.keys() {
+        if !modified.contains_key(key) {
+            unset.insert(format!("{}{}", prefix, key), "");
+        }
+    }
+}
+
+/// Whether every key of `document` can be part of a dotted path.
+fn addressable(document: &Document) -> bool {
+    !document.is_empty() && document.keys().all(|key| addressable_key(key))
+}
+
+fn addressable_key(key: &str) -> bool {
+    !key.is_empty() && !key.contains('.') && !key.starts_with('$')
+}
+
+impl<T> InstrumentedCollection<T>
+where
+    T: Serialize,
+{
+    /// Updates the document matching `query` with only the fields that differ between
+    /// `original` and `modified`, instead of replacing it, so concurrent writes to other fields
+    /// are kept. Returns `None` without a round trip when nothing changed.
+    ///
+    /// The update or replacement runs in the method's own span, which records the changed paths
+    /// in `db.update.fields`. When a top-level field name of either document cannot be used in
+    /// an update path, such as one containing a `.` or starting with `$`, the document is
+    /// replaced with `modified` instead and `db.update.replaced` is set.
+    #[instrument(
+    fields(
+    db.name = % self.info.database_name ,
+    db.system = "mongodb",
+    db.collection = % self.inner.name(),
+    otel.kind = "client",
+    tenant.id = self.info.tenant.as_deref(),
+    policy.violation = tracing::field::Empty,
+    db.request.size = tracing::field::Empty,
+    db.response.size = tracing::field::Empty,
+    db.update.fields = tracing::field::Empty,
+    db.update.replaced = tracing::field::Empty,
+    ),
+    skip(self, query, original, modified, options)
+    )]
+    pub async fn update_one_from_diff(
+        &self,
+        query: Document,
+        original: &T,
+        modified: &T,
+        options: impl Into<Option<UpdateOptions>>,
+    ) -> Result<Option<UpdateResult>> {
+        let (original, modified_document) = (to_document(original)?, to_document(modified)?);
+        let Some((update, changed)) = update(&original, &modified_document) else {
+            if original == modified_document {
+                return Ok(None);
+            }
+            tracing::Span::current().record("db.update.replaced", true);
+            let options = options.into().unwrap_or_default();
+            let options = ReplaceOptions::builder()
+                .bypass_document_validation(options.bypass_document_validation)
+                .upsert(options.upsert)
+                .collation(options.collation)
+                .hint(options.hint)
+                .write_concern(options.write_concern)
+                .let_vars(options.let_vars)
+                .comment(options.comment)
+                .build();
+            return self
+                .run_replace_one(query, modified, Some(options))
+                .await
+                .map(Some);
+        };
+        tracing::Span::current().record("db.update.fields", changed.join(",").as_str());
+        if update.is_empty() {
+            return Ok(None);
+        }
+        self.run_update_one(
+            query,
+            None,
+            UpdateModifications::Document(update),
+            options.into(),
+        )
+        .await
+        .map(Some)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mongodb::bson::doc;
+
+    use super::*;
+
+    #[test]
+    fn updates_set_and_unset_changed_paths() {
+        let cases = [
+            (doc! { "a": 1, "b": 2 }, doc! { "a": 1, "b": 2 }, doc! {}),
+            (
+                doc! { "a": 1, "b": 2 },
+                doc! { "a": 3, "b": 2, "c": 4 },
+                doc! { "$set": { "a": 3, "c": 4 } },
+            ),
+            (
+                doc! { "a": 1, "b": 2 },
+                doc! { "a": 1 },
+                doc! { "$unset": { "b": "" } },
+            ),
+            (
+                doc! { "address": { "city": "Paris", "zip": "75001", "geo": { "lat": 1 } } },
+                doc! { "address": { "city": "Lyon", "geo": { "lat": 2 } } },
+                doc! {
+                    "$set": { "address.city": "Lyon", "address.geo.lat": 2 },
+                    "$unset": { "address.zip": "" },
+                },
+            ),
+            (
+                doc! { "tags": ["a", "b"], "items": [{ "qty": 1 }] },
+                doc! { "tags": ["a"], "items": [{ "qty": 2 }] },
+                doc! { "$set": { "tags": ["a"], "items": [{ "qty": 2 }] } },
+            ),
+            (
+                doc! { "a": { "b": 1 } },
+                doc! { "a": 5 },
+                doc! { "$set": { "a": 5 } },
+            ),
+            (
+                doc! { "a": {} },
+                doc! { "a": { "b": 1 } },
+                doc! { "$set": { "a": { "b": 1 } } },
+            ),
+            (
+                doc! { "prices": { "1.5": 2 } },
+                doc! { "prices": { "1.5": 3 } },
+                doc! { "$set": { "prices": { "1.5": 3 } } },
+            ),
+        ];
+        for (original, modified, expected) in cases {
+            let (update, _) = update(&original, &modified).unwrap();
+            assert_eq!(update, expected, "{original} -> {modified}");
+        }
+    }
+
+    #[test]
+    fn changed_paths_are_listed() {
+        let (_, changed) = update(
+            &doc! { "a": { "b": 1 }, "c": 1, "d": 1 },
+            &doc! { "a": { "b": 2 }, "c": 1 },
+        )
+        .unwrap();
+        assert_eq!(changed, vec!["a.b", "d"]);
+    }
+
+    #[test]
+    fn unaddressable_top_level_fields_need_a_replacement() {
+        assert_eq!(update(&doc! { "a.b": 1 }, &doc! { "a.b": 2 }), None);
+        assert_eq!(update(&doc! { "a": 1 }, &doc! { "a": 1, "$b": 2 }), None);
+        assert_eq!(update(&doc! { "": 1 }, &doc! {}), None);
+    }
+}
@@ -0,0 +1,169 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use mongodb::bson::{doc, from_document, Bson};
+use mongodb::error::{BulkWriteError, ErrorKind, WriteError, WriteFailure};
+
+use crate::interceptor::OperationKind;
+use crate::policy::PolicyRule;
+use crate::size;
+
+/// Errors raised by this crate rather than by the driver.
+///
+/// They are carried inside [`mongodb::error::Error`] as a custom error so every wrapper keeps
+/// the driver's `Result` type; use [`Error::from_mongo`] to get them back.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// Another instance holds the migrations lock.
+    MigrationLocked { owner: String },
+    /// Two registered migrations share the same version.
+    DuplicateMigration { version: u64 },
+    /// The history collection records a version that is not registered.
+    UnknownMigration { version: u64 },
+    /// The collection [`Policy`](crate::policy::Policy) rejected the operation.
+    PolicyViolation {
+        rule: PolicyRule,
+        operation: OperationKind,
+    },
+    /// A [`TenantCollection`](crate::tenant::TenantCollection) refused an operation that could
+    /// reach another tenant's documents.
+    TenantViolation {
+        tenant: String,
+        reason: &'static str,
+    },
+    /// A [`MemoryCollection`](crate::memory::MemoryCollection) was given a query, update or
+    /// option it does not implement.
+    Unsupported { feature: String },
+    /// A [`ReplayCollection`](crate::fixture::ReplayCollection) got a request the fixture does
+    /// not have next.
+    Replay { reason: String },
+    /// The document at `index` of an insert is over the 16 MB BSON limit.
+    DocumentTooLarge { index: usize, size: usize },
+    /// Batches of an [`insert_many_batched`](crate::InstrumentedCollection::insert_many_batched)
+    /// failed. `inserted_ids` holds the documents known to be inserted and `write_errors` the
+    /// server's write errors, both by index in the inserted documents; `errors` describes the
+    /// failures that were not write errors, such as a network error or a rejecting interceptor.
+    BatchedInsert {
+        inserted_ids: HashMap<usize, Bson>,
+        write_errors: Vec<BulkWriteError>,
+        errors: Vec<String>,
+    },
+    /// A [`VersionedCollection`](crate::version::VersionedCollection) write found no document
+    /// at the expected version.
+    VersionConflict { field: String, expected: i64 },
+    /// A [`HistoryCollection`](crate::history::HistoryCollection) could not record a version.
+    History { reason: String },
+    /// A [`Pipeline`](crate::pipeline::Pipeline) breaks a stage ordering rule.
+    InvalidPipeline { reason: String },
+    /// An [`explain_update`](crate::InstrumentedCollection::explain_update) was given an update
+    /// it cannot put in an explained command.
+    Explain { reason: String },
+}
+
+impl Error {
+    pub fn from_mongo(error: &mongodb::error::Error) -> Option<&Error> {
+        error.get_custom::<Error>()
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::MigrationLocked { owner } => {
+                write!(f, "migrations are locked by {}", owner)
+            }
+            Error::DuplicateMigration { version } => {
+                write!(f, "migration version {} is registered twice", version)
+            }
+            Error::UnknownMigration { version } => {
+                write!(
+                    f,
+                    "migration version {} was applied but is not registered",
+                    version
+                )
+            }
+            Error::PolicyViolation { rule, operation } => {
+                write!(f, "{} rejected by the {} policy rule", operation, rule)
+            }
+            Error::TenantViolation { tenant, reason } => {
+                write!(f, "refused for tenant {}: {}", tenant, reason)
+            }
+            Error::Unsupported { feature } => {
+                write!(
+                    f,
+                    "{} is not supported by the in-memory collection",
+                    feature
+                )
+            }
+            Error::Replay { reason } => write!(f, "replay failed: {}", reason),
+            Error::DocumentTooLarge { index, size } => {
+                write!(
+                    f,
+                    "document {} is {} bytes, over the {} bytes BSON limit",
+                    index,
+                    size,
+                    size::MAX_DOCUMENT_SIZE
+                )
+            }
+            Error::BatchedInsert {
+                inserted_ids,
+                write_errors,
+                errors,
+            } => {
+                write!(
+                    f,
+                    "batched insert failed with {} write errors after inserting {} documents",
+                    write_errors.len(),
+                    inserted_ids.len()
+                )?;
+                for error in write_errors {
+                    write!(f, "; document {}: {}", error.index, error.message)?;
+                }
+                for error in errors {
+                    write!(f, "; {}", error)?;
+                }
+                Ok(())
+            }
+            Error::VersionConflict { field, expected } => {
+                write!(
+                    f,
+                    "no document at {} {}, it was modified or deleted concurrently",
+                    field, expected
+                )
+            }
+            Error::History { reason } => write!(f, "history not recorded: {}", reason),
+            Error::InvalidPipeline { reason } => write!(f, "invalid pipeline: {}", reason),
+            Error::Explain { reason } => write!(f, "cannot explain: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<Error> for mongodb::error::Error {
+    fn from(error: Error) -> Self {
+        mongodb::error::Error::custom(error)
+    }
+}
+
+pub(crate) fn is_duplicate_key(error: &mongodb::error::Error) -> bool {
+    matches!(
+        &*error.kind,
+        ErrorKind::Write(WriteFailure::WriteError(write_error)) if write_error.code == 11000
+    )
+}
+
+/// A write error as the server reports it, for errors produced without a server.
+pub(crate) fn write_error(code: i32, message: String) -> mongodb::error::Error {
+    match from_document::<WriteError>(doc! { "code": code, "errmsg": message }) {
+        Ok(error) => ErrorKind::Write(WriteFailure::WriteError(error)).into(),
+        Err(error) => error.into(),
+    }
+}
+
+pub(crate) fn duplicate_key(id: &Bson) -> mongodb::error::Error {
+    write_error(
+        11000,
+        format!("E11000 duplicate key error dup key: {{ _id: {} }}", id),
+    )
+}
@@ -0,0 +1,577 @@
+use std::time::Duration;
+
+use mongodb::bson::{doc, to_bson, Bson, Document};
+use mongodb::error::Result;
+use mongodb::options::{FindOptions, UpdateModifications};
+use tracing::instrument;
+
+use crate::error::Error;
+use crate::interceptor::{Operation, OperationKind};
+use crate::mongo_tracing::InstrumentedCollection;
+use crate::size;
+
+/// How much the server runs and reports for an explained command.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Verbosity {
+    /// The chosen plan only, without running it.
+    QueryPlanner,
+    /// The chosen plan, run to completion.
+    #[default]
+    ExecutionStats,
+    /// Every candidate plan, each run during plan selection.
+    AllPlansExecution,
+}
+
+impl Verbosity {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Verbosity::QueryPlanner => "queryPlanner",
+            Verbosity::ExecutionStats => "executionStats",
+            Verbosity::AllPlansExecution => "allPlansExecution",
+        }
+    }
+}
+
+/// A stage of a query plan, such as `IXSCAN` or `FETCH`, with its inputs.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlanStage {
+    pub stage: String,
+    pub index_name: Option<String>,
+    pub inputs: Vec<PlanStage>,
+}
+
+impl PlanStage {
+    fn parse(stage: &Document) -> Option<PlanStage> {
+        // Plans run by the slot-based engine nest the classic tree under `queryPlan`.
+        let stage = stage.get_document("queryPlan").unwrap_or(stage);
+        let mut inputs: Vec<PlanStage> = stage
+            .get_document("inputStage")
+            .ok()
+            .and_then(PlanStage::parse)
+            .into_iter()
+            .collect();
+        if let Ok(stages) = stage.get_array("inputStages") {
+            inputs.extend(
+                stages
+                    .iter()
+                    .filter_map(Bson::as_document)
+                    .filter_map(PlanStage::parse),
+            );
+        }
+        // Through `mongos`, a `SHARD_MERGE` or `SINGLE_SHARD` stage lists each shard's plan.
+        if let Ok(shards) = stage.get_array("shards") {
+            inputs.extend(
+                shards
+                    .iter()
+                    .filter_map(Bson::as_document)
+                    .filter_map(|shard| shard.get_document("winningPlan").ok())
+                    .filter_map(PlanStage::parse),
+            );
+        }
+        Some(PlanStage {
+            stage: stage.get_str("stage").ok()?.to_string(),
+            index_name: stage.get_str("indexName").ok().map(str::to_string),
+            inputs,
+        })
+    }
+
+    fn indexes(&self, indexes: &mut Vec<String>) {
+        if let Some(index) = &self.index_name {
+            if !indexes.contains(index) {
+                indexes.push(index.clone());
+            }
+        }
+        for input in &self.inputs {
+            input.indexes(indexes);
+        }
+    }
+}
+
+/// The main figures of an `explain` result. Execution figures are only reported from
+/// [`Verbosity::ExecutionStats`] on.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Explain {
+    pub winning_plan: Option<PlanStage>,
+    /// The indexes the winning plan scans; empty for a collection scan.
+    pub indexes_used: Vec<String>,
+    pub keys_examined: Option<u64>,
+    pub docs_examined: Option<u64>,
+    pub returned: Option<u64>,
+    pub execution_time: Option<Duration>,
+    /// The server's complete response.
+    pub raw: Document,
+}
+
+impl Explain {
+    fn parse(raw: Document) -> Explain {
+        // Aggregations whose first stage uses the query system report it in a `$cursor` stage.
+        let cursor = raw
+            .get_array("stages")
+            .ok()
+            .and_then(|stages| stages.first())
+            .and_then(Bson::as_document)
+            .and_then(|stage| stage.get_document("$cursor").ok());
+        let section = |name: &str| {
+            raw.get_document(name)
+                .ok()
+                .or_else(|| cursor.and_then(|cursor| cursor.get_document(name).ok()))
+        };
+
+        let winning_plan = section("queryPlanner")
+            .and_then(|planner| planner.get_document("winningPlan").ok())
+            .and_then(PlanStage::parse);
+        let mut indexes_used = Vec::new();
+        if let Some(plan) = &winning_plan {
+            plan.indexes(&mut indexes_used);
+        }
+        let stats = section("executionStats");
+        let stat = |name: &str| stats.and_then(|stats| count(stats.get(name)?));
+        Explain {
+            keys_examined: stat("totalKeysExamined"),
+            docs_examined: stat("totalDocsExamined"),
+            returned: stat("nReturned"),
+            execution_time: stat("executionTimeMillis").map(Duration::from_millis),
+            winning_plan,
+            indexes_used,
+            raw,
+        }
+    }
+}
+
+fn count(value: &Bson) -> Option<u64> {
+    match value {
+        Bson::Int32(value) => u64::try_from(*value).ok(),
+        Bson::Int64(value) => u64::try_from(*value).ok(),
+        Bson::Double(value) if *value >= 0.0 => Some(*value as u64),
+        _ => None,
+    }
+}
+
+fn update_document(update: &UpdateModifications) -> Result<Bson> {
+    match update {
+        UpdateModifications::Document(update) => Ok(Bson::Document(update.clone())),
+        UpdateModifications::Pipeline(stages) => Ok(Bson::Array(
+            stages.iter().cloned().map(Bson::Document).collect(),
+        )),
+        other => Err(Error::Explain {
+            reason: format!("unsupported update {:?}", other),
+        }
+        .into()),
+    }
+}
+
+impl<T> InstrumentedCollection<T> {
+    /// Explains `find` with the filter, sort, projection, skip, limit, hint and collation of
+    /// `options`.
+    #[instrument(
+    fields(
+    db.name = % self.info.database_name ,
+    db.system = "mongodb",
+    db.collection = % self.inner.name(),
+    otel.kind = "client",
+    tenant.id = self.info.tenant.as_deref(),
+    policy.violation = tracing::field::Empty,
+    db.request.size = tracing::field::Empty,
+    db.response.size = tracing::field::Empty,
+    db.explain.verbosity = verbosity.name(),
+    db.explain.indexes = tracing::field::Empty,
+    db.explain.docs_examined = tracing::field::Empty,
+    ),
+    skip(self,filter,options,verbosity)
+    )]
+    pub async fn explain_find(
+        &self,
+        filter: impl Into<Option<Document>>,
+        options: impl Into<Option<FindOptions>>,
+        verbosity: Verbosity,
+    ) -> Result<Explain> {
+        let filter = filter.into();
+        let mut find = doc! { "find": self.inner.name() };
+        if let Some(filter) = &filter {
+            find.insert("filter", filter.clone());
+        }
+        if let Some(options) = options.into() {
+            if let Some(sort) = options.sort {
+                find.insert("sort", sort);
+            }
+            if let Some(projection) = options.projection {
+                find.insert("projection", projection);
+            }
+            if let Some(skip) = options.skip {
+                find.insert("skip", skip as i64);
+            }
+            if let Some(limit) = options.limit {
+                find.insert("limit", limit);
+            }
+            if let Some(hint) = options.hint {
+                find.insert("hint", to_bson(&hint)?);
+            }
+            if let Some(collation) = options.collation {
+                find.insert("collation", to_bson(&collation)?);
+            }
+        }
+        let operation = Operation::new(OperationKind::Explain, self.inner.namespace())
+            .with_filter(filter.as_ref());
+        self.explain(&operation, find, verbosity).await
+    }
+
+    #[instrument(
+    fields(
+    db.name = % self.info.database_name ,
+    db.system = "mongodb",
+    db.collection = % self.inner.name(),
+    otel.kind = "client",
+    tenant.id = self.info.tenant.as_deref(),
+    policy.violation = tracing::field::Empty,
+    db.request.size = tracing::field::Empty,
+    db.response.size = tracing::field::Empty,
+    db.explain.verbosity = verbosity.name(),
+    db.explain.indexes = tracing::field::Empty,
+    db.explain.docs_examined = tracing::field::Empty,
+    ),
+    skip(self,pipeline,verbosity)
+    )]
+    pub async fn explain_aggregate(
+        &self,
+        pipeline: impl IntoIterator<Item = Document>,
+        verbosity: Verbosity,
+    ) -> Result<Explain> {
+        let pipeline: Vec<Document> = pipeline.into_iter().collect();
+        let operation =
+            Operation::new(OperationKind::Explain, self.inner.namespace()).with_pipeline(&pipeline);
+        self.explain(
+            &operation,
+            doc! { "aggregate": self.inner.name(), "pipeline": &pipeline, "cursor": {} },
+            verbosity,
+        )
+        .await
+    }
+
+    #[instrument(
+    fields(
+    db.name = % self.info.database_name ,
+    db.system = "mongodb",
+    db.collection = % self.inner.name(),
+    otel.kind = "client",
+    tenant.id = self.info.tenant.as_deref(),
+    policy.violation = tracing::field::Empty,
+    db.request.size = tracing::field::Empty,
+    db.response.size = tracing::field::Empty,
+    db.explain.verbosity = verbosity.name(),
+    db.explain.indexes = tracing::field::Empty,
+    db.explain.docs_examined = tracing::field::Empty,
+    ),
+    skip(self,filter,verbosity)
+    )]
+    pub async fn explain_count(
+        &self,
+        filter: impl Into<Option<Document>>,
+        verbosity: Verbosity,
+    ) -> Result<Explain> {
+        let query = filter.into().unwrap_or_default();
+        let operation = Operation::new(OperationKind::Explain, self.inner.namespace())
+            .with_filter(Some(&query));
+        self.explain(
+            &operation,
+            doc! { "count": self.inner.name(), "query": &query },
+            verbosity,
+        )
+        .await
+    }
+
+    /// Explains an update of the first document matching `query`, or of all of them when
+    /// `multi`. With [`Verbosity::ExecutionStats`] the update is evaluated but not applied.
+    #[instrument(
+    fields(
+    db.name = % self.info.database_name ,
+    db.system = "mongodb",
+    db.collection = % self.inner.name(),
+    otel.kind = "client",
+    tenant.id = self.info.tenant.as_deref(),
+    policy.violation = tracing::field::Empty,
+    db.request.size = tracing::field::Empty,
+    db.response.size = tracing::field::Empty,
+    db.explain.verbosity = verbosity.name(),
+    db.explain.indexes = tracing::field::Empty,
+    db.explain.docs_examined = tracing::field::Empty,
+    ),
+    skip(self,query,update,verbosity)
+    )]
+    pub async fn explain_update(
+        &self,
+        query: Document,
+        update: impl Into<UpdateModifications>,
+        multi: bool,
+        verbosity: Verbosity,
+    ) -> Result<Explain> {
+        let update = update.into();
+        let operation = Operation::new(OperationKind::Explain, self.inner.namespace())
+            .with_filter(Some(&query))
+            .with_update(&update);
+        let statement = doc! { "q": &query, "u": update_document(&update)?, "multi": multi };
+        self.explain(
+            &operation,
+            doc! { "update": self.inner.name(), "updates": [statement] },
+            verbosity,
+        )
+        .await
+    }
+
+    /// Explains a delete of the first document matching `query`, or of all of them when
+    /// `multi`. Nothing is deleted.
+    #[instrument(
+    fields(
+    db.name = % self.info.database_name ,
+    db.system = "mongodb",
+    db.collection = % self.inner.name(),
+    otel.kind = "client",
+    tenant.id = self.info.tenant.as_deref(),
+    policy.violation = tracing::field::Empty,
+    db.request.size = tracing::field::Empty,
+    db.response.size = tracing::field::Empty,
+    db.explain.verbosity = verbosity.name(),
+    db.explain.indexes = tracing::field::Empty,
+    db.explain.docs_examined = tracing::field::Empty,
+    ),
+    skip(self,query,verbosity)
+    )]
+    pub async fn explain_delete(
+        &self,
+        query: Document,
+        multi: bool,
+        verbosity: Verbosity,
+    ) -> Result<Explain> {
+        let operation = Operation::new(OperationKind::Explain, self.inner.namespace())
+            .with_filter(Some(&query));
+        let delete = doc! { "q": &query, "limit": if multi { 0 } else { 1 } };
+        self.explain(
+            &operation,
+            doc! { "delete": self.inner.name(), "deletes": [delete] },
+            verbosity,
+        )
+        .await
+    }
+
+    /// Runs the `explain` of `command` through the interceptors, as `operation`.
+    async fn explain(
+        &self,
+        operation: &Operation<'_>,
+        command: Document,
+        verbosity: Verbosity,
+    ) -> Result<Explain> {
+        let raw = self
+            .interceptors
+            .run(
+                operation,
+                self.info.database.run_command(
+                    doc! { "explain": command, "verbosity": verbosity.name() },
+                    None,
+                ),
+            )
+            .await?;
+        let span = tracing::Span::current();
+        span.record("db.response.size", size::of(&raw) as u64);
+        let explain = Explain::parse(raw);
+        span.record(
+            "db.explain.indexes",
+            explain.indexes_used.join(",").as_str(),
+        );
+        if let Some(docs_examined) = explain.docs_examined {
+            span.record("db.explain.docs_examined", docs_examined);
+        }
+        Ok(explain)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stage(stage: &str, index_name: Option<&str>, inputs: Vec<PlanStage>) -> PlanStage {
+        PlanStage {
+            stage: stage.to_string(),
+            index_name: index_name.map(str::to_string),
+            inputs,
+        }
+    }
+
+    #[test]
+    fn parses_a_classic_find() {
+        let explain = Explain::parse(doc! {
+            "explainVersion": "1",
+            "queryPlanner": {
+                "namespace": "shop.orders",
+                "winningPlan": {
+                    "stage": "FETCH",
+                    "inputStage": {
+                        "stage": "IXSCAN",
+                        "keyPattern": { "status": 1 },
+                        "indexName": "status_1",
+                        "direction": "forward",
+                    },
+                },
+                "rejectedPlans": [],
+            },
+            "executionStats": {
+                "executionSuccess": true,
+                "nReturned": 3,
+                "executionTimeMillis": 2,
+                "totalKeysExamined": 3,
+                "totalDocsExamined": 3,
+            },
+            "ok": 1.0,
+        });
+        assert_eq!(
+            explain.winning_plan,
+            Some(stage(
+                "FETCH",
+                None,
+                vec![stage("IXSCAN", Some("status_1"), vec![])]
+            ))
+        );
+        assert_eq!(explain.indexes_used, vec!["status_1"]);
+        assert_eq!(explain.keys_examined, Some(3));
+        assert_eq!(explain.docs_examined, Some(3));
+        assert_eq!(explain.returned, Some(3));
+        assert_eq!(explain.execution_time, Some(Duration::from_millis(2)));
+    }
+
+    #[test]
+    fn parses_a_slot_based_plan() {
+        let explain = Explain::parse(doc! {
+            "explainVersion": "2",
+            "queryPlanner": {
+                "winningPlan": {
+                    "queryPlan": {
+                        "stage": "IXSCAN",
+                        "planNodeId": 1,
+                        "indexName": "customer_1_created_-1",
+                    },
+                    "slotBasedPlan": { "slots": "$$RESULT=s11", "stages": "[1] ixseek ..." },
+                },
+            },
+            "executionStats": {
+                "nReturned": 10,
+                "executionTimeMillis": 0,
+                "totalKeysExamined": 10,
+                "totalDocsExamined": 0,
+            },
+            "ok": 1.0,
+        });
+        assert_eq!(explain.indexes_used, vec!["customer_1_created_-1"]);
+        assert_eq!(explain.docs_examined, Some(0));
+    }
+
+    #[test]
+    fn parses_an_aggregate_cursor_stage() {
+        let explain = Explain::parse(doc! {
+            "explainVersion": "1",
+            "stages": [
+                { "$cursor": {
+                    "queryPlanner": {
+                        "winningPlan": { "stage": "COLLSCAN", "direction": "forward" },
+                    },
+                    "executionStats": {
+                        "nReturned": 120,
+                        "executionTimeMillis": 4,
+                        "totalKeysExamined": 0,
+                        "totalDocsExamined": 500,
+                    },
+                } },
+                { "$group": { "_id": "$status", "n": { "$sum": { "$const": 1 } } } },
+            ],
+            "ok": 1.0,
+        });
+        assert_eq!(explain.winning_plan, Some(stage("COLLSCAN", None, vec![])));
+        assert!(explain.indexes_used.is_empty());
+        assert_eq!(explain.docs_examined, Some(500));
+        assert_eq!(explain.returned, Some(120));
+    }
+
+    #[test]
+    fn parses_a_sharded_plan() {
+        let explain = Explain::parse(doc! {
+            "queryPlanner": {
+                "mongosPlannerVersion": 1,
+                "winningPlan": {
+                    "stage": "SHARD_MERGE",
+                    "shards": [
+                        {
+                            "shardName": "shard-a",
+                            "winningPlan": {
+                                "stage": "SHARDING_FILTER",
+                                "inputStage": {
+                                    "stage": "FETCH",
+                                    "inputStage": { "stage": "IXSCAN", "indexName": "status_1" },
+                                },
+                            },
+                            "rejectedPlans": [],
+                        },
+                        {
+                            "shardName": "shard-b",
+                            "winningPlan": {
+                                "queryPlan": { "stage": "COLLSCAN", "direction": "forward" },
+                            },
+                            "rejectedPlans": [],
+                        },
+                    ],
+                },
+            },
+            "executionStats": {
+                "nReturned": 7,
+                "executionTimeMillis": 5,
+                "totalKeysExamined": 4,
+                "totalDocsExamined": 40,
+                "executionStages": { "stage": "SHARD_MERGE", "shards": [] },
+            },
+            "ok": 1.0,
+        });
+        assert_eq!(
+            explain.winning_plan,
+            Some(stage(
+                "SHARD_MERGE",
+                None,
+                vec![
+                    stage(
+                        "SHARDING_FILTER",
+                        None,
+                        vec![stage(
+                            "FETCH",
+                            None,
+                            vec![stage("IXSCAN", Some("status_1"), vec![])]
+                        )]
+                    ),
+                    stage("COLLSCAN", None, vec![]),
+                ]
+            ))
+        );
+        assert_eq!(explain.indexes_used, vec!["status_1"]);
+        assert_eq!(explain.keys_examined, Some(4));
+        assert_eq!(explain.docs_examined, Some(40));
+    }
+
+    #[test]
+    fn parses_a_plan_without_execution_stats() {
+        let explain = Explain::parse(doc! {
+            "queryPlanner": { "winningPlan": { "stage": "EOF" } },
+            "ok": 1.0,
+        });
+        assert_eq!(explain.winning_plan, Some(stage("EOF", None, vec![])));
+        assert_eq!(explain.docs_examined, None);
+        assert_eq!(explain.execution_time, None);
+    }
+
+    #[test]
+    fn explains_document_and_pipeline_updates() {
+        let update = UpdateModifications::Document(doc! { "$set": { "status": "paid" } });
+        assert_eq!(
+            update_document(&update).unwrap(),
+            Bson::Document(doc! { "$set": { "status": "paid" } })
+        );
+        let update = UpdateModifications::Pipeline(vec![doc! { "$unset": "draft" }]);
+        assert_eq!(
+            update_document(&update).unwrap(),
+            Bson::Array(vec![Bson::Document(doc! { "$unset": "draft" })])
+        );
+    }
+}
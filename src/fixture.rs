@@ -0,0 +1,805 @@
+use std::collections::{HashMap, VecDeque};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::marker::PhantomData;
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use async_trait::async_trait;
+use futures_util::stream::{self, BoxStream};
+use futures_util::StreamExt;
+use mongodb::bson::{doc, from_bson, to_bson, Bson, Document};
+use mongodb::error::{ErrorKind, Result, WriteFailure};
+use mongodb::options::{
+    CountOptions, DeleteOptions, DistinctOptions, FindOneOptions, FindOptions, InsertManyOptions,
+    InsertOneOptions, ReplaceOptions, UpdateModifications, UpdateOptions,
+};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::backend::CollectionBackend;
+use crate::error::{write_error, Error};
+use crate::mongo_tracing::InstrumentedCollection;
+use crate::results::{DeleteResult, InsertManyResult, InsertOneResult, UpdateResult};
+use crate::shape;
+
+/// Writes the operations of [`RecordingCollection`]s to a fixture file, one line of canonical
+/// extended JSON per operation with its namespace, request and response.
+///
+/// Requests are recorded with their values, which a [`Replay`] of the fixture checks, unless
+/// [`Recorder::redact`] is set: values are then replaced by `"?"` and only the shape of
+/// requests is checked. Responses are always recorded in full.
+#[derive(Clone)]
+pub struct Recorder {
+    file: Arc<Mutex<File>>,
+    redact: bool,
+}
+
+impl Recorder {
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Recorder {
+            file: Arc::new(Mutex::new(File::create(path)?)),
+            redact: false,
+        })
+    }
+
+    /// Records requests with their values replaced by `"?"`, for fixtures that must not hold
+    /// them; a replay then no longer tells requests of the same shape apart.
+    pub fn redact(mut self, redact: bool) -> Self {
+        self.redact = redact;
+        self
+    }
+
+    pub fn record<T, B>(
+        &self,
+        backend: B,
+        namespace: impl Into<String>,
+    ) -> RecordingCollection<T, B> {
+        RecordingCollection {
+            backend,
+            namespace: namespace.into(),
+            recorder: self.clone(),
+            document_type: PhantomData,
+        }
+    }
+
+    fn write(
+        &self,
+        namespace: &str,
+        operation: &str,
+        request: Document,
+        response: &Result<Bson>,
+    ) -> Result<()> {
+        let request = if self.redact {
+            shape::redact(&request)
+        } else {
+            request
+        };
+        let response = match response {
+            Ok(value) => doc! { "ok": value.clone() },
+            Err(error) => {
+                let mut recorded = doc! { "error": error.to_string() };
+                if let ErrorKind::Write(WriteFailure::WriteError(error)) = &*error.kind {
+                    recorded.insert("code", error.code);
+                    recorded.insert("message", error.message.clone());
+                }
+                recorded
+            }
+        };
+        let interaction = doc! {
+            "namespace": namespace,
+            "operation": operation,
+            "redacted": self.redact,
+            "request": request,
+            "response": response,
+        };
+        let line = Bson::Document(interaction).into_canonical_extjson();
+        writeln!(lock(&self.file), "{}", line)?;
+        Ok(())
+    }
+}
+
+impl<T> InstrumentedCollection<T> {
+    /// This collection as a [`CollectionBackend`] whose operations are written by `recorder`.
+    pub fn record_to(&self, recorder: &Recorder) -> RecordingCollection<T, Self> {
+        recorder.record(self.clone(), self.namespace().to_string())
+    }
+}
+
+/// A [`CollectionBackend`] forwarding to another and recording every operation.
+///
+/// `find` reads the whole cursor before returning, to record every document found, and
+/// returns them from memory.
+pub struct RecordingCollection<T, B> {
+    backend: B,
+    namespace: String,
+    recorder: Recorder,
+    document_type: PhantomData<fn() -> T>,
+}
+
+impl<T, B> RecordingCollection<T, B> {
+    fn record<R>(
+        &self,
+        operation: &str,
+        request: Document,
+        result: Result<R>,
+        response: impl FnOnce(&R) -> Result<Bson>,
+    ) -> Result<R> {
+        let response = match &result {
+            Ok(result) => response(result),
+            Err(error) => Err(error.clone()),
+        };
+        self.recorder
+            .write(&self.namespace, operation, request, &response)?;
+        result
+    }
+}
+
+/// Serves the responses of a fixture written by a [`Recorder`], without a server.
+///
+/// Operations must arrive in the recorded order with the recorded requests; anything else fails
+/// with [`Error::Replay`]. Recorded write errors are returned as such, other recorded errors as
+/// [`Error::Replay`].
+#[derive(Clone)]
+pub struct Replay {
+    interactions: Arc<Mutex<VecDeque<Document>>>,
+}
+
+impl Replay {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut interactions = VecDeque::new();
+        for line in BufReader::new(File::open(path)?).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let value: serde_json::Value = serde_json::from_str(&line)?;
+            match Bson::try_from(value) {
+                Ok(Bson::Document(interaction)) => interactions.push_back(interaction),
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "fixture line is not an extended JSON document",
+                    ))
+                }
+            }
+        }
+        Ok(Replay {
+            interactions: Arc::new(Mutex::new(interactions)),
+        })
+    }
+
+    pub fn collection<T>(&self, namespace: impl Into<String>) -> ReplayCollection<T> {
+        ReplayCollection {
+            replay: self.clone(),
+            namespace: namespace.into(),
+            document_type: PhantomData,
+        }
+    }
+
+    /// Interactions not replayed yet; a test should end with none left.
+    pub fn remaining(&self) -> usize {
+        lock(&self.interactions).len()
+    }
+
+    fn next(&self, namespace: &str, operation: &str, request: Document) -> Result<Bson> {
+        let expected = lock(&self.interactions).pop_front().ok_or_else(|| {
+            replay_error(format!(
+                "unexpected {} on {}, the fixture has no more interactions",
+                operation, namespace
+            ))
+        })?;
+        let request = if expected.get_bool("redacted").unwrap_or(false) {
+            shape::redact(&request)
+        } else {
+            request
+        };
+        let expected_namespace = expected.get_str("namespace").unwrap_or_default();
+        let expected_operation = expected.get_str("operation").unwrap_or_default();
+        if expected_namespace != namespace || expected_operation != operation {
+            return Err(replay_error(format!(
+                "expected {} on {}, got {} on {}",
+                expected_operation, expected_namespace, operation, namespace
+            )));
+        }
+        if expected.get_document("request").ok() != Some(&request) {
+            return Err(replay_error(format!(
+                "{} on {} does not match the recorded request",
+                operation, namespace
+            )));
+        }
+        let response = expected
+            .get_document("response")
+            .map_err(|_| replay_error(format!("{} on {} has no response", operation, namespace)))?;
+        if let Some(value) = response.get("ok") {
+            return Ok(value.clone());
+        }
+        match response.get_i32("code") {
+            Ok(code) => Err(write_error(
+                code,
+                response.get_str("message").unwrap_or_default().to_string(),
+            )),
+            Err(_) => Err(replay_error(format!(
+                "recorded error: {}",
+                response.get_str("error").unwrap_or_default()
+            ))),
+        }
+    }
+}
+
+/// A [`CollectionBackend`] answering from a [`Replay`].
+pub struct ReplayCollection<T> {
+    replay: Replay,
+    namespace: String,
+    document_type: PhantomData<fn() -> T>,
+}
+
+impl<T> ReplayCollection<T> {
+    fn next(&self, operation: &str, request: Document) -> Result<Bson> {
+        self.replay.next(&self.namespace, operation, request)
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn replay_error(reason: String) -> mongodb::error::Error {
+    Error::Replay { reason }.into()
+}
+
+fn find_request(filter: &Option<Document>, options: &Option<FindOptions>) -> Document {
+    let mut request = doc! { "filter": filter.clone() };
+    if let Some(options) = options {
+        request.insert(
+            "options",
+            doc! {
+                "sort": options.sort.clone(),
+                "skip": options.skip.map(|skip| skip as i64),
+                "limit": options.limit,
+                "projection": options.projection.clone(),
+            },
+        );
+    }
+    request
+}
+
+fn find_one_request(filter: &Option<Document>, options: &Option<FindOneOptions>) -> Document {
+    let mut request = doc! { "filter": filter.clone() };
+    if let Some(options) = options {
+        request.insert(
+            "options",
+            doc! {
+                "sort": options.sort.clone(),
+                "skip": options.skip.map(|skip| skip as i64),
+                "projection": options.projection.clone(),
+            },
+        );
+    }
+    request
+}
+
+fn count_request(filter: &Option<Document>, options: &Option<CountOptions>) -> Document {
+    let mut request = doc! { "filter": filter.clone() };
+    if let Some(options) = options {
+        request.insert(
+            "options",
+            doc! {
+                "skip": options.skip.map(|skip| skip as i64),
+                "limit": options.limit.map(|limit| limit as i64),
+            },
+        );
+    }
+    request
+}
+
+fn update_request(
+    query: &Document,
+    update: &UpdateModifications,
+    upsert: Option<bool>,
+) -> Document {
+    let update = match update {
+        UpdateModifications::Document(update) => Bson::Document(update.clone()),
+        UpdateModifications::Pipeline(stages) => {
+            Bson::Array(stages.iter().cloned().map(Bson::Document).collect())
+        }
+        _ => Bson::Null,
+    };
+    doc! { "query": query.clone(), "update": update, "upsert": upsert }
+}
+
+fn insert_many_response(result: &InsertManyResult) -> Bson {
+    let mut ids: Vec<(&usize, &Bson)> = result.inserted_ids.iter().collect();
+    ids.sort_by_key(|(index, _)| **index);
+    Bson::Array(ids.into_iter().map(|(_, id)| id.clone()).collect())
+}
+
+#[async_trait]
+impl<T, B> CollectionBackend<T> for RecordingCollection<T, B>
+where
+    T: Serialize + DeserializeOwned + Unpin + Send + Sync + 'static,
+    B: CollectionBackend<T>,
+{
+    async fn find(
+        &self,
+        filter: Option<Document>,
+        options: Option<FindOptions>,
+    ) -> Result<BoxStream<'static, Result<T>>> {
+        let request = find_request(&filter, &options);
+        let result = match self.backend.find(filter, options).await {
+            Ok(found) => found
+                .collect::<Vec<Result<T>>>()
+                .await
+                .into_iter()
+                .collect::<Result<Vec<T>>>(),
+            Err(error) => Err(error),
+        };
+        let found = self.record("find", request, result, |found| Ok(to_bson(found)?))?;
+        Ok(stream::iter(found.into_iter().map(Ok)).boxed())
+    }
+
+    async fn find_one(
+        &self,
+        filter: Option<Document>,
+        options: Option<FindOneOptions>,
+    ) -> Result<Option<T>> {
+        let request = find_one_request(&filter, &options);
+        let result = self.backend.find_one(filter, options).await;
+        self.record("find_one", request, result, |found| Ok(to_bson(found)?))
+    }
+
+    async fn insert_one(
+        &self,
+        document: &T,
+        options: Option<InsertOneOptions>,
+    ) -> Result<InsertOneResult> {
+        let request = doc! { "document": to_bson(document)? };
+        let result = self.backend.insert_one(document, options).await;
+        self.record("insert_one", request, result, |result| Ok(to_bson(result)?))
+    }
+
+    async fn insert_many(
+        &self,
+        documents: &[T],
+        options: Option<InsertManyOptions>,
+    ) -> Result<InsertManyResult> {
+        let request = doc! { "documents": to_bson(documents)? };
+        let result = self.backend.insert_many(documents, options).await;
+        self.record("insert_many", request, result, |result| {
+            Ok(insert_many_response(result))
+        })
+    }
+
+    async fn replace_one(
+        &self,
+        query: Document,
+        replacement: &T,
+        options: Option<ReplaceOptions>,
+    ) -> Result<UpdateResult> {
+        let upsert = options.as_ref().and_then(|options| options.upsert);
+        let request = doc! {
+            "query": query.clone(),
+            "replacement": to_bson(replacement)?,
+            "upsert": upsert,
+        };
+        let result = self.backend.replace_one(query, replacement, options).await;
+        self.record("replace_one", request, result, |result| {
+            Ok(to_bson(result)?)
+        })
+    }
+
+    async fn update_one(
+        &self,
+        query: Document,
+        update: UpdateModifications,
+        options: Option<UpdateOptions>,
+    ) -> Result<UpdateResult> {
+        let upsert = options.as_ref().and_then(|options| options.upsert);
+        let request = update_request(&query, &update, upsert);
+        let result = self.backend.update_one(query, update, options).await;
+        self.record("update_one", request, result, |result| Ok(to_bson(result)?))
+    }
+
+    async fn update_many(
+        &self,
+        query: Document,
+        update: UpdateModifications,
+        options: Option<UpdateOptions>,
+    ) -> Result<UpdateResult> {
+        let upsert = options.as_ref().and_then(|options| options.upsert);
+        let request = update_request(&query, &update, upsert);
+        let result = self.backend.update_many(query, update, options).await;
+        self.record("update_many", request, result, |result| {
+            Ok(to_bson(result)?)
+        })
+    }
+
+    async fn delete_one(
+        &self,
+        query: Document,
+        options: Option<DeleteOptions>,
+    ) -> Result<DeleteResult> {
+        let request = doc! { "query": query.clone() };
+        let result = self.backend.delete_one(query, options).await;
+        self.record("delete_one", request, result, |result| Ok(to_bson(result)?))
+    }
+
+    async fn delete_many(
+        &self,
+        query: Document,
+        options: Option<DeleteOptions>,
+    ) -> Result<DeleteResult> {
+        let request = doc! { "query": query.clone() };
+        let result = self.backend.delete_many(query, options).await;
+        self.record("delete_many", request, result, |result| {
+            Ok(to_bson(result)?)
+        })
+    }
+
+    async fn count_documents(
+        &self,
+        filter: Option<Document>,
+        options: Option<CountOptions>,
+    ) -> Result<u64> {
+        let request = count_request(&filter, &options);
+        let result = self.backend.count_documents(filter, options).await;
+        self.record("count_documents", request, result, |count| {
+            Ok(Bson::Int64(*count as i64))
+        })
+    }
+
+    async fn distinct(
+        &self,
+        field_name: &str,
+        filter: Option<Document>,
+        options: Option<DistinctOptions>,
+    ) -> Result<Vec<Bson>> {
+        let request = doc! { "field": field_name, "filter": filter.clone() };
+        let result = self.backend.distinct(field_name, filter, options).await;
+        self.record("distinct", request, result, |values| {
+            Ok(Bson::Array(values.clone()))
+        })
+    }
+}
+
+#[async_trait]
+impl<T> CollectionBackend<T> for ReplayCollection<T>
+where
+    T: Serialize + DeserializeOwned + Unpin + Send + Sync + 'static,
+{
+    async fn find(
+        &self,
+        filter: Option<Document>,
+        options: Option<FindOptions>,
+    ) -> Result<BoxStream<'static, Result<T>>> {
+        let found: Vec<T> = from_bson(self.next("find", find_request(&filter, &options))?)?;
+        Ok(stream::iter(found.into_iter().map(Ok)).boxed())
+    }
+
+    async fn find_one(
+        &self,
+        filter: Option<Document>,
+        options: Option<FindOneOptions>,
+    ) -> Result<Option<T>> {
+        Ok(from_bson(
+            self.next("find_one", find_one_request(&filter, &options))?,
+        )?)
+    }
+
+    async fn insert_one(
+        &self,
+        document: &T,
+        _options: Option<InsertOneOptions>,
+    ) -> Result<InsertOneResult> {
+        let request = doc! { "document": to_bson(document)? };
+        Ok(from_bson(self.next("insert_one", request)?)?)
+    }
+
+    async fn insert_many(
+        &self,
+        documents: &[T],
+        _options: Option<InsertManyOptions>,
+    ) -> Result<InsertManyResult> {
+        let request = doc! { "documents": to_bson(documents)? };
+        let ids: Vec<Bson> = from_bson(self.next("insert_many", request)?)?;
+        Ok(InsertManyResult {
+            inserted_ids: ids.into_iter().enumerate().collect::<HashMap<_, _>>(),
+        })
+    }
+
+    async fn replace_one(
+        &self,
+        query: Document,
+        replacement: &T,
+        options: Option<ReplaceOptions>,
+    ) -> Result<UpdateResult> {
+        let request = doc! {
+            "query": query,
+            "replacement": to_bson(replacement)?,
+            "upsert": options.and_then(|options| options.upsert),
+        };
+        Ok(from_bson(self.next("replace_one", request)?)?)
+    }
+
+    async fn update_one(
+        &self,
+        query: Document,
+        update: UpdateModifications,
+        options: Option<UpdateOptions>,
+    ) -> Result<UpdateResult> {
+        let upsert = options.and_then(|options| options.upsert);
+        let request = update_request(&query, &update, upsert);
+        Ok(from_bson(self.next("update_one", request)?)?)
+    }
+
+    async fn update_many(
+        &self,
+        query: Document,
+        update: UpdateModifications,
+        options: Option<UpdateOptions>,
+    ) -> Result<UpdateResult> {
+        let upsert = options.and_then(|options| options.upsert);
+        let request = update_request(&query, &update, upsert);
+        Ok(from_bson(self.next("update_many", request)?)?)
+    }
+
+    async fn delete_one(
+        &self,
+        query: Document,
+        _options: Option<DeleteOptions>,
+    ) -> Result<DeleteResult> {
+        Ok(from_bson(
+            self.next("delete_one", doc! { "query": query })?,
+        )?)
+    }
+
+    async fn delete_many(
+        &self,
+        query: Document,
+        _options: Option<DeleteOptions>,
+    ) -> Result<DeleteResult> {
+        Ok(from_bson(
+            self.next("delete_many", doc! { "query": query })?,
+        )?)
+    }
+
+    async fn count_documents(
+        &self,
+        filter: Option<Document>,
+        options: Option<CountOptions>,
+    ) -> Result<u64> {
+        let count: i64 =
+            from_bson(self.next("count_documents", count_request(&filter, &options))?)?;
+        Ok(count as u64)
+    }
+
+    async fn distinct(
+        &self,
+        field_name: &str,
+        filter: Option<Document>,
+        _options: Option<DistinctOptions>,
+    ) -> Result<Vec<Bson>> {
+        let request = doc! { "field": field_name, "filter": filter };
+        Ok(from_bson(self.next("distinct", request)?)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use futures_util::TryStreamExt;
+
+    use super::*;
+    use crate::memory::MemoryCollection;
+
+    fn fixture(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "mongo-tracing-{}-{}.jsonl",
+            std::process::id(),
+            name
+        ))
+    }
+
+    fn replay_reason(error: mongodb::error::Error) -> String {
+        match Error::from_mongo(&error) {
+            Some(Error::Replay { reason }) => reason.clone(),
+            _ => panic!("unexpected error: {error}"),
+        }
+    }
+
+    /// Runs the same operations against `orders`, returning what each one returned.
+    async fn exercise(orders: &impl CollectionBackend<Document>) -> Vec<String> {
+        let mut results = Vec::new();
+        let ada = doc! { "_id": 1, "status": "paid", "total": 3 };
+        results.push(format!("{:?}", orders.insert_one(&ada, None).await));
+        let more = [
+            doc! { "_id": 2, "status": "due", "total": 5 },
+            doc! { "_id": 3, "status": "paid", "total": 8 },
+        ];
+        results.push(format!(
+            "{:?}",
+            orders
+                .insert_many(&more, None)
+                .await
+                .map(|r| r.inserted_ids.len())
+        ));
+        let found: Result<Vec<Document>> = match orders
+            .find(
+                Some(doc! { "status": "paid" }),
+                Some(FindOptions::builder().sort(doc! { "total": -1 }).build()),
+            )
+            .await
+        {
+            Ok(found) => found.try_collect().await,
+            Err(error) => Err(error),
+        };
+        results.push(format!("{:?}", found));
+        results.push(format!(
+            "{:?}",
+            orders
+                .update_one(
+                    doc! { "_id": 2 },
+                    doc! { "$set": { "status": "paid" } }.into(),
+                    None
+                )
+                .await
+        ));
+        results.push(format!(
+            "{:?}",
+            orders
+                .count_documents(Some(doc! { "status": "paid" }), None)
+                .await
+        ));
+        results.push(format!("{:?}", orders.distinct("total", None, None).await));
+        results.push(format!(
+            "{:?}",
+            orders.delete_one(doc! { "_id": 3 }, None).await
+        ));
+        // A duplicate `_id` fails with the server's write error.
+        results.push(format!(
+            "{:?}",
+            orders
+                .insert_one(&ada, None)
+                .await
+                .map_err(|error| match &*error.kind {
+                    ErrorKind::Write(WriteFailure::WriteError(error)) => error.code,
+                    _ => 0,
+                })
+        ));
+        results
+    }
+
+    #[tokio::test]
+    async fn recorded_operations_replay_the_same_results() {
+        let path = fixture("round-trip");
+        let recorder = Recorder::create(&path).unwrap();
+        let recorded =
+            exercise(&recorder.record(MemoryCollection::<Document>::new(), "app.orders")).await;
+        assert_eq!(recorded[4], "Ok(3)");
+        assert!(recorded.last().unwrap().contains("11000"));
+
+        let replay = Replay::open(&path).unwrap();
+        let replayed = exercise(&replay.collection("app.orders")).await;
+        assert_eq!(replayed, recorded);
+        assert_eq!(replay.remaining(), 0);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    async fn record_find_then_count(path: &Path, redact: bool) {
+        let recorder = Recorder::create(path).unwrap().redact(redact);
+        let orders = recorder.record::<Document, _>(
+            MemoryCollection::with_documents([doc! { "status": "paid" }]),
+            "app.orders",
+        );
+        orders
+            .find(Some(doc! { "status": "paid" }), None)
+            .await
+            .unwrap()
+            .try_collect::<Vec<Document>>()
+            .await
+            .unwrap();
+        orders.count_documents(None, None).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn requests_out_of_order_are_refused() {
+        let path = fixture("out-of-order");
+        record_find_then_count(&path, false).await;
+
+        let orders = Replay::open(&path)
+            .unwrap()
+            .collection::<Document>("app.orders");
+        let error = orders.count_documents(None, None).await.unwrap_err();
+        assert_eq!(
+            replay_reason(error),
+            "expected find on app.orders, got count_documents on app.orders"
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn extra_and_leftover_requests_are_reported() {
+        let path = fixture("extra");
+        record_find_then_count(&path, false).await;
+
+        let replay = Replay::open(&path).unwrap();
+        let orders = replay.collection::<Document>("app.orders");
+        orders
+            .find(Some(doc! { "status": "paid" }), None)
+            .await
+            .unwrap()
+            .try_collect::<Vec<Document>>()
+            .await
+            .unwrap();
+        assert_eq!(replay.remaining(), 1);
+        orders.count_documents(None, None).await.unwrap();
+        assert_eq!(replay.remaining(), 0);
+
+        let error = orders.count_documents(None, None).await.unwrap_err();
+        assert_eq!(
+            replay_reason(error),
+            "unexpected count_documents on app.orders, the fixture has no more interactions"
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn requests_with_other_values_are_refused() {
+        let path = fixture("values");
+        record_find_then_count(&path, false).await;
+        let recorded = std::fs::read_to_string(&path).unwrap();
+        let first: serde_json::Value =
+            serde_json::from_str(recorded.lines().next().unwrap()).unwrap();
+        assert_eq!(first["request"]["filter"]["status"], "paid");
+        assert_eq!(first["redacted"], false);
+
+        let orders = Replay::open(&path)
+            .unwrap()
+            .collection::<Document>("app.orders");
+        let error = match orders.find(Some(doc! { "status": "due" }), None).await {
+            Ok(_) => panic!("a find with another value was replayed"),
+            Err(error) => error,
+        };
+        assert_eq!(
+            replay_reason(error),
+            "find on app.orders does not match the recorded request"
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn redacted_fixtures_match_requests_by_shape() {
+        let path = fixture("redacted");
+        record_find_then_count(&path, true).await;
+        let recorded = std::fs::read_to_string(&path).unwrap();
+        let first: serde_json::Value =
+            serde_json::from_str(recorded.lines().next().unwrap()).unwrap();
+        assert_eq!(first["request"]["filter"]["status"], "?");
+        assert_eq!(first["redacted"], true);
+
+        let replay = Replay::open(&path).unwrap();
+        let orders = replay.collection::<Document>("app.orders");
+        let found: Vec<Document> = orders
+            .find(Some(doc! { "status": "due" }), None)
+            .await
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].get_str("status"), Ok("paid"));
+
+        let error = orders
+            .count_documents(Some(doc! { "total": 3 }), None)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            replay_reason(error),
+            "count_documents on app.orders does not match the recorded request"
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+}
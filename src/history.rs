@@ -0,0 +1,621 @@
+use std::borrow::Borrow;
+use std::sync::Arc;
+
+use futures_util::{StreamExt, TryStreamExt};
+use mongodb::bson::{doc, from_document, to_document, Bson, DateTime, Document};
+use mongodb::error::Result;
+use mongodb::options::{
+    FindOneAndDeleteOptions, FindOneAndReplaceOptions, FindOneAndUpdateOptions, FindOneOptions,
+    FindOptions, InsertOneOptions, ReturnDocument, UpdateModifications, UpdateOptions,
+};
+use mongodb::results::InsertOneResult;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::backend::CollectionBackend;
+use crate::error::{is_duplicate_key, Error};
+use crate::mongo_tracing::InstrumentedCollection;
+
+/// A prior version of a document, valid from `valid_from` until `valid_to`, when it was
+/// replaced, updated or deleted.
+///
+/// The first recorded version starts when its `ObjectId` `_id` was generated; it is unknown for
+/// other ids.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Version<T> {
+    pub document_id: Bson,
+    pub valid_from: Option<DateTime>,
+    pub valid_to: DateTime,
+    pub document: T,
+}
+
+/// A view of an [`InstrumentedCollection`] that keeps the pre-image of every document it
+/// replaces, updates or deletes in a sibling `<name>_history` collection.
+///
+/// The pre-image is read atomically with `find_one_and_*`; the history entry is written right
+/// after, so a write that fails in between is applied without being recorded. Writes made
+/// through the collection itself are not recorded.
+///
+/// Each version takes its interval from a head document in `<name>_history_heads`, with the
+/// document's `_id` and the start of its current version, moved forward with an update
+/// conditional on the start it read, so concurrent writes to a document get adjacent intervals
+/// that never overlap. A writer whose update finds the head moved retries from the new start;
+/// it only does so after another writer recorded. Racing writes are ordered by when they
+/// recorded, which may differ from when they were applied. A delete leaves a head without
+/// start, which [`HistoryCollection::insert_one`] restarts when the `_id` is reinserted; a
+/// document reinserted elsewhere gets an unknown start.
+pub struct HistoryCollection<T> {
+    inner: InstrumentedCollection<T>,
+    history: InstrumentedCollection<Document>,
+    heads: InstrumentedCollection<Document>,
+    stores: Option<Stores>,
+}
+
+/// Backends replacing the versions and heads collections.
+#[derive(Clone)]
+struct Stores {
+    versions: Arc<dyn CollectionBackend<Document>>,
+    heads: Arc<dyn CollectionBackend<Document>>,
+}
+
+impl<T> InstrumentedCollection<T> {
+    pub fn with_history(&self) -> HistoryCollection<T> {
+        let name = format!("{}_history", self.name());
+        HistoryCollection {
+            inner: self.clone(),
+            history: self.history_collection(&name),
+            heads: self.history_collection(&format!("{}_heads", name)),
+            stores: None,
+        }
+    }
+
+    fn history_collection(&self, name: &str) -> InstrumentedCollection<Document> {
+        let mut history = self.clone_with_type::<Document>();
+        history.inner = self.info.database.collection(name);
+        history.audit = None;
+        history.cache = None;
+        history
+    }
+}
+
+impl<T> Clone for HistoryCollection<T> {
+    fn clone(&self) -> Self {
+        HistoryCollection {
+            inner: self.inner.clone(),
+            history: self.history.clone(),
+            heads: self.heads.clone(),
+            stores: self.stores.clone(),
+        }
+    }
+}
+
+impl<T> HistoryCollection<T> {
+    /// Keeps the versions in `name` and the heads in `<name>_heads`, instead of
+    /// `<name>_history` and `<name>_history_heads`.
+    pub fn history_collection(mut self, name: &str) -> Self {
+        self.history = self.inner.history_collection(name);
+        self.heads = self.inner.history_collection(&format!("{}_heads", name));
+        self
+    }
+
+    /// Keeps the versions in `versions` and the heads in `heads` instead of collections of the
+    /// database.
+    #[cfg(test)]
+    pub(crate) fn history_store(
+        mut self,
+        versions: impl CollectionBackend<Document> + 'static,
+        heads: impl CollectionBackend<Document> + 'static,
+    ) -> Self {
+        self.stores = Some(Stores {
+            versions: Arc::new(versions),
+            heads: Arc::new(heads),
+        });
+        self
+    }
+
+    pub fn collection(&self) -> &InstrumentedCollection<T> {
+        &self.inner
+    }
+
+    pub fn history(&self) -> &InstrumentedCollection<Document> {
+        &self.history
+    }
+
+    fn store(&self) -> Arc<dyn CollectionBackend<Document>> {
+        match &self.stores {
+            Some(stores) => stores.versions.clone(),
+            None => Arc::new(self.history.clone()),
+        }
+    }
+
+    fn heads(&self) -> Arc<dyn CollectionBackend<Document>> {
+        match &self.stores {
+            Some(stores) => stores.heads.clone(),
+            None => Arc::new(self.heads.clone()),
+        }
+    }
+
+    /// Stores `previous` as the version that ended now, returning it as a `T`.
+    async fn record(&self, previous: Option<Document>) -> Result<Option<T>>
+    where
+        T: DeserializeOwned,
+    {
+        let previous = match previous {
+            Some(previous) => previous,
+            None => return Ok(None),
+        };
+        let id = previous.get("_id").cloned().unwrap_or(Bson::Null);
+        let (valid_from, valid_to) = self.claim(&id).await?;
+        self.store()
+            .insert_one(
+                &doc! {
+                    "documentId": id,
+                    "validFrom": valid_from.map_or(Bson::Null, Bson::DateTime),
+                    "validTo": valid_to,
+                    "document": previous.clone(),
+                },
+                None,
+            )
+            .await?;
+        Ok(Some(from_document(previous)?))
+    }
+
+    /// Ends the current version of the document with `_id` `id` now, returning its interval.
+    ///
+    /// The head is only moved if it still holds the start read, and only created if it is
+    /// missing, so of two writers reading the same start one retries with the other's end. A
+    /// head left by a delete gives the version an unknown start, as the reinsert was not seen.
+    async fn claim(&self, id: &Bson) -> Result<(Option<DateTime>, DateTime)> {
+        let heads = self.heads();
+        loop {
+            let now = DateTime::now();
+            let head = heads.find_one(Some(doc! { "_id": id }), None).await?;
+            let (filter, update, upsert) = match &head {
+                Some(head) => match (head.get("validFrom"), head.get("deletedAt")) {
+                    (Some(Bson::DateTime(valid_from)), _) => (
+                        doc! { "_id": id, "validFrom": valid_from },
+                        doc! { "$set": { "validFrom": now } },
+                        false,
+                    ),
+                    (None, Some(Bson::DateTime(deleted_at))) => (
+                        doc! { "_id": id, "deletedAt": deleted_at },
+                        doc! { "$set": { "validFrom": now }, "$unset": { "deletedAt": "" } },
+                        false,
+                    ),
+                    _ => {
+                        return Err(Error::History {
+                            reason: format!("the head of {} has no validFrom date", id),
+                        }
+                        .into())
+                    }
+                },
+                None => (
+                    doc! { "_id": id },
+                    doc! { "$setOnInsert": { "validFrom": now } },
+                    true,
+                ),
+            };
+            let claimed = heads
+                .update_one(
+                    filter,
+                    UpdateModifications::Document(update),
+                    Some(UpdateOptions::builder().upsert(upsert).build()),
+                )
+                .await;
+            match (head, claimed) {
+                (Some(head), Ok(claimed)) if claimed.matched_count == 1 => {
+                    return Ok((head.get_datetime("validFrom").ok().copied(), now))
+                }
+                (None, Ok(claimed)) if claimed.upserted_id.is_some() => {
+                    return Ok((self.first_start(id).await?, now))
+                }
+                // Another writer created the head first.
+                (None, Err(error)) if is_duplicate_key(&error) => {}
+                (_, Err(error)) => return Err(error),
+                _ => {}
+            }
+        }
+    }
+
+    /// Starts the life of the document with `_id` `id` now, after a delete or before its first
+    /// version.
+    async fn start(&self, id: &Bson) -> Result<()> {
+        self.heads()
+            .update_one(
+                doc! { "_id": id },
+                UpdateModifications::Document(doc! {
+                    "$set": { "validFrom": DateTime::now() },
+                    "$unset": { "deletedAt": "" },
+                }),
+                Some(UpdateOptions::builder().upsert(true).build()),
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Stores `previous` as the version a delete ended now, then leaves a head without start, so
+    /// that a reinsert does not begin at the delete.
+    async fn record_deletion(&self, previous: Option<Document>) -> Result<Option<T>>
+    where
+        T: DeserializeOwned,
+    {
+        let id = match previous.as_ref().and_then(|previous| previous.get("_id")) {
+            Some(id) => id.clone(),
+            None => return self.record(previous).await,
+        };
+        let previous = self.record(previous).await?;
+        self.heads()
+            .replace_one(
+                doc! { "_id": &id },
+                &doc! { "_id": &id, "deletedAt": DateTime::now() },
+                None,
+            )
+            .await?;
+        Ok(previous)
+    }
+
+    /// The start of the version recorded first through a head: the end of the last version
+    /// recorded without one, else the creation time `id` tells.
+    async fn first_start(&self, id: &Bson) -> Result<Option<DateTime>> {
+        let last = self
+            .store()
+            .find_one(
+                Some(doc! { "documentId": id }),
+                Some(
+                    FindOneOptions::builder()
+                        .sort(doc! { "validTo": -1 })
+                        .projection(doc! { "validTo": 1 })
+                        .build(),
+                ),
+            )
+            .await?;
+        Ok(last
+            .and_then(|last| last.get_datetime("validTo").ok().copied())
+            .or_else(|| created(id)))
+    }
+
+    /// Whether the current version of the document with `_id` `id` started after `at`, or it
+    /// was deleted before `at` without being reinserted through [`HistoryCollection::insert_one`].
+    async fn started_after(&self, id: &Bson, at: DateTime) -> Result<bool> {
+        let head = self
+            .heads()
+            .find_one(Some(doc! { "_id": id }), None)
+            .await?;
+        Ok(head.is_some_and(|head| {
+            match (
+                head.get_datetime("validFrom"),
+                head.get_datetime("deletedAt"),
+            ) {
+                (Ok(valid_from), _) => at < *valid_from,
+                (_, Ok(deleted_at)) => *deleted_at <= at,
+                _ => false,
+            }
+        }))
+    }
+
+    /// Replaces the matching document, returning its previous version.
+    pub async fn replace_one(
+        &self,
+        query: Document,
+        replacement: impl Borrow<T>,
+        options: impl Into<Option<FindOneAndReplaceOptions>>,
+    ) -> Result<Option<T>>
+    where
+        T: Serialize + DeserializeOwned,
+    {
+        let mut options = options.into().unwrap_or_default();
+        options.return_document = Some(ReturnDocument::Before);
+        let previous = self
+            .inner
+            .clone_with_type::<Document>()
+            .find_one_and_replace(query, to_document(replacement.borrow())?, options)
+            .await?;
+        self.record(previous).await
+    }
+
+    /// Updates the matching document, returning its previous version.
+    pub async fn update_one(
+        &self,
+        query: Document,
+        update: impl Into<UpdateModifications>,
+        options: impl Into<Option<FindOneAndUpdateOptions>>,
+    ) -> Result<Option<T>>
+    where
+        T: DeserializeOwned,
+    {
+        let mut options = options.into().unwrap_or_default();
+        options.return_document = Some(ReturnDocument::Before);
+        let previous = self
+            .inner
+            .clone_with_type::<Document>()
+            .find_one_and_update(query, update, options)
+            .await?;
+        self.record(previous).await
+    }
+
+    /// Inserts `doc`, starting its first version now; needed to reinsert a deleted `_id` whose
+    /// history should stay accurate.
+    pub async fn insert_one(
+        &self,
+        doc: impl Borrow<T>,
+        options: impl Into<Option<InsertOneOptions>>,
+    ) -> Result<InsertOneResult>
+    where
+        T: Serialize,
+    {
+        let inserted = self.inner.insert_one(doc, options).await?;
+        self.start(&inserted.inserted_id).await?;
+        Ok(inserted)
+    }
+
+    /// Deletes the matching document, returning its last version.
+    pub async fn delete_one(
+        &self,
+        query: Document,
+        options: impl Into<Option<FindOneAndDeleteOptions>>,
+    ) -> Result<Option<T>>
+    where
+        T: DeserializeOwned,
+    {
+        let previous = self
+            .inner
+            .clone_with_type::<Document>()
+            .find_one_and_delete(query, options)
+            .await?;
+        self.record_deletion(previous).await
+    }
+
+    /// Every recorded version of the document with `_id` `id`, oldest first.
+    pub async fn versions(&self, id: impl Into<Bson>) -> Result<Vec<Version<T>>>
+    where
+        T: DeserializeOwned + Unpin + Send + Sync,
+    {
+        self.store()
+            .find(
+                Some(doc! { "documentId": id.into() }),
+                Some(FindOptions::builder().sort(doc! { "validTo": 1 }).build()),
+            )
+            .await?
+            .map(|version| Ok(from_document(version?)?))
+            .try_collect()
+            .await
+    }
+
+    /// The document with `_id` `id` as it was at `at`: the version valid then, or the current
+    /// document when no version ended after `at`.
+    ///
+    /// `None` when the document did not exist yet at `at`, as far as known: before the start of
+    /// its first version, or before the generation of its `ObjectId` `_id`.
+    pub async fn find_as_of(&self, id: impl Into<Bson>, at: DateTime) -> Result<Option<T>>
+    where
+        T: DeserializeOwned + Unpin + Send + Sync,
+    {
+        let id = id.into();
+        let version = self
+            .store()
+            .find_one(
+                Some(doc! { "documentId": &id, "validTo": { "$gt": at } }),
+                Some(
+                    FindOneOptions::builder()
+                        .sort(doc! { "validTo": 1 })
+                        .build(),
+                ),
+            )
+            .await?
+            .map(from_document::<Version<T>>)
+            .transpose()?;
+        match version {
+            Some(version) if version.valid_from.is_some_and(|from| at < from) => Ok(None),
+            Some(version) => Ok(Some(version.document)),
+            None if created(&id).is_some_and(|created| at < created) => Ok(None),
+            None if self.started_after(&id, at).await? => Ok(None),
+            None => self.inner.find_one(doc! { "_id": id }, None).await,
+        }
+    }
+}
+
+/// When the document with `_id` `id` was created, if its id tells.
+fn created(id: &Bson) -> Option<DateTime> {
+    match id {
+        Bson::ObjectId(id) => Some(id.timestamp()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use mongodb::bson::oid::ObjectId;
+
+    use super::*;
+    use crate::memory::MemoryCollection;
+    use crate::testing::test_database;
+
+    fn products(
+        store: &MemoryCollection<Document>,
+        heads: &MemoryCollection<Document>,
+    ) -> HistoryCollection<Document> {
+        // Versions and heads only go to the stores.
+        test_database("app")
+            .collection::<Document>("products")
+            .with_history()
+            .history_store(store.clone(), heads.clone())
+    }
+
+    /// Records the product with `_id` `id` at prices 1 then 2, a few milliseconds apart.
+    async fn two_versions(products: &HistoryCollection<Document>, id: &ObjectId) {
+        for price in [1, 2] {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+            products
+                .record(Some(doc! { "_id": id, "price": price }))
+                .await
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn object_ids_tell_the_creation_time() {
+        let before = DateTime::from_millis(DateTime::now().timestamp_millis() / 1000 * 1000);
+        let generated = created(&Bson::ObjectId(ObjectId::new())).unwrap();
+        assert!(generated >= before);
+        assert!(generated <= DateTime::now());
+        assert_eq!(created(&Bson::Int32(1)), None);
+        assert_eq!(created(&Bson::String("sku-1".into())), None);
+    }
+
+    #[tokio::test]
+    async fn consecutive_versions_follow_each_other() {
+        let (store, heads) = (MemoryCollection::new(), MemoryCollection::new());
+        let products = products(&store, &heads);
+        let id = ObjectId::new();
+        two_versions(&products, &id).await;
+
+        let versions = products.versions(id).await.unwrap();
+        assert_eq!(versions.len(), 2);
+        assert_eq!(versions[0].valid_from, Some(id.timestamp()));
+        assert_eq!(versions[1].valid_from, Some(versions[0].valid_to));
+        assert!(versions[0].valid_to < versions[1].valid_to);
+        assert_eq!(versions[0].document.get_i32("price"), Ok(1));
+        assert_eq!(versions[1].document.get_i32("price"), Ok(2));
+        assert_eq!(store.documents().len(), 2);
+        let head = heads.documents().remove(0);
+        assert_eq!(head.get_datetime("validFrom"), Ok(&versions[1].valid_to));
+    }
+
+    #[tokio::test]
+    async fn concurrent_writes_get_adjacent_intervals() {
+        let (store, heads) = (MemoryCollection::new(), MemoryCollection::new());
+        let products = products(&store, &heads);
+        let id = ObjectId::new();
+        let writes = (0..32).map(|price| {
+            let products = products.clone();
+            tokio::spawn(async move {
+                products
+                    .record(Some(doc! { "_id": id, "price": price }))
+                    .await
+            })
+        });
+        for write in futures_util::future::join_all(writes).await {
+            write.unwrap().unwrap();
+        }
+
+        let versions = products.versions(id).await.unwrap();
+        assert_eq!(versions.len(), 32);
+        assert_eq!(versions[0].valid_from, Some(id.timestamp()));
+        for pair in versions.windows(2) {
+            assert_eq!(pair[1].valid_from, Some(pair[0].valid_to));
+        }
+    }
+
+    #[tokio::test]
+    async fn find_as_of_returns_the_version_valid_then() {
+        let (store, heads) = (MemoryCollection::new(), MemoryCollection::new());
+        let products = products(&store, &heads);
+        let id = ObjectId::new();
+        two_versions(&products, &id).await;
+        let versions = products.versions(id).await.unwrap();
+        let millis =
+            |at: DateTime, offset: i64| DateTime::from_millis(at.timestamp_millis() + offset);
+
+        let price = |found: Option<Document>| found.map(|found| found.get_i32("price").unwrap());
+        let before = millis(id.timestamp(), -1);
+        assert_eq!(price(products.find_as_of(id, before).await.unwrap()), None);
+        let first = millis(versions[0].valid_to, -1);
+        assert_eq!(
+            price(products.find_as_of(id, first).await.unwrap()),
+            Some(1)
+        );
+        let second = versions[0].valid_to;
+        assert_eq!(
+            price(products.find_as_of(id, second).await.unwrap()),
+            Some(2)
+        );
+    }
+
+    #[tokio::test]
+    async fn versions_continue_a_history_without_head() {
+        let (store, heads) = (MemoryCollection::new(), MemoryCollection::new());
+        let products = products(&store, &heads);
+        let ended = DateTime::from_millis(DateTime::now().timestamp_millis() - 1000);
+        let version = doc! {
+            "documentId": "sku-1",
+            "validFrom": Bson::Null,
+            "validTo": ended,
+            "document": { "_id": "sku-1" },
+        };
+        store.insert_one(&version, None).await.unwrap();
+
+        products
+            .record(Some(doc! { "_id": "sku-1", "price": 3 }))
+            .await
+            .unwrap();
+        let versions = products.versions("sku-1").await.unwrap();
+        assert_eq!(versions.len(), 2);
+        assert_eq!(versions[1].valid_from, Some(ended));
+    }
+
+    #[tokio::test]
+    async fn reinserted_documents_start_after_their_delete() {
+        let (store, heads) = (MemoryCollection::new(), MemoryCollection::new());
+        let products = products(&store, &heads);
+        let id = ObjectId::new();
+        two_versions(&products, &id).await;
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        products
+            .record_deletion(Some(doc! { "_id": id, "price": 3 }))
+            .await
+            .unwrap();
+        let deleted = products.versions(id).await.unwrap()[2].valid_to;
+        let millis =
+            |at: DateTime, offset: i64| DateTime::from_millis(at.timestamp_millis() + offset);
+        let price = |found: Option<Document>| found.map(|found| found.get_i32("price").unwrap());
+
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        let gap = millis(deleted, 2);
+        assert_eq!(price(products.find_as_of(id, gap).await.unwrap()), None);
+
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        products.start(&Bson::ObjectId(id)).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        products
+            .record(Some(doc! { "_id": id, "price": 4 }))
+            .await
+            .unwrap();
+
+        let versions = products.versions(id).await.unwrap();
+        assert_eq!(versions.len(), 4);
+        let reinserted = versions[3].valid_from.unwrap();
+        assert!(reinserted > gap);
+        assert_eq!(price(products.find_as_of(id, gap).await.unwrap()), None);
+        assert_eq!(
+            price(products.find_as_of(id, millis(deleted, -1)).await.unwrap()),
+            Some(3)
+        );
+        assert_eq!(
+            price(products.find_as_of(id, reinserted).await.unwrap()),
+            Some(4)
+        );
+    }
+
+    #[tokio::test]
+    async fn heads_without_a_start_fail_the_write() {
+        let (store, heads) = (MemoryCollection::new(), MemoryCollection::new());
+        let products = products(&store, &heads);
+        heads
+            .insert_one(&doc! { "_id": "sku-1", "validFrom": "yesterday" }, None)
+            .await
+            .unwrap();
+
+        let error = products
+            .record(Some(doc! { "_id": "sku-1", "price": 3 }))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            Error::from_mongo(&error),
+            Some(Error::History { .. })
+        ));
+        assert!(products.versions("sku-1").await.unwrap().is_empty());
+    }
+}
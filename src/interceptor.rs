@@ -0,0 +1,732 @@
+use std::fmt;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use mongodb::bson::{Bson, Document, RawDocumentBuf};
+use mongodb::change_stream::session::SessionChangeStream;
+use mongodb::change_stream::ChangeStream;
+use mongodb::error::{Error, Result};
+use mongodb::options::{
+    AggregateOptions, ChangeStreamOptions, CountOptions, CreateIndexOptions, DeleteOptions,
+    DistinctOptions, DropCollectionOptions, DropIndexOptions, EstimatedDocumentCountOptions,
+    FindOneAndDeleteOptions, FindOneAndReplaceOptions, FindOneAndUpdateOptions, FindOneOptions,
+    FindOptions, InsertManyOptions, InsertOneOptions, ListIndexesOptions, ReplaceOptions,
+    UpdateModifications, UpdateOptions,
+};
+use mongodb::results::{
+    CreateIndexResult, CreateIndexesResult, DeleteResult, InsertManyResult, InsertOneResult,
+    UpdateResult,
+};
+use mongodb::{Cursor, Namespace, SessionCursor};
+
+use crate::{pipeline, size};
+
+/// Hooks called around every operation of an [`InstrumentedCollection`], inside its span.
+///
+/// Returning an error from `before` aborts the operation before it reaches the driver; `on_error`
+/// is then called on the interceptors whose `before` already ran.
+///
+/// [`InstrumentedCollection`]: crate::InstrumentedCollection
+pub trait Interceptor: Send + Sync {
+    fn before(&self, _operation: &Operation<'_>) -> Result<()> {
+        Ok(())
+    }
+
+    fn after(&self, _operation: &Operation<'_>, _outcome: &Outcome) {}
+
+    fn on_error(&self, _operation: &Operation<'_>, _error: &Error) {}
+
+    /// Called when the [`InstrumentedCursor`] returned by a `find` or `aggregate` is dropped,
+    /// with what was read from it; `operation` is the one `after` received.
+    ///
+    /// [`InstrumentedCursor`]: crate::InstrumentedCursor
+    fn after_cursor(&self, _operation: &Operation<'_>, _read: &CursorRead) {}
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum OperationKind {
+    Find,
+    FindOne,
+    FindOneAndDelete,
+    FindOneAndUpdate,
+    FindOneAndReplace,
+    InsertOne,
+    InsertMany,
+    ReplaceOne,
+    UpdateOne,
+    UpdateMany,
+    DeleteOne,
+    DeleteMany,
+    Aggregate,
+    CountDocuments,
+    EstimatedDocumentCount,
+    Distinct,
+    Drop,
+    CreateIndex,
+    CreateIndexes,
+    DropIndex,
+    DropIndexes,
+    ListIndexes,
+    ListIndexNames,
+    Watch,
+    /// An `explain` of one of the other operations; nothing is written.
+    Explain,
+}
+
+impl OperationKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            OperationKind::Find => "find",
+            OperationKind::FindOne => "find_one",
+            OperationKind::FindOneAndDelete => "find_one_and_delete",
+            OperationKind::FindOneAndUpdate => "find_one_and_update",
+            OperationKind::FindOneAndReplace => "find_one_and_replace",
+            OperationKind::InsertOne => "insert_one",
+            OperationKind::InsertMany => "insert_many",
+            OperationKind::ReplaceOne => "replace_one",
+            OperationKind::UpdateOne => "update_one",
+            OperationKind::UpdateMany => "update_many",
+            OperationKind::DeleteOne => "delete_one",
+            OperationKind::DeleteMany => "delete_many",
+            OperationKind::Aggregate => "aggregate",
+            OperationKind::CountDocuments => "count_documents",
+            OperationKind::EstimatedDocumentCount => "estimated_document_count",
+            OperationKind::Distinct => "distinct",
+            OperationKind::Drop => "drop",
+            OperationKind::CreateIndex => "create_index",
+            OperationKind::CreateIndexes => "create_indexes",
+            OperationKind::DropIndex => "drop_index",
+            OperationKind::DropIndexes => "drop_indexes",
+            OperationKind::ListIndexes => "list_indexes",
+            OperationKind::ListIndexNames => "list_index_names",
+            OperationKind::Watch => "watch",
+            OperationKind::Explain => "explain",
+        }
+    }
+
+    /// Whether the operation modifies documents or the collection itself.
+    pub fn is_write(&self) -> bool {
+        !matches!(
+            self,
+            OperationKind::Find
+                | OperationKind::FindOne
+                | OperationKind::Aggregate
+                | OperationKind::CountDocuments
+                | OperationKind::EstimatedDocumentCount
+                | OperationKind::Distinct
+                | OperationKind::ListIndexes
+                | OperationKind::ListIndexNames
+                | OperationKind::Watch
+                | OperationKind::Explain
+        )
+    }
+}
+
+impl fmt::Display for OperationKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum OperationOptions<'a> {
+    Find(&'a FindOptions),
+    FindOne(&'a FindOneOptions),
+    FindOneAndDelete(&'a FindOneAndDeleteOptions),
+    FindOneAndUpdate(&'a FindOneAndUpdateOptions),
+    FindOneAndReplace(&'a FindOneAndReplaceOptions),
+    InsertOne(&'a InsertOneOptions),
+    InsertMany(&'a InsertManyOptions),
+    Replace(&'a ReplaceOptions),
+    Update(&'a UpdateOptions),
+    Delete(&'a DeleteOptions),
+    Aggregate(&'a AggregateOptions),
+    Count(&'a CountOptions),
+    EstimatedDocumentCount(&'a EstimatedDocumentCountOptions),
+    Distinct(&'a DistinctOptions),
+    DropCollection(&'a DropCollectionOptions),
+    CreateIndex(&'a CreateIndexOptions),
+    DropIndex(&'a DropIndexOptions),
+    ListIndexes(&'a ListIndexesOptions),
+    ChangeStream(&'a ChangeStreamOptions),
+}
+
+macro_rules! operation_options_from {
+    ($($options:ident => $variant:ident),* $(,)?) => {
+        $(
+            impl<'a> From<&'a $options> for OperationOptions<'a> {
+                fn from(options: &'a $options) -> Self {
+                    OperationOptions::$variant(options)
+                }
+            }
+        )*
+    };
+}
+
+operation_options_from!(
+    FindOptions => Find,
+    FindOneOptions => FindOne,
+    FindOneAndDeleteOptions => FindOneAndDelete,
+    FindOneAndUpdateOptions => FindOneAndUpdate,
+    FindOneAndReplaceOptions => FindOneAndReplace,
+    InsertOneOptions => InsertOne,
+    InsertManyOptions => InsertMany,
+    ReplaceOptions => Replace,
+    UpdateOptions => Update,
+    DeleteOptions => Delete,
+    AggregateOptions => Aggregate,
+    CountOptions => Count,
+    EstimatedDocumentCountOptions => EstimatedDocumentCount,
+    DistinctOptions => Distinct,
+    DropCollectionOptions => DropCollection,
+    CreateIndexOptions => CreateIndex,
+    DropIndexOptions => DropIndex,
+    ListIndexesOptions => ListIndexes,
+    ChangeStreamOptions => ChangeStream,
+);
+
+/// Describes the operation being intercepted; arguments are borrowed from the call.
+#[derive(Clone, Debug)]
+pub struct Operation<'a> {
+    kind: OperationKind,
+    namespace: Namespace,
+    filter: Option<&'a Document>,
+    requested_filter: Option<&'a Document>,
+    update: Option<&'a UpdateModifications>,
+    pipeline: Option<&'a [Document]>,
+    options: Option<OperationOptions<'a>>,
+    in_session: bool,
+    documents_size: usize,
+    started: Instant,
+}
+
+impl<'a> Operation<'a> {
+    pub(crate) fn new(kind: OperationKind, namespace: Namespace) -> Self {
+        Operation {
+            kind,
+            namespace,
+            filter: None,
+            requested_filter: None,
+            update: None,
+            pipeline: None,
+            options: None,
+            in_session: false,
+            documents_size: 0,
+            started: Instant::now(),
+        }
+    }
+
+    pub(crate) fn with_filter(mut self, filter: Option<&'a Document>) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Sets the filter the caller passed, when a view restricted it before sending.
+    pub(crate) fn with_requested_filter(mut self, filter: Option<&'a Document>) -> Self {
+        self.requested_filter = filter;
+        self
+    }
+
+    pub(crate) fn with_update(mut self, update: &'a UpdateModifications) -> Self {
+        self.update = Some(update);
+        self
+    }
+
+    pub(crate) fn with_pipeline(mut self, pipeline: &'a [Document]) -> Self {
+        self.pipeline = Some(pipeline);
+        self
+    }
+
+    pub(crate) fn with_options<O>(mut self, options: Option<&'a O>) -> Self
+    where
+        &'a O: Into<OperationOptions<'a>>,
+    {
+        self.options = options.map(Into::into);
+        self
+    }
+
+    pub(crate) fn started_at(mut self, started: Instant) -> Self {
+        self.started = started;
+        self
+    }
+
+    pub(crate) fn in_session(mut self) -> Self {
+        self.in_session = true;
+        self
+    }
+
+    /// Sets the BSON size of the documents inserted or of the replacement.
+    pub(crate) fn with_documents_size(mut self, size: usize) -> Self {
+        self.documents_size = size;
+        self
+    }
+
+    pub fn kind(&self) -> OperationKind {
+        self.kind
+    }
+
+    pub fn namespace(&self) -> &Namespace {
+        &self.namespace
+    }
+
+    pub fn filter(&self) -> Option<&'a Document> {
+        self.filter
+    }
+
+    /// The filter as the caller passed it, before a [`TenantCollection`] or
+    /// [`SoftDeleteCollection`] restricted it; [`Operation::filter`] is what the server gets.
+    ///
+    /// [`TenantCollection`]: crate::tenant::TenantCollection
+    /// [`SoftDeleteCollection`]: crate::soft_delete::SoftDeleteCollection
+    pub fn requested_filter(&self) -> Option<&'a Document> {
+        self.requested_filter.or(self.filter)
+    }
+
+    pub fn update(&self) -> Option<&'a UpdateModifications> {
+        self.update
+    }
+
+    pub fn pipeline(&self) -> Option<&'a [Document]> {
+        self.pipeline
+    }
+
+    pub fn options(&self) -> Option<OperationOptions<'a>> {
+        self.options
+    }
+
+    /// Whether the operation writes: its kind is a write, or it is an aggregation ending in
+    /// `$out` or `$merge`.
+    pub fn is_write(&self) -> bool {
+        self.kind.is_write() || self.pipeline.is_some_and(pipeline::is_write)
+    }
+
+    pub fn is_in_session(&self) -> bool {
+        self.in_session
+    }
+
+    /// The BSON size in bytes of the filter, update, pipeline and documents sent.
+    pub fn request_size(&self) -> usize {
+        let update = match self.update {
+            Some(UpdateModifications::Document(update)) => size::of(update),
+            Some(UpdateModifications::Pipeline(pipeline)) => pipeline.iter().map(size::of).sum(),
+            _ => 0,
+        };
+        let pipeline: usize = self.pipeline.unwrap_or_default().iter().map(size::of).sum();
+        self.filter.map_or(0, size::of) + update + pipeline + self.documents_size
+    }
+
+    pub(crate) fn started(&self) -> Instant {
+        self.started
+    }
+
+    /// Time since the operation was issued, read it in `after`/`on_error` for its latency.
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+}
+
+/// What was read from a cursor by the time it was dropped.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CursorRead {
+    pub documents: u64,
+    /// Batches received from the server, the one returned with the cursor included. Estimated
+    /// from the times the cursor had to wait, which also happens for other reasons.
+    pub batches: u64,
+}
+
+/// A summary of a successful operation's result.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Outcome {
+    /// `id` is the `_id` of the document returned, when it has one.
+    Found {
+        found: bool,
+        id: Option<Bson>,
+    },
+    Cursor,
+    Inserted {
+        ids: Vec<Bson>,
+    },
+    Updated {
+        matched: u64,
+        modified: u64,
+        upserted_id: Option<Bson>,
+    },
+    Deleted {
+        count: u64,
+    },
+    Count(u64),
+    Values(usize),
+    Indexes(Vec<String>),
+    Done,
+}
+
+pub(crate) trait ToOutcome {
+    fn to_outcome(&self) -> Outcome;
+}
+
+impl ToOutcome for Option<RawDocumentBuf> {
+    fn to_outcome(&self) -> Outcome {
+        Outcome::Found {
+            found: self.is_some(),
+            id: self
+                .as_ref()
+                .and_then(|document| document.get("_id").ok().flatten())
+                .and_then(|id| Bson::try_from(id.to_raw_bson()).ok()),
+        }
+    }
+}
+
+impl ToOutcome for Option<Document> {
+    fn to_outcome(&self) -> Outcome {
+        Outcome::Found {
+            found: self.is_some(),
+            id: self
+                .as_ref()
+                .and_then(|document| document.get("_id"))
+                .cloned(),
+        }
+    }
+}
+
+impl<T> ToOutcome for Cursor<T> {
+    fn to_outcome(&self) -> Outcome {
+        Outcome::Cursor
+    }
+}
+
+impl<T> ToOutcome for SessionCursor<T> {
+    fn to_outcome(&self) -> Outcome {
+        Outcome::Cursor
+    }
+}
+
+impl<T> ToOutcome for ChangeStream<T>
+where
+    T: serde::de::DeserializeOwned + Unpin + Send + Sync,
+{
+    fn to_outcome(&self) -> Outcome {
+        Outcome::Cursor
+    }
+}
+
+impl<T> ToOutcome for SessionChangeStream<T>
+where
+    T: serde::de::DeserializeOwned + Unpin + Send + Sync,
+{
+    fn to_outcome(&self) -> Outcome {
+        Outcome::Cursor
+    }
+}
+
+impl ToOutcome for InsertOneResult {
+    fn to_outcome(&self) -> Outcome {
+        Outcome::Inserted {
+            ids: vec![self.inserted_id.clone()],
+        }
+    }
+}
+
+impl ToOutcome for InsertManyResult {
+    fn to_outcome(&self) -> Outcome {
+        let mut ids: Vec<(&usize, &Bson)> = self.inserted_ids.iter().collect();
+        ids.sort_by_key(|(index, _)| **index);
+        Outcome::Inserted {
+            ids: ids.into_iter().map(|(_, id)| id.clone()).collect(),
+        }
+    }
+}
+
+impl ToOutcome for UpdateResult {
+    fn to_outcome(&self) -> Outcome {
+        Outcome::Updated {
+            matched: self.matched_count,
+            modified: self.modified_count,
+            upserted_id: self.upserted_id.clone(),
+        }
+    }
+}
+
+impl ToOutcome for DeleteResult {
+    fn to_outcome(&self) -> Outcome {
+        Outcome::Deleted {
+            count: self.deleted_count,
+        }
+    }
+}
+
+impl ToOutcome for u64 {
+    fn to_outcome(&self) -> Outcome {
+        Outcome::Count(*self)
+    }
+}
+
+impl ToOutcome for Vec<Bson> {
+    fn to_outcome(&self) -> Outcome {
+        Outcome::Values(self.len())
+    }
+}
+
+impl ToOutcome for Vec<String> {
+    fn to_outcome(&self) -> Outcome {
+        Outcome::Indexes(self.clone())
+    }
+}
+
+impl ToOutcome for CreateIndexResult {
+    fn to_outcome(&self) -> Outcome {
+        Outcome::Indexes(vec![self.index_name.clone()])
+    }
+}
+
+impl ToOutcome for CreateIndexesResult {
+    fn to_outcome(&self) -> Outcome {
+        Outcome::Indexes(self.index_names.clone())
+    }
+}
+
+impl ToOutcome for Document {
+    fn to_outcome(&self) -> Outcome {
+        Outcome::Done
+    }
+}
+
+impl ToOutcome for () {
+    fn to_outcome(&self) -> Outcome {
+        Outcome::Done
+    }
+}
+
+#[derive(Clone, Default)]
+pub(crate) struct InterceptorChain {
+    interceptors: Vec<Arc<dyn Interceptor>>,
+}
+
+impl InterceptorChain {
+    pub(crate) fn push(&mut self, interceptor: Arc<dyn Interceptor>) {
+        self.interceptors.push(interceptor);
+    }
+
+    pub(crate) async fn run<R: ToOutcome>(
+        &self,
+        operation: &Operation<'_>,
+        call: impl Future<Output = Result<R>>,
+    ) -> Result<R> {
+        let span = tracing::Span::current();
+        if !span.is_disabled() {
+            span.record("db.request.size", operation.request_size() as u64);
+        }
+        self.before(operation)?;
+        match call.await {
+            Ok(result) => {
+                let outcome = result.to_outcome();
+                for interceptor in &self.interceptors {
+                    interceptor.after(operation, &outcome);
+                }
+                Ok(result)
+            }
+            Err(error) => {
+                self.on_error(operation, &error);
+                Err(error)
+            }
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.interceptors.is_empty()
+    }
+
+    pub(crate) fn after_cursor(&self, operation: &Operation<'_>, read: &CursorRead) {
+        for interceptor in &self.interceptors {
+            interceptor.after_cursor(operation, read);
+        }
+    }
+
+    /// Runs every `before` hook in order; when one fails, only the interceptors before it are
+    /// told of the error, the others never saw the operation.
+    fn before(&self, operation: &Operation<'_>) -> Result<()> {
+        for (index, interceptor) in self.interceptors.iter().enumerate() {
+            if let Err(error) = interceptor.before(operation) {
+                for interceptor in &self.interceptors[..index] {
+                    interceptor.on_error(operation, &error);
+                }
+                return Err(error);
+            }
+        }
+        Ok(())
+    }
+
+    fn on_error(&self, operation: &Operation<'_>, error: &Error) {
+        for interceptor in &self.interceptors {
+            interceptor.on_error(operation, error);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Mutex;
+
+    use mongodb::bson::doc;
+    use tracing::Instrument;
+
+    use super::*;
+    use crate::testing::Spans;
+
+    /// Logs each hook called as `<name>.<hook>`, refusing operations in `before` if `refuse`.
+    struct Logged {
+        name: &'static str,
+        refuse: bool,
+        log: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl Interceptor for Logged {
+        fn before(&self, _operation: &Operation<'_>) -> Result<()> {
+            self.log
+                .lock()
+                .unwrap()
+                .push(format!("{}.before", self.name));
+            match self.refuse {
+                true => Err(crate::Error::Unsupported {
+                    feature: "refused".to_string(),
+                }
+                .into()),
+                false => Ok(()),
+            }
+        }
+
+        fn after(&self, _operation: &Operation<'_>, outcome: &Outcome) {
+            self.log
+                .lock()
+                .unwrap()
+                .push(format!("{}.after {:?}", self.name, outcome));
+        }
+
+        fn on_error(&self, _operation: &Operation<'_>, _error: &Error) {
+            self.log
+                .lock()
+                .unwrap()
+                .push(format!("{}.on_error", self.name));
+        }
+    }
+
+    fn chain(refusing: Option<&'static str>) -> (InterceptorChain, Arc<Mutex<Vec<String>>>) {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut chain = InterceptorChain::default();
+        for name in ["a", "b", "c"] {
+            chain.push(Arc::new(Logged {
+                name,
+                refuse: refusing == Some(name),
+                log: log.clone(),
+            }));
+        }
+        (chain, log)
+    }
+
+    fn namespace() -> Namespace {
+        Namespace {
+            db: "app".to_string(),
+            coll: "users".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn refused_operations_never_reach_the_driver() {
+        let (chain, log) = chain(Some("b"));
+        let operation = Operation::new(OperationKind::DeleteOne, namespace());
+        let called = AtomicBool::new(false);
+
+        let result = chain
+            .run(&operation, async {
+                called.store(true, Ordering::SeqCst);
+                Ok(0_u64)
+            })
+            .await;
+
+        assert!(result.is_err());
+        assert!(!called.load(Ordering::SeqCst));
+        assert_eq!(*log.lock().unwrap(), ["a.before", "b.before", "a.on_error"]);
+    }
+
+    #[tokio::test]
+    async fn driver_errors_reach_every_interceptor() {
+        let (chain, log) = chain(None);
+        let operation = Operation::new(OperationKind::UpdateOne, namespace());
+
+        let result: Result<u64> = chain
+            .run(&operation, async {
+                Err(crate::error::write_error(11000, "duplicate".to_string()))
+            })
+            .await;
+
+        assert!(result.is_err());
+        assert_eq!(
+            *log.lock().unwrap(),
+            [
+                "a.before",
+                "b.before",
+                "c.before",
+                "a.on_error",
+                "b.on_error",
+                "c.on_error"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn after_receives_the_outcome() {
+        let (chain, log) = chain(None);
+        let operation = Operation::new(OperationKind::FindOne, namespace());
+
+        let found = chain
+            .run(&operation, async {
+                Ok(Some(doc! { "_id": 7, "name": "ada" }))
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(found, Some(doc! { "_id": 7, "name": "ada" }));
+        let outcome = Outcome::Found {
+            found: true,
+            id: Some(Bson::Int32(7)),
+        };
+        let log = log.lock().unwrap();
+        assert_eq!(log.len(), 6);
+        assert_eq!(
+            log[3..],
+            [
+                format!("a.after {:?}", outcome),
+                format!("b.after {:?}", outcome),
+                format!("c.after {:?}", outcome)
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn the_request_size_is_recorded_on_the_span() {
+        let spans = Spans::default();
+        let _default = tracing::subscriber::set_default(spans.clone());
+        let filter = doc! { "name": "ada" };
+        let update = UpdateModifications::Document(doc! { "$set": { "active": true } });
+        let operation = Operation::new(OperationKind::UpdateOne, namespace())
+            .with_filter(Some(&filter))
+            .with_update(&update)
+            .with_documents_size(10);
+        let span = tracing::info_span!("update_one", db.request.size = tracing::field::Empty);
+
+        InterceptorChain::default()
+            .run(&operation, async { Ok(0_u64) })
+            .instrument(span)
+            .await
+            .unwrap();
+
+        let expected = size::of(&filter) + size::of(&doc! { "$set": { "active": true } }) + 10;
+        assert_eq!(operation.request_size(), expected);
+        let spans = spans.spans.lock().unwrap();
+        assert_eq!(
+            spans[0].1["db.request.size"].as_deref(),
+            Some(expected.to_string().as_str())
+        );
+    }
+}
@@ -1,3 +1,37 @@
+pub mod audit;
+pub mod backend;
+pub mod cache;
+mod cursor;
+mod database;
+mod diff;
+mod error;
+pub mod explain;
+pub mod fixture;
+pub mod history;
+pub mod interceptor;
+pub mod memory;
+pub mod migrations;
 mod mongo_tracing;
+pub mod pipeline;
+pub mod policy;
+mod projection;
+mod query;
+pub mod results;
+#[cfg(feature = "schema")]
+pub mod schema;
+#[cfg(feature = "tower")]
+pub mod service;
+mod shape;
+mod size;
+pub mod soft_delete;
+pub mod stats;
+pub mod tenant;
+#[cfg(test)]
+mod testing;
+pub mod typed;
+pub mod version;
 
-pub use mongo_tracing::InstrumentedCollectionExt;
+pub use cursor::{DecodeFailure, InstrumentedCursor, InstrumentedSessionCursor, LenientCursor};
+pub use database::InstrumentedDatabase;
+pub use error::Error;
+pub use mongo_tracing::{InstrumentedCollection, InstrumentedCollectionExt};
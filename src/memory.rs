@@ -0,0 +1,1311 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use async_trait::async_trait;
+use futures_util::stream::{self, BoxStream};
+use futures_util::StreamExt;
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{doc, from_document, to_document, Bson, DateTime, Document};
+use mongodb::error::Result;
+use mongodb::options::{
+    CountOptions, DeleteOptions, DistinctOptions, FindOneOptions, FindOptions, InsertManyOptions,
+    InsertOneOptions, ReplaceOptions, UpdateModifications, UpdateOptions,
+};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::backend::CollectionBackend;
+use crate::error::{duplicate_key, write_error, Error};
+use crate::results::{DeleteResult, InsertManyResult, InsertOneResult, UpdateResult};
+
+/// An in-memory [`CollectionBackend`] for tests that should not need a server.
+///
+/// Queries support field equality, dotted paths into documents and arrays, the comparison,
+/// `$in`/`$nin`, `$exists`, `$not`, `$size`, `$all`, `$elemMatch`, `$and`/`$or`/`$nor`
+/// operators; updates support `$set`, `$setOnInsert`, `$unset`, `$inc`, `$mul`, `$min`,
+/// `$max`, `$rename`, `$currentDate` (as a date), `$push`/`$addToSet` (with `$each` but no other
+/// modifier), `$pull` and `$pop`, and upserts. Find options honour `sort`, `skip`, `limit` and
+/// `projection`; other options are ignored. Anything else fails with [`Error::Unsupported`].
+/// Changing a document's `_id` fails with the server's write error. Clones share their
+/// documents.
+pub struct MemoryCollection<T> {
+    documents: Arc<Mutex<Vec<Document>>>,
+    document_type: PhantomData<fn() -> T>,
+}
+
+impl<T> MemoryCollection<T> {
+    pub fn new() -> Self {
+        MemoryCollection::with_documents(Vec::new())
+    }
+
+    pub fn with_documents(documents: impl IntoIterator<Item = Document>) -> Self {
+        MemoryCollection {
+            documents: Arc::new(Mutex::new(documents.into_iter().map(with_id).collect())),
+            document_type: PhantomData,
+        }
+    }
+
+    /// A snapshot of the stored documents, in insertion order.
+    pub fn documents(&self) -> Vec<Document> {
+        self.lock().clone()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<Document>> {
+        self.documents
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn select(
+        &self,
+        filter: Option<&Document>,
+        sort: Option<&Document>,
+        skip: Option<u64>,
+        limit: Option<i64>,
+        projection: Option<&Document>,
+    ) -> Result<Vec<Document>> {
+        let mut selected = Vec::new();
+        for document in self.lock().iter() {
+            if filter.map_or(Ok(true), |filter| matches(document, filter))? {
+                selected.push(document.clone());
+            }
+        }
+        if let Some(sort) = sort {
+            selected.sort_by(|a, b| sort_order(a, b, sort));
+        }
+        let skip = skip.unwrap_or(0) as usize;
+        let limit = match limit.map(i64::unsigned_abs) {
+            Some(0) | None => usize::MAX,
+            Some(limit) => limit as usize,
+        };
+        selected
+            .into_iter()
+            .skip(skip)
+            .take(limit)
+            .map(|document| match projection {
+                Some(projection) => project(&document, projection),
+                None => Ok(document),
+            })
+            .collect()
+    }
+
+    fn update(
+        &self,
+        query: &Document,
+        update: &UpdateModifications,
+        upsert: bool,
+        many: bool,
+    ) -> Result<UpdateResult> {
+        let update = match update {
+            UpdateModifications::Document(update) => update,
+            _ => return Err(unsupported("pipeline updates")),
+        };
+        if update.keys().any(|key| !key.starts_with('$')) {
+            return Err(unsupported("update documents without operators"));
+        }
+        let mut documents = self.lock();
+        let mut result = UpdateResult {
+            matched_count: 0,
+            modified_count: 0,
+            upserted_id: None,
+        };
+        for document in documents.iter_mut() {
+            if !matches(document, query)? {
+                continue;
+            }
+            let mut updated = document.clone();
+            apply_update(&mut updated, update, false)?;
+            if !bson_eq(
+                updated.get("_id").unwrap_or(&Bson::Null),
+                document.get("_id").unwrap_or(&Bson::Null),
+            ) {
+                return Err(immutable_id());
+            }
+            result.matched_count += 1;
+            if updated != *document {
+                *document = updated;
+                result.modified_count += 1;
+            }
+            if !many {
+                break;
+            }
+        }
+        if result.matched_count == 0 && upsert {
+            let mut document = seed(query)?;
+            apply_update(&mut document, update, true)?;
+            let document = with_id(document);
+            result.upserted_id = Some(insert(&mut documents, document)?);
+        }
+        Ok(result)
+    }
+
+    fn delete(&self, query: &Document, many: bool) -> Result<DeleteResult> {
+        let mut documents = self.lock();
+        let mut deleted = Vec::new();
+        for (index, document) in documents.iter().enumerate() {
+            if matches(document, query)? {
+                deleted.push(index);
+                if !many {
+                    break;
+                }
+            }
+        }
+        for index in deleted.iter().rev() {
+            documents.remove(*index);
+        }
+        Ok(DeleteResult {
+            deleted_count: deleted.len() as u64,
+        })
+    }
+}
+
+impl<T> Default for MemoryCollection<T> {
+    fn default() -> Self {
+        MemoryCollection::new()
+    }
+}
+
+impl<T> Clone for MemoryCollection<T> {
+    fn clone(&self) -> Self {
+        MemoryCollection {
+            documents: self.documents.clone(),
+            document_type: PhantomData,
+        }
+    }
+}
+
+#[async_trait]
+impl<T> CollectionBackend<T> for MemoryCollection<T>
+where
+    T: Serialize + DeserializeOwned + Unpin + Send + Sync + 'static,
+{
+    async fn find(
+        &self,
+        filter: Option<Document>,
+        options: Option<FindOptions>,
+    ) -> Result<BoxStream<'static, Result<T>>> {
+        let options = options.unwrap_or_default();
+        let found = self.select(
+            filter.as_ref(),
+            options.sort.as_ref(),
+            options.skip,
+            options.limit,
+            options.projection.as_ref(),
+        )?;
+        let decoded: Vec<Result<T>> = found
+            .into_iter()
+            .map(|document| from_document(document).map_err(Into::into))
+            .collect();
+        Ok(stream::iter(decoded).boxed())
+    }
+
+    async fn find_one(
+        &self,
+        filter: Option<Document>,
+        options: Option<FindOneOptions>,
+    ) -> Result<Option<T>> {
+        let options = options.unwrap_or_default();
+        let found = self.select(
+            filter.as_ref(),
+            options.sort.as_ref(),
+            options.skip,
+            Some(1),
+            options.projection.as_ref(),
+        )?;
+        match found.into_iter().next() {
+            Some(document) => Ok(Some(from_document(document)?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn insert_one(
+        &self,
+        document: &T,
+        _options: Option<InsertOneOptions>,
+    ) -> Result<InsertOneResult> {
+        let document = with_id(to_document(document)?);
+        Ok(InsertOneResult {
+            inserted_id: insert(&mut self.lock(), document)?,
+        })
+    }
+
+    async fn insert_many(
+        &self,
+        documents: &[T],
+        _options: Option<InsertManyOptions>,
+    ) -> Result<InsertManyResult> {
+        let mut stored = self.lock();
+        let mut inserted_ids = HashMap::new();
+        for (index, document) in documents.iter().enumerate() {
+            let document = with_id(to_document(document)?);
+            inserted_ids.insert(index, insert(&mut stored, document)?);
+        }
+        Ok(InsertManyResult { inserted_ids })
+    }
+
+    async fn replace_one(
+        &self,
+        query: Document,
+        replacement: &T,
+        options: Option<ReplaceOptions>,
+    ) -> Result<UpdateResult> {
+        let replacement = to_document(replacement)?;
+        if replacement.keys().any(|key| key.starts_with('$')) {
+            return Err(unsupported("replacement documents with operators"));
+        }
+        let mut documents = self.lock();
+        let mut result = UpdateResult {
+            matched_count: 0,
+            modified_count: 0,
+            upserted_id: None,
+        };
+        for document in documents.iter_mut() {
+            if !matches(document, &query)? {
+                continue;
+            }
+            let id = document.get("_id").cloned().unwrap_or(Bson::Null);
+            if replacement
+                .get("_id")
+                .is_some_and(|replaced| !bson_eq(replaced, &id))
+            {
+                return Err(immutable_id());
+            }
+            let mut replaced = doc! { "_id": id };
+            replaced.extend(replacement.clone());
+            result.matched_count = 1;
+            if replaced != *document {
+                *document = replaced;
+                result.modified_count = 1;
+            }
+            return Ok(result);
+        }
+        if options.and_then(|options| options.upsert).unwrap_or(false) {
+            let mut document = replacement;
+            if let Some(id) = seed(&query)?.get("_id") {
+                document.entry("_id".to_string()).or_insert(id.clone());
+            }
+            result.upserted_id = Some(insert(&mut documents, with_id(document))?);
+        }
+        Ok(result)
+    }
+
+    async fn update_one(
+        &self,
+        query: Document,
+        update: UpdateModifications,
+        options: Option<UpdateOptions>,
+    ) -> Result<UpdateResult> {
+        let upsert = options.and_then(|options| options.upsert).unwrap_or(false);
+        self.update(&query, &update, upsert, false)
+    }
+
+    async fn update_many(
+        &self,
+        query: Document,
+        update: UpdateModifications,
+        options: Option<UpdateOptions>,
+    ) -> Result<UpdateResult> {
+        let upsert = options.and_then(|options| options.upsert).unwrap_or(false);
+        self.update(&query, &update, upsert, true)
+    }
+
+    async fn delete_one(
+        &self,
+        query: Document,
+        _options: Option<DeleteOptions>,
+    ) -> Result<DeleteResult> {
+        self.delete(&query, false)
+    }
+
+    async fn delete_many(
+        &self,
+        query: Document,
+        _options: Option<DeleteOptions>,
+    ) -> Result<DeleteResult> {
+        self.delete(&query, true)
+    }
+
+    async fn count_documents(
+        &self,
+        filter: Option<Document>,
+        options: Option<CountOptions>,
+    ) -> Result<u64> {
+        let options = options.unwrap_or_default();
+        let limit = options.limit.map(|limit| limit as i64);
+        let found = self.select(filter.as_ref(), None, options.skip, limit, None)?;
+        Ok(found.len() as u64)
+    }
+
+    async fn distinct(
+        &self,
+        field_name: &str,
+        filter: Option<Document>,
+        _options: Option<DistinctOptions>,
+    ) -> Result<Vec<Bson>> {
+        let found = self.select(filter.as_ref(), None, None, None, None)?;
+        let mut values: Vec<Bson> = Vec::new();
+        for document in &found {
+            for value in lookup(document, field_name) {
+                let items = match value {
+                    Bson::Array(items) => items.iter().collect(),
+                    value => vec![value],
+                };
+                for item in items {
+                    if !values.iter().any(|known| bson_eq(known, item)) {
+                        values.push(item.clone());
+                    }
+                }
+            }
+        }
+        Ok(values)
+    }
+}
+
+fn unsupported(feature: impl Into<String>) -> mongodb::error::Error {
+    Error::Unsupported {
+        feature: feature.into(),
+    }
+    .into()
+}
+
+/// The server's error for an update or replacement changing a document's `_id`.
+fn immutable_id() -> mongodb::error::Error {
+    write_error(
+        66,
+        "Performing an update on the path '_id' would modify the immutable field '_id'".to_string(),
+    )
+}
+
+fn with_id(document: Document) -> Document {
+    if document.contains_key("_id") {
+        return document;
+    }
+    let mut identified = doc! { "_id": ObjectId::new() };
+    identified.extend(document);
+    identified
+}
+
+fn insert(documents: &mut Vec<Document>, document: Document) -> Result<Bson> {
+    let id = document.get("_id").cloned().unwrap_or(Bson::Null);
+    if documents
+        .iter()
+        .any(|stored| stored.get("_id").is_some_and(|stored| bson_eq(stored, &id)))
+    {
+        return Err(duplicate_key(&id));
+    }
+    documents.push(document);
+    Ok(id)
+}
+
+fn matches(document: &Document, filter: &Document) -> Result<bool> {
+    for (key, condition) in filter {
+        let matched = match key.as_str() {
+            "$and" | "$or" | "$nor" => {
+                let mut results = Vec::new();
+                for clause in clauses(key, condition)? {
+                    results.push(matches(document, clause)?);
+                }
+                match key.as_str() {
+                    "$and" => results.iter().all(|matched| *matched),
+                    "$or" => results.iter().any(|matched| *matched),
+                    _ => !results.iter().any(|matched| *matched),
+                }
+            }
+            operator if operator.starts_with('$') => {
+                return Err(unsupported(format!("the {} query operator", operator)))
+            }
+            path => matches_field(&lookup(document, path), condition)?,
+        };
+        if !matched {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+fn clauses<'a>(operator: &str, condition: &'a Bson) -> Result<Vec<&'a Document>> {
+    match condition {
+        Bson::Array(clauses) => clauses
+            .iter()
+            .map(|clause| {
+                clause.as_document().ok_or_else(|| {
+                    unsupported(format!("{} clauses other than documents", operator))
+                })
+            })
+            .collect(),
+        _ => Err(unsupported(format!("{} without an array", operator))),
+    }
+}
+
+/// Whether `condition` is a document of operators, like `{ $gt: 1 }`, rather than a value;
+/// mixing operators and fields is refused as the server does.
+fn is_operator_document(condition: &Bson) -> Result<bool> {
+    let condition = match condition {
+        Bson::Document(condition) if !condition.is_empty() => condition,
+        _ => return Ok(false),
+    };
+    let operators = condition.keys().filter(|key| key.starts_with('$')).count();
+    match operators {
+        0 => Ok(false),
+        operators if operators == condition.len() => Ok(true),
+        _ => Err(unsupported("conditions mixing operators and fields")),
+    }
+}
+
+fn matches_field(values: &[&Bson], condition: &Bson) -> Result<bool> {
+    let operators = match condition {
+        Bson::Document(operators) if is_operator_document(condition)? => operators,
+        Bson::RegularExpression(_) => return Err(unsupported("regular expressions")),
+        _ => return Ok(equals_any(values, condition)),
+    };
+    for (operator, argument) in operators {
+        let matched = match operator.as_str() {
+            "$eq" => equals_any(values, argument),
+            "$ne" => !equals_any(values, argument),
+            "$gt" => any_element(values, |value| {
+                compare(value, argument) == Some(Ordering::Greater)
+            }),
+            "$gte" => any_element(values, |value| {
+                matches!(
+                    compare(value, argument),
+                    Some(Ordering::Greater | Ordering::Equal)
+                )
+            }),
+            "$lt" => any_element(values, |value| {
+                compare(value, argument) == Some(Ordering::Less)
+            }),
+            "$lte" => any_element(values, |value| {
+                matches!(
+                    compare(value, argument),
+                    Some(Ordering::Less | Ordering::Equal)
+                )
+            }),
+            "$in" => array(operator, argument)?
+                .iter()
+                .any(|candidate| equals_any(values, candidate)),
+            "$nin" => !array(operator, argument)?
+                .iter()
+                .any(|candidate| equals_any(values, candidate)),
+            "$all" => array(operator, argument)?
+                .iter()
+                .all(|candidate| equals_any(values, candidate)),
+            "$exists" => truthy(argument) != values.is_empty(),
+            "$not" if is_operator_document(argument)? => !matches_field(values, argument)?,
+            "$size" => values.iter().any(|value| {
+                matches!(value, Bson::Array(items) if number(argument) == Some(items.len() as f64))
+            }),
+            "$elemMatch" => {
+                let mut matched = false;
+                for value in values {
+                    if let Bson::Array(items) = value {
+                        for item in items {
+                            matched = matched || element_matches(item, argument)?;
+                        }
+                    }
+                }
+                matched
+            }
+            operator => return Err(unsupported(format!("the {} query operator", operator))),
+        };
+        if !matched {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+fn element_matches(item: &Bson, condition: &Bson) -> Result<bool> {
+    match (item, condition) {
+        (_, condition) if is_operator_document(condition)? => matches_field(&[item], condition),
+        (Bson::Document(item), Bson::Document(condition)) => matches(item, condition),
+        _ => Ok(false),
+    }
+}
+
+fn array<'a>(operator: &str, argument: &'a Bson) -> Result<&'a Vec<Bson>> {
+    match argument {
+        Bson::Array(items) => Ok(items),
+        _ => Err(unsupported(format!("{} without an array", operator))),
+    }
+}
+
+fn equals_any(values: &[&Bson], target: &Bson) -> bool {
+    (*target == Bson::Null && values.is_empty())
+        || values.iter().any(|value| bson_eq(value, target))
+        || any_element(values, |value| bson_eq(value, target))
+}
+
+/// Whether `predicate` holds for one of `values` or for an element of one of them.
+fn any_element(values: &[&Bson], predicate: impl Fn(&Bson) -> bool) -> bool {
+    values.iter().any(|value| match value {
+        Bson::Array(items) => items.iter().any(&predicate),
+        value => predicate(value),
+    })
+}
+
+/// The values at a dotted `path`, descending into every document of the arrays on the way.
+fn lookup<'a>(document: &'a Document, path: &str) -> Vec<&'a Bson> {
+    let segments: Vec<&str> = path.split('.').collect();
+    let mut values = Vec::new();
+    if let Some(value) = document.get(segments[0]) {
+        lookup_in(value, &segments[1..], &mut values);
+    }
+    values
+}
+
+fn lookup_in<'a>(value: &'a Bson, segments: &[&str], values: &mut Vec<&'a Bson>) {
+    let (segment, rest) = match segments.split_first() {
+        Some(split) => split,
+        None => return values.push(value),
+    };
+    match value {
+        Bson::Document(document) => {
+            if let Some(value) = document.get(*segment) {
+                lookup_in(value, rest, values);
+            }
+        }
+        Bson::Array(items) => match segment.parse::<usize>() {
+            Ok(index) => {
+                if let Some(item) = items.get(index) {
+                    lookup_in(item, rest, values);
+                }
+            }
+            Err(_) => {
+                for item in items.iter().filter(|item| item.as_document().is_some()) {
+                    lookup_in(item, segments, values);
+                }
+            }
+        },
+        _ => {}
+    }
+}
+
+fn get_path<'a>(document: &'a Document, path: &str) -> Option<&'a Bson> {
+    let mut segments = path.split('.');
+    let mut value = document.get(segments.next()?)?;
+    for segment in segments {
+        value = match value {
+            Bson::Document(document) => document.get(segment)?,
+            Bson::Array(items) => items.get(segment.parse::<usize>().ok()?)?,
+            _ => return None,
+        };
+    }
+    Some(value)
+}
+
+fn set_path(document: &mut Document, path: &str, value: Bson) -> Result<()> {
+    match path.split_once('.') {
+        None => {
+            document.insert(path, value);
+            Ok(())
+        }
+        Some((field, rest)) => {
+            let child = document
+                .entry(field.to_string())
+                .or_insert_with(|| Bson::Document(Document::new()));
+            set_in(child, rest, value)
+        }
+    }
+}
+
+fn set_in(target: &mut Bson, path: &str, value: Bson) -> Result<()> {
+    match target {
+        Bson::Document(document) => set_path(document, path, value),
+        Bson::Array(items) => {
+            let (segment, rest) = match path.split_once('.') {
+                Some((segment, rest)) => (segment, Some(rest)),
+                None => (path, None),
+            };
+            let index: usize = segment
+                .parse()
+                .map_err(|_| unsupported(format!("the array field path {}", path)))?;
+            if items.len() <= index {
+                items.resize(index + 1, Bson::Null);
+            }
+            match rest {
+                Some(rest) => set_in(&mut items[index], rest, value),
+                None => {
+                    items[index] = value;
+                    Ok(())
+                }
+            }
+        }
+        _ => Err(unsupported(format!("setting {} inside a scalar", path))),
+    }
+}
+
+fn remove_path(document: &mut Document, path: &str) -> Option<Bson> {
+    match path.split_once('.') {
+        None => document.remove(path),
+        Some((field, rest)) => match document.get_mut(field)? {
+            Bson::Document(document) => remove_path(document, rest),
+            _ => None,
+        },
+    }
+}
+
+/// Removes the field at `path`; an array element named by its index is set to null instead,
+/// keeping the positions of the others.
+fn unset_path(document: &mut Document, path: &str) {
+    match path.split_once('.') {
+        None => {
+            document.remove(path);
+        }
+        Some((field, rest)) => {
+            if let Some(child) = document.get_mut(field) {
+                unset_in(child, rest);
+            }
+        }
+    }
+}
+
+fn unset_in(target: &mut Bson, path: &str) {
+    match target {
+        Bson::Document(document) => unset_path(document, path),
+        Bson::Array(items) => {
+            let (segment, rest) = match path.split_once('.') {
+                Some((segment, rest)) => (segment, Some(rest)),
+                None => (path, None),
+            };
+            let item = match segment
+                .parse::<usize>()
+                .ok()
+                .and_then(|index| items.get_mut(index))
+            {
+                Some(item) => item,
+                None => return,
+            };
+            match rest {
+                Some(rest) => unset_in(item, rest),
+                None => *item = Bson::Null,
+            }
+        }
+        _ => {}
+    }
+}
+
+fn project(document: &Document, projection: &Document) -> Result<Document> {
+    if projection
+        .values()
+        .any(|value| value.as_document().is_some())
+    {
+        return Err(unsupported("projection operators"));
+    }
+    let including = projection
+        .iter()
+        .any(|(path, value)| path != "_id" && truthy(value));
+    if !including {
+        let mut projected = document.clone();
+        for (path, _) in projection.iter().filter(|(_, value)| !truthy(value)) {
+            remove_path(&mut projected, path);
+        }
+        return Ok(projected);
+    }
+    let mut projected = Document::new();
+    if projection.get("_id").is_none_or(truthy) {
+        if let Some(id) = document.get("_id") {
+            projected.insert("_id", id.clone());
+        }
+    }
+    for (path, _) in projection
+        .iter()
+        .filter(|(path, value)| *path != "_id" && truthy(value))
+    {
+        if let Some(value) = get_path(document, path) {
+            set_path(&mut projected, path, value.clone())?;
+        }
+    }
+    Ok(projected)
+}
+
+/// The document an upsert starts from: the equality conditions of its filter.
+fn seed(filter: &Document) -> Result<Document> {
+    let mut document = Document::new();
+    for (key, condition) in filter {
+        let value = match condition {
+            _ if key == "$and" => {
+                for clause in clauses(key, condition)? {
+                    document.extend(seed(clause)?);
+                }
+                continue;
+            }
+            _ if key.starts_with('$') => continue,
+            Bson::Document(operators) if is_operator_document(condition)? => {
+                match operators.get("$eq") {
+                    Some(value) => value.clone(),
+                    None => continue,
+                }
+            }
+            value => value.clone(),
+        };
+        set_path(&mut document, key, value)?;
+    }
+    Ok(document)
+}
+
+fn apply_update(document: &mut Document, update: &Document, inserting: bool) -> Result<()> {
+    for (operator, fields) in update {
+        let fields = fields
+            .as_document()
+            .ok_or_else(|| unsupported(format!("{} without a document", operator)))?;
+        for (path, value) in fields {
+            let current = get_path(document, path).cloned();
+            match operator.as_str() {
+                "$set" => set_path(document, path, value.clone())?,
+                "$setOnInsert" if inserting => set_path(document, path, value.clone())?,
+                "$setOnInsert" => {}
+                "$unset" => unset_path(document, path),
+                "$inc" => {
+                    let current = current.unwrap_or(Bson::Int32(0));
+                    set_path(document, path, arithmetic(&current, value, false)?)?
+                }
+                "$mul" => {
+                    let current = current.unwrap_or(Bson::Int32(0));
+                    set_path(document, path, arithmetic(&current, value, true)?)?
+                }
+                "$min" | "$max" => {
+                    let wanted = if operator == "$min" {
+                        Ordering::Less
+                    } else {
+                        Ordering::Greater
+                    };
+                    let replace = current
+                        .as_ref()
+                        .is_none_or(|current| compare(value, current) == Some(wanted));
+                    if replace {
+                        set_path(document, path, value.clone())?;
+                    }
+                }
+                "$rename" => {
+                    let target = value
+                        .as_str()
+                        .ok_or_else(|| unsupported("$rename to a non-string path"))?;
+                    if let Some(renamed) = remove_path(document, path) {
+                        set_path(document, target, renamed)?;
+                    }
+                }
+                "$currentDate" => {
+                    let date = match value {
+                        Bson::Boolean(true) => true,
+                        Bson::Document(kind) => kind == &doc! { "$type": "date" },
+                        _ => false,
+                    };
+                    if !date {
+                        return Err(unsupported(format!("$currentDate of {}", value)));
+                    }
+                    set_path(document, path, Bson::DateTime(DateTime::now()))?
+                }
+                "$push" | "$addToSet" => {
+                    let added = match value {
+                        Bson::Document(modifiers)
+                            if modifiers.keys().any(|key| key.starts_with('$')) =>
+                        {
+                            if let Some(modifier) = modifiers.keys().find(|key| *key != "$each") {
+                                return Err(unsupported(format!("{} with {}", operator, modifier)));
+                            }
+                            array("$each", modifiers.get("$each").unwrap_or(&Bson::Null))?.clone()
+                        }
+                        value => vec![value.clone()],
+                    };
+                    let mut items = match current {
+                        None => Vec::new(),
+                        Some(Bson::Array(items)) => items,
+                        Some(_) => return Err(unsupported(format!("{} to a non-array", operator))),
+                    };
+                    for item in added {
+                        if operator == "$push" || !items.iter().any(|known| bson_eq(known, &item)) {
+                            items.push(item);
+                        }
+                    }
+                    set_path(document, path, Bson::Array(items))?
+                }
+                "$pull" => {
+                    if let Some(Bson::Array(items)) = current {
+                        let mut kept = Vec::new();
+                        for item in items {
+                            if !pulled(&item, value)? {
+                                kept.push(item);
+                            }
+                        }
+                        set_path(document, path, Bson::Array(kept))?
+                    }
+                }
+                "$pop" => {
+                    if let Some(Bson::Array(mut items)) = current {
+                        if number(value).is_some_and(|first| first < 0.0) {
+                            if !items.is_empty() {
+                                items.remove(0);
+                            }
+                        } else {
+                            items.pop();
+                        }
+                        set_path(document, path, Bson::Array(items))?
+                    }
+                }
+                operator => return Err(unsupported(format!("the {} update operator", operator))),
+            }
+        }
+    }
+    Ok(())
+}
+
+fn pulled(item: &Bson, condition: &Bson) -> Result<bool> {
+    match (item, condition) {
+        (_, condition) if is_operator_document(condition)? => matches_field(&[item], condition),
+        (Bson::Document(item), Bson::Document(condition)) => matches(item, condition),
+        _ => Ok(bson_eq(item, condition)),
+    }
+}
+
+fn arithmetic(current: &Bson, operand: &Bson, multiply: bool) -> Result<Bson> {
+    let overflow = || unsupported("integer overflow in an update");
+    match (current, operand) {
+        (Bson::Int32(a), Bson::Int32(b)) => {
+            let result = if multiply {
+                a.checked_mul(*b)
+            } else {
+                a.checked_add(*b)
+            };
+            match result {
+                Some(result) => Ok(Bson::Int32(result)),
+                None => arithmetic(&Bson::Int64(i64::from(*a)), operand, multiply),
+            }
+        }
+        _ => match (
+            integer(current),
+            integer(operand),
+            number(current),
+            number(operand),
+        ) {
+            (Some(a), Some(b), _, _) => {
+                let result = if multiply {
+                    a.checked_mul(b)
+                } else {
+                    a.checked_add(b)
+                };
+                result.map(Bson::Int64).ok_or_else(overflow)
+            }
+            (_, _, Some(a), Some(b)) => Ok(Bson::Double(if multiply { a * b } else { a + b })),
+            _ => Err(unsupported("arithmetic on a non-numeric field")),
+        },
+    }
+}
+
+fn truthy(value: &Bson) -> bool {
+    match value {
+        Bson::Boolean(value) => *value,
+        Bson::Null | Bson::Undefined => false,
+        value => number(value).is_none_or(|number| number != 0.0),
+    }
+}
+
+fn integer(value: &Bson) -> Option<i64> {
+    match value {
+        Bson::Int32(value) => Some(i64::from(*value)),
+        Bson::Int64(value) => Some(*value),
+        _ => None,
+    }
+}
+
+fn number(value: &Bson) -> Option<f64> {
+    match value {
+        Bson::Int32(value) => Some(f64::from(*value)),
+        Bson::Int64(value) => Some(*value as f64),
+        Bson::Double(value) => Some(*value),
+        _ => None,
+    }
+}
+
+/// Orders two values of the same type, numbers of any type together.
+fn compare(a: &Bson, b: &Bson) -> Option<Ordering> {
+    if let (Some(a), Some(b)) = (integer(a), integer(b)) {
+        return Some(a.cmp(&b));
+    }
+    if let (Some(a), Some(b)) = (number(a), number(b)) {
+        return a.partial_cmp(&b);
+    }
+    match (a, b) {
+        (Bson::String(a), Bson::String(b)) => Some(a.cmp(b)),
+        (Bson::DateTime(a), Bson::DateTime(b)) => Some(a.cmp(b)),
+        (Bson::ObjectId(a), Bson::ObjectId(b)) => Some(a.bytes().cmp(&b.bytes())),
+        (Bson::Boolean(a), Bson::Boolean(b)) => Some(a.cmp(b)),
+        (Bson::Timestamp(a), Bson::Timestamp(b)) => {
+            Some((a.time, a.increment).cmp(&(b.time, b.increment)))
+        }
+        _ => None,
+    }
+}
+
+fn bson_eq(a: &Bson, b: &Bson) -> bool {
+    match (a, b) {
+        (Bson::Document(a), Bson::Document(b)) => {
+            a.len() == b.len()
+                && a.iter()
+                    .zip(b)
+                    .all(|((ka, va), (kb, vb))| ka == kb && bson_eq(va, vb))
+        }
+        (Bson::Array(a), Bson::Array(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| bson_eq(a, b))
+        }
+        _ if number(a).is_some() && number(b).is_some() => compare(a, b) == Some(Ordering::Equal),
+        _ => a == b,
+    }
+}
+
+/// The server's ordering of BSON types.
+fn type_rank(value: Option<&Bson>) -> u8 {
+    match value {
+        Some(Bson::MinKey) => 0,
+        None | Some(Bson::Null) | Some(Bson::Undefined) => 1,
+        Some(Bson::Int32(_) | Bson::Int64(_) | Bson::Double(_) | Bson::Decimal128(_)) => 2,
+        Some(Bson::String(_) | Bson::Symbol(_)) => 3,
+        Some(Bson::Document(_)) => 4,
+        Some(Bson::Array(_)) => 5,
+        Some(Bson::Binary(_)) => 6,
+        Some(Bson::ObjectId(_)) => 7,
+        Some(Bson::Boolean(_)) => 8,
+        Some(Bson::DateTime(_)) => 9,
+        Some(Bson::Timestamp(_)) => 10,
+        Some(Bson::RegularExpression(_)) => 11,
+        Some(Bson::MaxKey) => 13,
+        Some(_) => 12,
+    }
+}
+
+/// Orders two values of possibly different types as the server does.
+fn order(a: Option<&Bson>, b: Option<&Bson>) -> Ordering {
+    type_rank(a).cmp(&type_rank(b)).then_with(|| match (a, b) {
+        (Some(a), Some(b)) => compare(a, b).unwrap_or(Ordering::Equal),
+        _ => Ordering::Equal,
+    })
+}
+
+/// The value a document sorts by: an array sorts by its smallest element ascending and its
+/// largest descending, and an empty array, `None` here, before null and missing fields.
+fn sort_key(value: Option<&Bson>, descending: bool) -> Option<Option<&Bson>> {
+    match value {
+        Some(Bson::Array(items)) if descending => items
+            .iter()
+            .max_by(|a, b| order(Some(a), Some(b)))
+            .map(Some),
+        Some(Bson::Array(items)) => items
+            .iter()
+            .min_by(|a, b| order(Some(a), Some(b)))
+            .map(Some),
+        value => Some(value),
+    }
+}
+
+fn sort_order(a: &Document, b: &Document, sort: &Document) -> Ordering {
+    let rank = |key: Option<Option<&Bson>>| key.map_or(1, |value| 2 * type_rank(value));
+    for (path, direction) in sort {
+        let descending = number(direction).is_some_and(|direction| direction < 0.0);
+        let a = sort_key(get_path(a, path), descending);
+        let b = sort_key(get_path(b, path), descending);
+        let order = rank(a).cmp(&rank(b)).then_with(|| match (a, b) {
+            (Some(a), Some(b)) => order(a, b),
+            _ => Ordering::Equal,
+        });
+        let order = if descending { order.reverse() } else { order };
+        if order != Ordering::Equal {
+            return order;
+        }
+    }
+    Ordering::Equal
+}
+
+#[cfg(test)]
+mod tests {
+    use mongodb::error::{ErrorKind, WriteFailure};
+
+    use super::*;
+
+    fn selected(documents: Vec<Document>, filter: Document) -> Vec<i32> {
+        let collection = MemoryCollection::<Document>::with_documents(documents);
+        collection
+            .select(Some(&filter), None, None, None, None)
+            .unwrap()
+            .iter()
+            .map(|document| document.get_i32("_id").unwrap())
+            .collect()
+    }
+
+    fn updated(mut document: Document, update: Document) -> Document {
+        apply_update(&mut document, &update, false).unwrap();
+        document
+    }
+
+    #[test]
+    fn null_matches_missing_fields() {
+        let documents = || {
+            vec![
+                doc! { "_id": 1, "a": null },
+                doc! { "_id": 2 },
+                doc! { "_id": 3, "a": 1 },
+                doc! { "_id": 4, "a": [1, null] },
+                doc! { "_id": 5, "a": { "b": null } },
+            ]
+        };
+        let cases = [
+            (doc! { "a": null }, vec![1, 2, 4]),
+            (doc! { "a": { "$eq": null } }, vec![1, 2, 4]),
+            (doc! { "a": { "$ne": null } }, vec![3, 5]),
+            (doc! { "a": { "$exists": false } }, vec![2]),
+            (doc! { "a": { "$exists": true } }, vec![1, 3, 4, 5]),
+            (doc! { "a.b": null }, vec![1, 2, 3, 4, 5]),
+            (doc! { "a.b": { "$exists": true } }, vec![5]),
+            (doc! { "a": { "$in": [null, 1] } }, vec![1, 2, 3, 4]),
+        ];
+        for (filter, expected) in cases {
+            assert_eq!(selected(documents(), filter.clone()), expected, "{filter}");
+        }
+    }
+
+    #[test]
+    fn arrays_are_traversed() {
+        let documents = || {
+            vec![
+                doc! { "_id": 1, "tags": ["x", "y"], "scores": [0, 5] },
+                doc! { "_id": 2, "tags": ["y"], "scores": [2] },
+                doc! {
+                    "_id": 3,
+                    "items": [{ "sku": "a", "qty": 1 }, { "sku": "b", "qty": 5 }],
+                },
+            ]
+        };
+        let cases = [
+            (doc! { "tags": "x" }, vec![1]),
+            (doc! { "tags": ["y"] }, vec![2]),
+            (doc! { "tags.0": "y" }, vec![2]),
+            (doc! { "tags": { "$all": ["x", "y"] } }, vec![1]),
+            (doc! { "tags": { "$size": 1 } }, vec![2]),
+            (doc! { "scores": { "$gt": 1, "$lt": 3 } }, vec![1, 2]),
+            (
+                doc! { "scores": { "$elemMatch": { "$gt": 1, "$lt": 3 } } },
+                vec![2],
+            ),
+            (doc! { "items.sku": "b" }, vec![3]),
+            (doc! { "items.qty": { "$gt": 4 } }, vec![3]),
+            (
+                doc! { "items": { "$elemMatch": { "sku": "a", "qty": 5 } } },
+                vec![],
+            ),
+            (
+                doc! { "items": { "$elemMatch": { "sku": "b", "qty": { "$gte": 5 } } } },
+                vec![3],
+            ),
+        ];
+        for (filter, expected) in cases {
+            assert_eq!(selected(documents(), filter.clone()), expected, "{filter}");
+        }
+    }
+
+    #[test]
+    fn conditions_mixing_operators_and_fields_are_refused() {
+        let document = doc! { "a": { "b": 1, "$gt": 0 } };
+        assert!(matches(&document, &doc! { "a": { "$gt": 0, "b": 1 } }).is_err());
+        assert!(matches(&document, &doc! { "a": { "b": 1, "$gt": 0 } }).is_err());
+        assert!(matches(&document, &doc! { "a": { "b": 1 } }).is_ok_and(|matched| !matched));
+    }
+
+    #[test]
+    fn upserts_start_from_the_filter() {
+        let cases = [
+            (
+                doc! { "_id": 1, "name": "ada", "age": { "$gt": 30 } },
+                doc! { "$set": { "city": "paris" } },
+                doc! { "_id": 1, "name": "ada", "city": "paris" },
+            ),
+            (
+                doc! { "_id": 1, "$and": [{ "name": { "$eq": "ada" } }, { "a.b": 2 }] },
+                doc! { "$setOnInsert": { "new": true } },
+                doc! { "_id": 1, "name": "ada", "a": { "b": 2 }, "new": true },
+            ),
+            (
+                doc! { "_id": 1, "$or": [{ "name": "ada" }] },
+                doc! { "$inc": { "count": 1 } },
+                doc! { "_id": 1, "count": 1 },
+            ),
+        ];
+        for (filter, update, expected) in cases {
+            let collection = MemoryCollection::<Document>::new();
+            let result = collection
+                .update(&filter, &UpdateModifications::Document(update), true, false)
+                .unwrap();
+            assert_eq!(result.upserted_id, Some(Bson::Int32(1)));
+            assert_eq!(collection.documents(), vec![expected]);
+        }
+    }
+
+    #[test]
+    fn set_on_insert_is_skipped_for_matches() {
+        let collection = MemoryCollection::<Document>::with_documents([doc! { "_id": 1 }]);
+        let update = doc! { "$setOnInsert": { "new": true }, "$set": { "seen": true } };
+        collection
+            .update(
+                &doc! { "_id": 1 },
+                &UpdateModifications::Document(update),
+                true,
+                false,
+            )
+            .unwrap();
+        assert_eq!(
+            collection.documents(),
+            vec![doc! { "_id": 1, "seen": true }]
+        );
+    }
+
+    #[test]
+    fn updates_cannot_change_the_id() {
+        let collection = MemoryCollection::<Document>::with_documents([doc! { "_id": 1 }]);
+        let update = |update: Document| {
+            collection.update(
+                &doc! {},
+                &UpdateModifications::Document(update),
+                false,
+                true,
+            )
+        };
+        let error = update(doc! { "$set": { "_id": 2 } }).unwrap_err();
+        match *error.kind {
+            ErrorKind::Write(WriteFailure::WriteError(error)) => assert_eq!(error.code, 66),
+            kind => panic!("unexpected error: {kind:?}"),
+        }
+        assert!(update(doc! { "$unset": { "_id": "" } }).is_err());
+        assert!(update(doc! { "$set": { "_id": 1, "a": 1 } }).is_ok());
+        assert_eq!(collection.documents(), vec![doc! { "_id": 1, "a": 1 }]);
+    }
+
+    #[test]
+    fn updates_apply_their_operators() {
+        let cases = [
+            (
+                doc! { "a": 1, "b": 2 },
+                doc! { "$unset": { "b": "" }, "$inc": { "a": 2, "c": 1 } },
+                doc! { "a": 3, "c": 1 },
+            ),
+            (
+                doc! { "a": { "b": 1 } },
+                doc! { "$set": { "a.c": 2 }, "$mul": { "a.b": 3 } },
+                doc! { "a": { "b": 3, "c": 2 } },
+            ),
+            (
+                doc! { "a": 5 },
+                doc! { "$min": { "a": 3 }, "$max": { "b": 1 } },
+                doc! { "a": 3, "b": 1 },
+            ),
+            (
+                doc! { "a": 1 },
+                doc! { "$rename": { "a": "b.c" } },
+                doc! { "b": { "c": 1 } },
+            ),
+            (
+                doc! { "tags": ["x"] },
+                doc! { "$push": { "tags": { "$each": ["y", "x"] } } },
+                doc! { "tags": ["x", "y", "x"] },
+            ),
+            (
+                doc! { "tags": ["x"] },
+                doc! { "$addToSet": { "tags": { "$each": ["y", "x"] } } },
+                doc! { "tags": ["x", "y"] },
+            ),
+            (
+                doc! { "scores": [1, 5, 9] },
+                doc! { "$pull": { "scores": { "$gt": 4 } } },
+                doc! { "scores": [1] },
+            ),
+            (
+                doc! { "scores": [1, 5, 9] },
+                doc! { "$pop": { "scores": -1 } },
+                doc! { "scores": [5, 9] },
+            ),
+        ];
+        for (document, update, expected) in cases {
+            assert_eq!(updated(document, update.clone()), expected, "{update}");
+        }
+    }
+
+    #[test]
+    fn unsupported_update_modifiers_are_rejected() {
+        let cases = [
+            doc! { "$push": { "tags": { "$each": ["x"], "$slice": 2 } } },
+            doc! { "$push": { "tags": { "$each": ["x"], "$sort": 1 } } },
+            doc! { "$push": { "tags": { "$each": ["x"], "$position": 0 } } },
+            doc! { "$push": { "tags": { "$slice": 2 } } },
+            doc! { "$addToSet": { "tags": { "$each": ["x"], "$sort": 1 } } },
+            doc! { "$currentDate": { "at": { "$type": "timestamp" } } },
+            doc! { "$currentDate": { "at": false } },
+        ];
+        for update in cases {
+            let mut document = doc! { "tags": [] };
+            let error = apply_update(&mut document, &update, false).unwrap_err();
+            assert!(
+                matches!(Error::from_mongo(&error), Some(Error::Unsupported { .. })),
+                "{update}"
+            );
+        }
+        let mut document = doc! {};
+        let update = doc! { "$currentDate": { "a": true, "b": { "$type": "date" } } };
+        apply_update(&mut document, &update, false).unwrap();
+        assert!(document.get_datetime("a").is_ok() && document.get_datetime("b").is_ok());
+    }
+
+    #[test]
+    fn unset_reaches_into_arrays() {
+        let cases = [
+            (
+                doc! { "a": [1, 2, 3] },
+                doc! { "$unset": { "a.1": "" } },
+                doc! { "a": [1, null, 3] },
+            ),
+            (
+                doc! { "a": [{ "b": 1, "c": 1 }, { "b": 2 }] },
+                doc! { "$unset": { "a.0.b": "" } },
+                doc! { "a": [{ "c": 1 }, { "b": 2 }] },
+            ),
+            (
+                doc! { "a": [1] },
+                doc! { "$unset": { "a.5": "", "x.y": "" } },
+                doc! { "a": [1] },
+            ),
+            (
+                doc! { "a": { "b": [[1, 2]] } },
+                doc! { "$unset": { "a.b.0.0": "" } },
+                doc! { "a": { "b": [[null, 2]] } },
+            ),
+        ];
+        for (document, update, expected) in cases {
+            assert_eq!(updated(document, update.clone()), expected, "{update}");
+        }
+    }
+
+    #[test]
+    fn sorts_across_types() {
+        let documents = vec![
+            doc! { "_id": 1, "v": true },
+            doc! { "_id": 2, "v": "b" },
+            doc! { "_id": 3, "v": 2.5 },
+            doc! { "_id": 4 },
+            doc! { "_id": 5, "v": { "a": 1 } },
+            doc! { "_id": 6, "v": 1 },
+            doc! { "_id": 7, "v": null },
+            doc! { "_id": 8, "v": [] },
+            doc! { "_id": 9, "v": ["a", 3] },
+            doc! { "_id": 10, "v": "a" },
+        ];
+        let sorted = |direction: i32| {
+            let collection = MemoryCollection::<Document>::with_documents(documents.clone());
+            let sort = doc! { "v": direction, "_id": 1 };
+            collection
+                .select(None, Some(&sort), None, None, None)
+                .unwrap()
+                .iter()
+                .map(|document| document.get_i32("_id").unwrap())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(sorted(1), vec![8, 4, 7, 6, 3, 9, 10, 2, 5, 1]);
+        assert_eq!(sorted(-1), vec![1, 5, 2, 9, 10, 3, 6, 4, 7, 8]);
+    }
+}
@@ -0,0 +1,596 @@
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use futures_util::TryStreamExt;
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{doc, from_document, to_document, DateTime, Document};
+use mongodb::error::Result;
+use mongodb::options::{FindOptions, UpdateModifications, UpdateOptions};
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use crate::backend::CollectionBackend;
+use crate::database::InstrumentedDatabase;
+use crate::error::{is_duplicate_key, Error};
+use crate::mongo_tracing::InstrumentedCollection;
+
+const LOCK_ID: &str = "__lock__";
+
+/// A versioned schema or data migration.
+///
+/// Migrations run in ascending `version` order; `down` must undo what `up` did.
+#[async_trait]
+pub trait Migration: Send + Sync {
+    fn version(&self) -> u64;
+    fn name(&self) -> &str;
+    async fn up(&self, ctx: &mut MigrationContext<'_>) -> Result<()>;
+    async fn down(&self, ctx: &mut MigrationContext<'_>) -> Result<()>;
+}
+
+/// Handed to each migration step; report the documents a step changed with
+/// [`MigrationContext::touched`] so they end up on its span and in the history.
+pub struct MigrationContext<'a> {
+    database: &'a InstrumentedDatabase,
+    documents_touched: u64,
+}
+
+impl<'a> MigrationContext<'a> {
+    pub fn database(&self) -> &InstrumentedDatabase {
+        self.database
+    }
+
+    pub fn collection<T>(&self, name: &str) -> InstrumentedCollection<T> {
+        self.database.collection(name)
+    }
+
+    pub fn touched(&mut self, count: u64) {
+        self.documents_touched += count;
+    }
+
+    pub fn documents_touched(&self) -> u64 {
+        self.documents_touched
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Up,
+    Down,
+}
+
+impl Direction {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Direction::Up => "up",
+            Direction::Down => "down",
+        }
+    }
+}
+
+/// A row of the migrations-history collection.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AppliedMigration {
+    #[serde(rename = "_id")]
+    pub version: u64,
+    pub name: String,
+    pub applied_at: DateTime,
+    pub duration_ms: u64,
+    pub documents_touched: u64,
+}
+
+#[derive(Clone, Debug)]
+pub struct MigrationStep {
+    pub version: u64,
+    pub name: String,
+    pub direction: Direction,
+    pub documents_touched: u64,
+    pub duration: Duration,
+}
+
+/// What a [`Migrator`] run did, or would have done when `dry_run` is set.
+#[derive(Clone, Debug, Default)]
+pub struct MigrationReport {
+    pub dry_run: bool,
+    pub steps: Vec<MigrationStep>,
+}
+
+pub struct Migrator {
+    database: InstrumentedDatabase,
+    history: String,
+    store: Option<Arc<dyn CollectionBackend<Document>>>,
+    owner: String,
+    lock_timeout: Duration,
+    dry_run: bool,
+    migrations: BTreeMap<u64, Box<dyn Migration>>,
+    duplicates: Vec<u64>,
+}
+
+impl Migrator {
+    pub fn new(database: impl Into<InstrumentedDatabase>) -> Self {
+        Migrator {
+            database: database.into(),
+            history: "_migrations".to_string(),
+            store: None,
+            owner: ObjectId::new().to_hex(),
+            lock_timeout: Duration::from_secs(15 * 60),
+            dry_run: false,
+            migrations: BTreeMap::new(),
+            duplicates: Vec::new(),
+        }
+    }
+
+    pub fn history_collection(mut self, name: impl Into<String>) -> Self {
+        self.history = name.into();
+        self
+    }
+
+    /// Keeps the history and the lock in `store` instead of a collection of the database.
+    #[cfg(test)]
+    pub(crate) fn history_store(
+        mut self,
+        store: impl CollectionBackend<Document> + 'static,
+    ) -> Self {
+        self.store = Some(Arc::new(store));
+        self
+    }
+
+    /// Identifies this instance in the lock document, defaults to a random id.
+    pub fn owner(mut self, owner: impl Into<String>) -> Self {
+        self.owner = owner.into();
+        self
+    }
+
+    /// A lock older than this is considered abandoned and taken over.
+    pub fn lock_timeout(mut self, timeout: Duration) -> Self {
+        self.lock_timeout = timeout;
+        self
+    }
+
+    /// Only report the steps that would run, without locking or running them.
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    pub fn migration(mut self, migration: impl Migration + 'static) -> Self {
+        let version = migration.version();
+        if self
+            .migrations
+            .insert(version, Box::new(migration))
+            .is_some()
+        {
+            self.duplicates.push(version);
+        }
+        self
+    }
+
+    pub async fn applied(&self) -> Result<Vec<AppliedMigration>> {
+        self.history()
+            .find(
+                Some(doc! { "_id": { "$ne": LOCK_ID } }),
+                Some(FindOptions::builder().sort(doc! { "_id": 1 }).build()),
+            )
+            .await?
+            .and_then(|applied| async move { Ok(from_document(applied)?) })
+            .try_collect()
+            .await
+    }
+
+    /// Applies every registered migration that is not in the history yet.
+    pub async fn up(&self) -> Result<MigrationReport> {
+        self.check_registered()?;
+        self.run(Direction::Up, |applied| {
+            let applied: HashSet<u64> = applied.iter().map(|m| m.version).collect();
+            Ok(self
+                .migrations
+                .values()
+                .filter(|m| !applied.contains(&m.version()))
+                .map(|m| m.as_ref())
+                .collect())
+        })
+        .await
+    }
+
+    /// Reverts, newest first, every applied migration with a version above `target`.
+    pub async fn down_to(&self, target: u64) -> Result<MigrationReport> {
+        self.check_registered()?;
+        self.run(Direction::Down, |applied| {
+            let mut pending = Vec::new();
+            for applied in applied.iter().rev() {
+                if applied.version <= target {
+                    break;
+                }
+                match self.migrations.get(&applied.version) {
+                    Some(migration) => pending.push(migration.as_ref()),
+                    None => {
+                        return Err(Error::UnknownMigration {
+                            version: applied.version,
+                        }
+                        .into())
+                    }
+                }
+            }
+            Ok(pending)
+        })
+        .await
+    }
+
+    fn check_registered(&self) -> Result<()> {
+        match self.duplicates.first() {
+            Some(version) => Err(Error::DuplicateMigration { version: *version }.into()),
+            None => Ok(()),
+        }
+    }
+
+    /// Runs the migrations `pending` selects from the history. The history is read once the
+    /// lock is held, so an instance that waited for another one does not replay its steps.
+    async fn run<'a>(
+        &'a self,
+        direction: Direction,
+        pending: impl FnOnce(&[AppliedMigration]) -> Result<Vec<&'a dyn Migration>>,
+    ) -> Result<MigrationReport> {
+        let mut report = MigrationReport {
+            dry_run: self.dry_run,
+            steps: Vec::new(),
+        };
+        if self.dry_run {
+            report.steps = pending(&self.applied().await?)?
+                .iter()
+                .map(|migration| MigrationStep {
+                    version: migration.version(),
+                    name: migration.name().to_string(),
+                    direction,
+                    documents_touched: 0,
+                    duration: Duration::ZERO,
+                })
+                .collect();
+            return Ok(report);
+        }
+
+        self.lock().await?;
+        let result = self.run_locked(direction, pending, &mut report).await;
+        let unlocked = self.unlock().await;
+        result.and(unlocked).map(|_| report)
+    }
+
+    async fn run_locked<'a>(
+        &'a self,
+        direction: Direction,
+        pending: impl FnOnce(&[AppliedMigration]) -> Result<Vec<&'a dyn Migration>>,
+        report: &mut MigrationReport,
+    ) -> Result<()> {
+        for migration in pending(&self.applied().await?)? {
+            report.steps.push(self.step(migration, direction).await?);
+        }
+        Ok(())
+    }
+
+    #[instrument(
+    fields(
+    db.name = % self.database.name(),
+    db.system = "mongodb",
+    migration.version = migration.version(),
+    migration.name = migration.name(),
+    migration.direction = direction.as_str(),
+    migration.documents_touched = tracing::field::Empty,
+    ),
+    skip(self, migration, direction)
+    )]
+    async fn step(&self, migration: &dyn Migration, direction: Direction) -> Result<MigrationStep> {
+        let started = Instant::now();
+        let mut ctx = MigrationContext {
+            database: &self.database,
+            documents_touched: 0,
+        };
+        match direction {
+            Direction::Up => migration.up(&mut ctx).await?,
+            Direction::Down => migration.down(&mut ctx).await?,
+        }
+        let duration = started.elapsed();
+        tracing::Span::current().record("migration.documents_touched", ctx.documents_touched);
+
+        match direction {
+            Direction::Up => {
+                let applied = AppliedMigration {
+                    version: migration.version(),
+                    name: migration.name().to_string(),
+                    applied_at: DateTime::now(),
+                    duration_ms: duration.as_millis() as u64,
+                    documents_touched: ctx.documents_touched,
+                };
+                self.history()
+                    .insert_one(&to_document(&applied)?, None)
+                    .await?;
+            }
+            Direction::Down => {
+                self.history()
+                    .delete_one(doc! { "_id": migration.version() as i64 }, None)
+                    .await?;
+            }
+        }
+
+        Ok(MigrationStep {
+            version: migration.version(),
+            name: migration.name().to_string(),
+            direction,
+            documents_touched: ctx.documents_touched,
+            duration,
+        })
+    }
+
+    async fn lock(&self) -> Result<()> {
+        let now = DateTime::now();
+        let expired =
+            DateTime::from_millis(now.timestamp_millis() - self.lock_timeout.as_millis() as i64);
+        let locks = self.history();
+        let taken = locks
+            .update_one(
+                doc! { "_id": LOCK_ID, "lockedAt": { "$lt": expired } },
+                UpdateModifications::Document(
+                    doc! { "$set": { "owner": &self.owner, "lockedAt": now } },
+                ),
+                Some(UpdateOptions::builder().upsert(true).build()),
+            )
+            .await;
+        match taken {
+            Ok(_) => Ok(()),
+            Err(error) if is_duplicate_key(&error) => {
+                let owner = locks
+                    .find_one(Some(doc! { "_id": LOCK_ID }), None)
+                    .await?
+                    .and_then(|lock| lock.get_str("owner").ok().map(str::to_string))
+                    .unwrap_or_default();
+                Err(Error::MigrationLocked { owner }.into())
+            }
+            Err(error) => Err(error),
+        }
+    }
+
+    async fn unlock(&self) -> Result<()> {
+        self.history()
+            .delete_one(doc! { "_id": LOCK_ID, "owner": &self.owner }, None)
+            .await
+            .map(|_| ())
+    }
+
+    fn history(&self) -> Arc<dyn CollectionBackend<Document>> {
+        match &self.store {
+            Some(store) => store.clone(),
+            None => Arc::new(self.database.collection::<Document>(&self.history)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::memory::MemoryCollection;
+    use crate::testing::test_database;
+
+    struct Logged {
+        version: u64,
+        fails: bool,
+        log: Arc<Mutex<Vec<String>>>,
+    }
+
+    #[async_trait]
+    impl Migration for Logged {
+        fn version(&self) -> u64 {
+            self.version
+        }
+
+        fn name(&self) -> &str {
+            "logged"
+        }
+
+        async fn up(&self, ctx: &mut MigrationContext<'_>) -> Result<()> {
+            self.log
+                .lock()
+                .unwrap()
+                .push(format!("up {}", self.version));
+            ctx.touched(self.version);
+            if self.fails {
+                return Err(Error::Unsupported {
+                    feature: "failing step".to_string(),
+                }
+                .into());
+            }
+            Ok(())
+        }
+
+        async fn down(&self, _ctx: &mut MigrationContext<'_>) -> Result<()> {
+            self.log
+                .lock()
+                .unwrap()
+                .push(format!("down {}", self.version));
+            Ok(())
+        }
+    }
+
+    struct Fixture {
+        store: MemoryCollection<Document>,
+        log: Arc<Mutex<Vec<String>>>,
+        database: InstrumentedDatabase,
+    }
+
+    impl Fixture {
+        fn new() -> Self {
+            Fixture {
+                store: MemoryCollection::new(),
+                log: Arc::default(),
+                database: test_database("app"),
+            }
+        }
+
+        fn migrator(&self, versions: &[u64], failing: Option<u64>) -> Migrator {
+            let mut migrator = Migrator::new(self.database.clone())
+                .owner("this")
+                .history_store(self.store.clone());
+            for &version in versions {
+                migrator = migrator.migration(Logged {
+                    version,
+                    fails: failing == Some(version),
+                    log: self.log.clone(),
+                });
+            }
+            migrator
+        }
+
+        fn log(&self) -> Vec<String> {
+            self.log.lock().unwrap().clone()
+        }
+
+        fn locked(&self) -> bool {
+            self.store
+                .documents()
+                .iter()
+                .any(|document| document.get_str("_id") == Ok(LOCK_ID))
+        }
+    }
+
+    fn versions(migrations: &[AppliedMigration]) -> Vec<u64> {
+        migrations.iter().map(|m| m.version).collect()
+    }
+
+    #[tokio::test]
+    async fn up_applies_pending_migrations_in_version_order() {
+        let fixture = Fixture::new();
+        let migrator = fixture.migrator(&[3, 1, 2], None);
+
+        let report = migrator.up().await.unwrap();
+        assert_eq!(
+            report.steps.iter().map(|s| s.version).collect::<Vec<_>>(),
+            vec![1, 2, 3]
+        );
+        assert_eq!(report.steps[2].documents_touched, 3);
+        assert_eq!(fixture.log(), vec!["up 1", "up 2", "up 3"]);
+        assert_eq!(versions(&migrator.applied().await.unwrap()), vec![1, 2, 3]);
+        assert!(!fixture.locked());
+
+        assert!(migrator.up().await.unwrap().steps.is_empty());
+        assert_eq!(fixture.log().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn down_to_reverts_newest_first() {
+        let fixture = Fixture::new();
+        let migrator = fixture.migrator(&[1, 2, 3], None);
+        migrator.up().await.unwrap();
+
+        let report = migrator.down_to(1).await.unwrap();
+        assert_eq!(
+            report.steps.iter().map(|s| s.version).collect::<Vec<_>>(),
+            vec![3, 2]
+        );
+        assert_eq!(fixture.log()[3..], ["down 3", "down 2"]);
+        assert_eq!(versions(&migrator.applied().await.unwrap()), vec![1]);
+    }
+
+    #[tokio::test]
+    async fn dry_run_reports_without_running() {
+        let fixture = Fixture::new();
+        let report = fixture
+            .migrator(&[1, 2], None)
+            .dry_run(true)
+            .up()
+            .await
+            .unwrap();
+        assert!(report.dry_run);
+        assert_eq!(report.steps.len(), 2);
+        assert!(fixture.log().is_empty());
+        assert!(fixture.store.documents().is_empty());
+    }
+
+    #[tokio::test]
+    async fn a_held_lock_stops_the_run() {
+        let fixture = Fixture::new();
+        fixture
+            .store
+            .insert_one(
+                &doc! { "_id": LOCK_ID, "owner": "other", "lockedAt": DateTime::now() },
+                None,
+            )
+            .await
+            .unwrap();
+
+        let error = fixture.migrator(&[1], None).up().await.unwrap_err();
+        assert_eq!(
+            Error::from_mongo(&error),
+            Some(&Error::MigrationLocked {
+                owner: "other".to_string()
+            })
+        );
+        assert!(fixture.log().is_empty());
+    }
+
+    #[tokio::test]
+    async fn an_expired_lock_is_taken_over() {
+        let fixture = Fixture::new();
+        fixture
+            .store
+            .insert_one(
+                &doc! { "_id": LOCK_ID, "owner": "other", "lockedAt": DateTime::from_millis(0) },
+                None,
+            )
+            .await
+            .unwrap();
+
+        fixture.migrator(&[1], None).up().await.unwrap();
+        assert_eq!(fixture.log(), vec!["up 1"]);
+        assert!(!fixture.locked());
+    }
+
+    #[tokio::test]
+    async fn an_instance_locking_after_another_replays_nothing() {
+        let fixture = Fixture::new();
+        let first = fixture.migrator(&[1, 2], None);
+        let second = fixture.migrator(&[1, 2], None).owner("second");
+
+        first.up().await.unwrap();
+        assert!(second.up().await.unwrap().steps.is_empty());
+        assert_eq!(fixture.log(), vec!["up 1", "up 2"]);
+    }
+
+    #[tokio::test]
+    async fn a_failed_step_releases_the_lock() {
+        let fixture = Fixture::new();
+        let error = fixture
+            .migrator(&[1, 2, 3], Some(2))
+            .up()
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            Error::from_mongo(&error),
+            Some(Error::Unsupported { .. })
+        ));
+        assert_eq!(fixture.log(), vec!["up 1", "up 2"]);
+        assert!(!fixture.locked());
+        assert_eq!(
+            versions(&fixture.migrator(&[], None).applied().await.unwrap()),
+            vec![1]
+        );
+    }
+
+    #[tokio::test]
+    async fn duplicate_and_unknown_versions_are_rejected() {
+        let fixture = Fixture::new();
+        let error = fixture.migrator(&[1, 1], None).up().await.unwrap_err();
+        assert_eq!(
+            Error::from_mongo(&error),
+            Some(&Error::DuplicateMigration { version: 1 })
+        );
+
+        fixture.migrator(&[1, 2], None).up().await.unwrap();
+        let error = fixture.migrator(&[1], None).down_to(0).await.unwrap_err();
+        assert_eq!(
+            Error::from_mongo(&error),
+            Some(&Error::UnknownMigration { version: 2 })
+        );
+        assert!(!fixture.locked());
+    }
+}
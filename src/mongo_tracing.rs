@@ -1,27 +1,42 @@
 use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::sync::Arc;
 
-use mongodb::bson::{Bson, Document};
+use mongodb::bson::{from_bson, from_document, Bson, Document, RawDocumentBuf};
 use mongodb::change_stream::event::ChangeStreamEvent;
 use mongodb::change_stream::session::SessionChangeStream;
 use mongodb::change_stream::ChangeStream;
-use mongodb::error::Result;
+use mongodb::error::{BulkWriteError, ErrorKind, Result};
 use mongodb::options::{
     AggregateOptions, ChangeStreamOptions, CountOptions, CreateIndexOptions, DeleteOptions,
     DistinctOptions, DropCollectionOptions, DropIndexOptions, EstimatedDocumentCountOptions,
-    FindOneOptions, FindOptions, InsertManyOptions, InsertOneOptions, ListIndexesOptions,
-    ReadConcern, ReplaceOptions, UpdateModifications, UpdateOptions, WriteConcern,
+    FindOneAndDeleteOptions, FindOneAndReplaceOptions, FindOneAndUpdateOptions, FindOneOptions,
+    FindOptions, InsertManyOptions, InsertOneOptions, ListIndexesOptions, ReadConcern,
+    ReplaceOptions, UpdateModifications, UpdateOptions, WriteConcern,
 };
 use mongodb::results::{
     CreateIndexResult, CreateIndexesResult, DeleteResult, InsertManyResult, InsertOneResult,
     UpdateResult,
 };
-use mongodb::{ClientSession, Collection, Cursor, Database, IndexModel, SessionCursor};
+use mongodb::{ClientSession, Collection, Cursor, Database, IndexModel, Namespace, SessionCursor};
 use serde::de::DeserializeOwned;
 use serde::Serialize;
-use tracing::instrument;
+use tracing::{instrument, Instrument, Span};
 
-struct CollectionInfo {
-    database_name: String,
+use crate::audit::Audit;
+use crate::cache::{self, Cache};
+use crate::cursor::{InstrumentedCursor, InstrumentedSessionCursor};
+use crate::database::InstrumentedDatabase;
+use crate::interceptor::{Interceptor, InterceptorChain, Operation, OperationKind, ToOutcome};
+use crate::pipeline;
+use crate::results;
+use crate::size;
+
+#[derive(Clone)]
+pub(crate) struct CollectionInfo {
+    pub(crate) database_name: String,
+    pub(crate) database: Database,
+    pub(crate) tenant: Option<String>,
 }
 
 pub trait InstrumentedCollectionExt {
@@ -30,18 +45,435 @@ pub trait InstrumentedCollectionExt {
 
 impl InstrumentedCollectionExt for Database {
     fn collection_instrumented<T>(&self, name: &str) -> InstrumentedCollection<T> {
+        InstrumentedCollection::new(self, name)
+    }
+}
+
+pub struct InstrumentedCollection<T> {
+    pub(crate) info: CollectionInfo,
+    pub(crate) inner: Collection<T>,
+    pub(crate) interceptors: InterceptorChain,
+    pub(crate) audit: Option<Audit>,
+    pub(crate) cache: Option<Cache>,
+    pub(crate) projection: Option<Document>,
+}
+
+impl<T> Clone for InstrumentedCollection<T> {
+    fn clone(&self) -> Self {
+        InstrumentedCollection {
+            info: self.info.clone(),
+            inner: self.inner.clone(),
+            interceptors: self.interceptors.clone(),
+            audit: self.audit.clone(),
+            cache: self.cache.clone(),
+            projection: self.projection.clone(),
+        }
+    }
+}
+
+impl<T> InstrumentedCollection<T> {
+    pub(crate) fn new(database: &Database, name: &str) -> Self {
         InstrumentedCollection {
             info: CollectionInfo {
-                database_name: self.name().parse().unwrap(),
+                database_name: database.name().to_string(),
+                database: database.clone(),
+                tenant: None,
             },
-            inner: self.collection(name),
+            inner: database.collection(name),
+            interceptors: InterceptorChain::default(),
+            audit: None,
+            cache: None,
+            projection: None,
         }
     }
+
+    /// Adds an interceptor after the ones already registered; `before` hooks run in that order.
+    pub fn with_interceptor(mut self, interceptor: impl Interceptor + 'static) -> Self {
+        self.interceptors.push(Arc::new(interceptor));
+        self
+    }
+
+    /// The same collection deserializing documents as `U`, keeping interceptors and scope.
+    pub fn clone_with_type<U>(&self) -> InstrumentedCollection<U> {
+        InstrumentedCollection {
+            info: self.info.clone(),
+            inner: self.inner.clone_with_type(),
+            interceptors: self.interceptors.clone(),
+            audit: self.audit.clone(),
+            cache: self.cache.clone(),
+            projection: None,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    pub fn namespace(&self) -> Namespace {
+        self.inner.namespace()
+    }
+
+    pub fn database(&self) -> InstrumentedDatabase {
+        InstrumentedDatabase::new(self.info.database.clone())
+    }
+
+    /// The driver collection over raw BSON, which lets payload sizes be measured.
+    pub(crate) fn raw(&self) -> Collection<RawDocumentBuf> {
+        self.inner.clone_with_type()
+    }
+
+    /// The automatic projection, unless the caller set a projection; recorded on the span.
+    fn auto_projection(&self, requested: Option<&Document>) -> Option<Document> {
+        if requested.is_some() {
+            return None;
+        }
+        let projection = self.projection.clone()?;
+        tracing::Span::current().record(
+            "db.projection",
+            tracing::field::display(Bson::Document(projection.clone()).into_relaxed_extjson()),
+        );
+        Some(projection)
+    }
+
+    /// Invalidates the cache and audits a write once it returned; reads, such as aggregations
+    /// not ending in `$out` or `$merge`, are returned as they are.
+    pub(crate) async fn written<R: ToOutcome>(
+        &self,
+        operation: &Operation<'_>,
+        result: Result<R>,
+        session: Option<&mut ClientSession>,
+    ) -> Result<R> {
+        if !operation.is_write() {
+            return result;
+        }
+        if let Some(cache) = &self.cache {
+            match session.as_deref() {
+                Some(session) => cache.hold(session.id()),
+                None => cache.clear(),
+            }
+        }
+        let result = result?;
+        if let Some(audit) = &self.audit {
+            audit
+                .record(operation, &result.to_outcome(), session)
+                .await?;
+        }
+        Ok(result)
+    }
 }
 
-pub struct InstrumentedCollection<T> {
-    info: CollectionInfo,
-    inner: Collection<T>,
+/// The bodies of the operations `MongoService` serves, shared by the traced methods and the
+/// service; they run in the caller's span.
+impl<T> InstrumentedCollection<T> {
+    pub(crate) async fn run_find(
+        &self,
+        filter: Option<Document>,
+        mut options: Option<FindOptions>,
+    ) -> Result<InstrumentedCursor<T>> {
+        if let Some(projection) = self.auto_projection(
+            options
+                .as_ref()
+                .and_then(|options| options.projection.as_ref()),
+        ) {
+            options.get_or_insert_with(FindOptions::default).projection = Some(projection);
+        }
+        let operation = Operation::new(OperationKind::Find, self.inner.namespace())
+            .with_filter(filter.as_ref())
+            .with_options(options.as_ref());
+        self.interceptors
+            .run(&operation, self.raw().find(filter.clone(), options.clone()))
+            .await
+            .map(|cursor| {
+                InstrumentedCursor::new(cursor).reporting_to(&self.interceptors, &operation)
+            })
+    }
+
+    pub(crate) async fn run_find_one(
+        &self,
+        filter: Option<Document>,
+        mut options: Option<FindOneOptions>,
+    ) -> Result<Option<T>>
+    where
+        T: DeserializeOwned,
+    {
+        let cacheable = options.is_none();
+        if let Some(projection) = self.auto_projection(
+            options
+                .as_ref()
+                .and_then(|options| options.projection.as_ref()),
+        ) {
+            options
+                .get_or_insert_with(FindOneOptions::default)
+                .projection = Some(projection);
+        }
+        let operation = Operation::new(OperationKind::FindOne, self.inner.namespace())
+            .with_filter(filter.as_ref())
+            .with_options(options.as_ref());
+        if let (Some(cache), true) = (&self.cache, cacheable) {
+            let key = cache::key(
+                &self.inner.namespace(),
+                filter.as_ref(),
+                self.projection.as_ref(),
+            );
+            let cached = cache.get(&key);
+            tracing::Span::current().record("cache.hit", cached.is_some());
+            return match cached {
+                Some(found) => {
+                    let found = self
+                        .interceptors
+                        .run(&operation, async { Ok(found) })
+                        .await?;
+                    Ok(found.map(from_document).transpose()?)
+                }
+                None => {
+                    let generation = cache.generation();
+                    let found = self
+                        .interceptors
+                        .run(
+                            &operation,
+                            self.raw().find_one(filter.clone(), options.clone()),
+                        )
+                        .await?;
+                    cache.insert(
+                        key,
+                        found.as_ref().map(|raw| raw.to_document()).transpose()?,
+                        generation,
+                    );
+                    size::received(found)
+                }
+            };
+        }
+        let found = self
+            .interceptors
+            .run(
+                &operation,
+                self.raw().find_one(filter.clone(), options.clone()),
+            )
+            .await?;
+        size::received(found)
+    }
+
+    pub(crate) async fn run_insert_one(
+        &self,
+        doc: &T,
+        options: Option<InsertOneOptions>,
+    ) -> Result<InsertOneResult>
+    where
+        T: Serialize,
+    {
+        let doc = size::sent(doc)?;
+        let operation = Operation::new(OperationKind::InsertOne, self.inner.namespace())
+            .with_documents_size(doc.as_bytes().len())
+            .with_options(options.as_ref());
+        let result = self
+            .interceptors
+            .run(&operation, self.raw().insert_one(&doc, options.clone()))
+            .await;
+        self.written(&operation, result, None).await
+    }
+
+    pub(crate) async fn run_insert_many(
+        &self,
+        docs: impl IntoIterator<Item = impl Borrow<T>>,
+        options: Option<InsertManyOptions>,
+    ) -> Result<InsertManyResult>
+    where
+        T: Serialize,
+    {
+        let docs = size::sent_all(docs)?;
+        let sent = docs.iter().map(|doc| doc.as_bytes().len()).sum();
+        let operation = Operation::new(OperationKind::InsertMany, self.inner.namespace())
+            .with_documents_size(sent)
+            .with_options(options.as_ref());
+        let result = self
+            .interceptors
+            .run(&operation, self.raw().insert_many(&docs, options.clone()))
+            .await;
+        self.written(&operation, result, None).await
+    }
+
+    pub(crate) async fn run_replace_one(
+        &self,
+        query: Document,
+        replacement: &T,
+        options: Option<ReplaceOptions>,
+    ) -> Result<UpdateResult>
+    where
+        T: Serialize,
+    {
+        let replacement = size::sent(replacement)?;
+        let operation = Operation::new(OperationKind::ReplaceOne, self.inner.namespace())
+            .with_filter(Some(&query))
+            .with_documents_size(replacement.as_bytes().len())
+            .with_options(options.as_ref());
+        let result = self
+            .interceptors
+            .run(
+                &operation,
+                self.raw()
+                    .replace_one(query.clone(), &replacement, options.clone()),
+            )
+            .await;
+        self.written(&operation, result, None).await
+    }
+
+    pub(crate) async fn run_update_one(
+        &self,
+        query: Document,
+        requested: Option<&Document>,
+        update: UpdateModifications,
+        options: Option<UpdateOptions>,
+    ) -> Result<UpdateResult> {
+        let operation = Operation::new(OperationKind::UpdateOne, self.inner.namespace())
+            .with_filter(Some(&query))
+            .with_requested_filter(requested)
+            .with_update(&update)
+            .with_options(options.as_ref());
+        let result = self
+            .interceptors
+            .run(
+                &operation,
+                self.inner
+                    .update_one(query.clone(), update.clone(), options.clone()),
+            )
+            .await;
+        self.written(&operation, result, None).await
+    }
+
+    pub(crate) async fn run_update_many(
+        &self,
+        query: Document,
+        requested: Option<&Document>,
+        update: UpdateModifications,
+        options: Option<UpdateOptions>,
+    ) -> Result<UpdateResult> {
+        let operation = Operation::new(OperationKind::UpdateMany, self.inner.namespace())
+            .with_filter(Some(&query))
+            .with_requested_filter(requested)
+            .with_update(&update)
+            .with_options(options.as_ref());
+        let result = self
+            .interceptors
+            .run(
+                &operation,
+                self.inner
+                    .update_many(query.clone(), update.clone(), options.clone()),
+            )
+            .await;
+        self.written(&operation, result, None).await
+    }
+
+    pub(crate) async fn run_delete_one(
+        &self,
+        query: Document,
+        requested: Option<&Document>,
+        options: Option<DeleteOptions>,
+    ) -> Result<DeleteResult> {
+        let operation = Operation::new(OperationKind::DeleteOne, self.inner.namespace())
+            .with_filter(Some(&query))
+            .with_requested_filter(requested)
+            .with_options(options.as_ref());
+        let result = self
+            .interceptors
+            .run(
+                &operation,
+                self.inner.delete_one(query.clone(), options.clone()),
+            )
+            .await;
+        self.written(&operation, result, None).await
+    }
+
+    pub(crate) async fn run_delete_many(
+        &self,
+        query: Document,
+        requested: Option<&Document>,
+        options: Option<DeleteOptions>,
+    ) -> Result<DeleteResult> {
+        let operation = Operation::new(OperationKind::DeleteMany, self.inner.namespace())
+            .with_filter(Some(&query))
+            .with_requested_filter(requested)
+            .with_options(options.as_ref());
+        let result = self
+            .interceptors
+            .run(
+                &operation,
+                self.inner.delete_many(query.clone(), options.clone()),
+            )
+            .await;
+        self.written(&operation, result, None).await
+    }
+
+    pub(crate) async fn run_count_documents(
+        &self,
+        filter: Option<Document>,
+        options: Option<CountOptions>,
+    ) -> Result<u64> {
+        let operation = Operation::new(OperationKind::CountDocuments, self.inner.namespace())
+            .with_filter(filter.as_ref())
+            .with_options(options.as_ref());
+        self.interceptors
+            .run(
+                &operation,
+                self.inner.count_documents(filter.clone(), options.clone()),
+            )
+            .await
+    }
+
+    pub(crate) async fn run_estimated_document_count(
+        &self,
+        options: Option<EstimatedDocumentCountOptions>,
+    ) -> Result<u64> {
+        let operation = Operation::new(
+            OperationKind::EstimatedDocumentCount,
+            self.inner.namespace(),
+        )
+        .with_options(options.as_ref());
+        self.interceptors
+            .run(
+                &operation,
+                self.inner.estimated_document_count(options.clone()),
+            )
+            .await
+    }
+
+    pub(crate) async fn run_aggregate<U>(
+        &self,
+        pipeline: Vec<Document>,
+        options: Option<AggregateOptions>,
+    ) -> Result<InstrumentedCursor<U>> {
+        let operation = Operation::new(OperationKind::Aggregate, self.inner.namespace())
+            .with_pipeline(&pipeline)
+            .with_options(options.as_ref());
+        pipeline::record(&pipeline);
+        let result = self
+            .interceptors
+            .run(
+                &operation,
+                self.inner.aggregate(pipeline.clone(), options.clone()),
+            )
+            .await;
+        self.written(&operation, result, None).await.map(|cursor| {
+            InstrumentedCursor::new(cursor.with_type()).reporting_to(&self.interceptors, &operation)
+        })
+    }
+
+    pub(crate) async fn run_distinct(
+        &self,
+        field_name: &str,
+        filter: Option<Document>,
+        options: Option<DistinctOptions>,
+    ) -> Result<Vec<Bson>> {
+        let operation = Operation::new(OperationKind::Distinct, self.inner.namespace())
+            .with_filter(filter.as_ref())
+            .with_options(options.as_ref());
+        self.interceptors
+            .run(
+                &operation,
+                self.inner
+                    .distinct(field_name, filter.clone(), options.clone()),
+            )
+            .await
+    }
 }
 
 impl<T> InstrumentedCollection<T>
@@ -54,6 +486,12 @@ where
     db.system = "mongodb",
     db.collection = % self.inner.name(),
     otel.kind = "client",
+    tenant.id = self.info.tenant.as_deref(),
+    policy.violation = tracing::field::Empty,
+    db.request.size = tracing::field::Empty,
+    db.response.size = tracing::field::Empty,
+    db.projection = tracing::field::Empty,
+    cache.hit = tracing::field::Empty,
     ),
     skip(self, filter, options)
     )]
@@ -62,7 +500,7 @@ where
         filter: impl Into<Option<Document>>,
         options: impl Into<Option<FindOneOptions>>,
     ) -> Result<Option<T>> {
-        self.inner.find_one(filter, options).await
+        self.run_find_one(filter.into(), options.into()).await
     }
     #[instrument(
     fields(
@@ -70,6 +508,11 @@ where
     db.system = "mongodb",
     db.collection = % self.inner.name(),
     otel.kind = "client",
+    tenant.id = self.info.tenant.as_deref(),
+    policy.violation = tracing::field::Empty,
+    db.request.size = tracing::field::Empty,
+    db.response.size = tracing::field::Empty,
+    db.projection = tracing::field::Empty,
     ),
     skip(self, filter, options,session)
     )]
@@ -79,9 +522,257 @@ where
         options: impl Into<Option<FindOneOptions>>,
         session: &mut ClientSession,
     ) -> Result<Option<T>> {
-        self.inner
-            .find_one_with_session(filter, options, session)
-            .await
+        let filter = filter.into();
+        let mut options = options.into();
+        if let Some(projection) = self.auto_projection(
+            options
+                .as_ref()
+                .and_then(|options| options.projection.as_ref()),
+        ) {
+            options
+                .get_or_insert_with(FindOneOptions::default)
+                .projection = Some(projection);
+        }
+        let operation = Operation::new(OperationKind::FindOne, self.inner.namespace())
+            .with_filter(filter.as_ref())
+            .with_options(options.as_ref())
+            .in_session();
+        let found = self
+            .interceptors
+            .run(
+                &operation,
+                self.raw()
+                    .find_one_with_session(filter.clone(), options.clone(), session),
+            )
+            .await?;
+        size::received(found)
+    }
+    #[instrument(
+    fields(
+    db.name = % self.info.database_name ,
+    db.system = "mongodb",
+    db.collection = % self.inner.name(),
+    otel.kind = "client",
+    tenant.id = self.info.tenant.as_deref(),
+    policy.violation = tracing::field::Empty,
+    db.request.size = tracing::field::Empty,
+    db.response.size = tracing::field::Empty,
+    ),
+    skip(self, filter, options)
+    )]
+    pub async fn find_one_and_delete(
+        &self,
+        filter: Document,
+        options: impl Into<Option<FindOneAndDeleteOptions>>,
+    ) -> Result<Option<T>> {
+        let options = options.into();
+        let operation = Operation::new(OperationKind::FindOneAndDelete, self.inner.namespace())
+            .with_filter(Some(&filter))
+            .with_options(options.as_ref());
+        let result = self
+            .interceptors
+            .run(
+                &operation,
+                self.raw()
+                    .find_one_and_delete(filter.clone(), options.clone()),
+            )
+            .await;
+        size::received(self.written(&operation, result, None).await?)
+    }
+    #[instrument(
+    fields(
+    db.name = % self.info.database_name ,
+    db.system = "mongodb",
+    db.collection = % self.inner.name(),
+    otel.kind = "client",
+    tenant.id = self.info.tenant.as_deref(),
+    policy.violation = tracing::field::Empty,
+    db.request.size = tracing::field::Empty,
+    db.response.size = tracing::field::Empty,
+    ),
+    skip(self, filter, options,session)
+    )]
+    pub async fn find_one_and_delete_with_session(
+        &self,
+        filter: Document,
+        options: impl Into<Option<FindOneAndDeleteOptions>>,
+        session: &mut ClientSession,
+    ) -> Result<Option<T>> {
+        let options = options.into();
+        let operation = Operation::new(OperationKind::FindOneAndDelete, self.inner.namespace())
+            .with_filter(Some(&filter))
+            .with_options(options.as_ref())
+            .in_session();
+        let result = self
+            .interceptors
+            .run(
+                &operation,
+                self.raw().find_one_and_delete_with_session(
+                    filter.clone(),
+                    options.clone(),
+                    session,
+                ),
+            )
+            .await;
+        size::received(self.written(&operation, result, Some(session)).await?)
+    }
+    #[instrument(
+    fields(
+    db.name = % self.info.database_name ,
+    db.system = "mongodb",
+    db.collection = % self.inner.name(),
+    otel.kind = "client",
+    tenant.id = self.info.tenant.as_deref(),
+    policy.violation = tracing::field::Empty,
+    db.request.size = tracing::field::Empty,
+    db.response.size = tracing::field::Empty,
+    ),
+    skip(self, filter, update, options)
+    )]
+    pub async fn find_one_and_update(
+        &self,
+        filter: Document,
+        update: impl Into<UpdateModifications>,
+        options: impl Into<Option<FindOneAndUpdateOptions>>,
+    ) -> Result<Option<T>> {
+        let update = update.into();
+        let options = options.into();
+        let operation = Operation::new(OperationKind::FindOneAndUpdate, self.inner.namespace())
+            .with_filter(Some(&filter))
+            .with_update(&update)
+            .with_options(options.as_ref());
+        let result = self
+            .interceptors
+            .run(
+                &operation,
+                self.raw()
+                    .find_one_and_update(filter.clone(), update.clone(), options.clone()),
+            )
+            .await;
+        size::received(self.written(&operation, result, None).await?)
+    }
+    #[instrument(
+    fields(
+    db.name = % self.info.database_name ,
+    db.system = "mongodb",
+    db.collection = % self.inner.name(),
+    otel.kind = "client",
+    tenant.id = self.info.tenant.as_deref(),
+    policy.violation = tracing::field::Empty,
+    db.request.size = tracing::field::Empty,
+    db.response.size = tracing::field::Empty,
+    ),
+    skip(self, filter, update, options,session)
+    )]
+    pub async fn find_one_and_update_with_session(
+        &self,
+        filter: Document,
+        update: impl Into<UpdateModifications>,
+        options: impl Into<Option<FindOneAndUpdateOptions>>,
+        session: &mut ClientSession,
+    ) -> Result<Option<T>> {
+        let update = update.into();
+        let options = options.into();
+        let operation = Operation::new(OperationKind::FindOneAndUpdate, self.inner.namespace())
+            .with_filter(Some(&filter))
+            .with_update(&update)
+            .with_options(options.as_ref())
+            .in_session();
+        let result = self
+            .interceptors
+            .run(
+                &operation,
+                self.raw().find_one_and_update_with_session(
+                    filter.clone(),
+                    update.clone(),
+                    options.clone(),
+                    session,
+                ),
+            )
+            .await;
+        size::received(self.written(&operation, result, Some(session)).await?)
+    }
+}
+
+impl<T> InstrumentedCollection<T>
+where
+    T: Serialize + DeserializeOwned + Unpin + Send + Sync,
+{
+    #[instrument(
+    fields(
+    db.name = % self.info.database_name ,
+    db.system = "mongodb",
+    db.collection = % self.inner.name(),
+    otel.kind = "client",
+    tenant.id = self.info.tenant.as_deref(),
+    policy.violation = tracing::field::Empty,
+    db.request.size = tracing::field::Empty,
+    db.response.size = tracing::field::Empty,
+    ),
+    skip(self, filter, replacement, options)
+    )]
+    pub async fn find_one_and_replace(
+        &self,
+        filter: Document,
+        replacement: impl Borrow<T>,
+        options: impl Into<Option<FindOneAndReplaceOptions>>,
+    ) -> Result<Option<T>> {
+        let replacement = size::sent(replacement.borrow())?;
+        let options = options.into();
+        let operation = Operation::new(OperationKind::FindOneAndReplace, self.inner.namespace())
+            .with_filter(Some(&filter))
+            .with_documents_size(replacement.as_bytes().len())
+            .with_options(options.as_ref());
+        let result = self
+            .interceptors
+            .run(
+                &operation,
+                self.raw()
+                    .find_one_and_replace(filter.clone(), &replacement, options.clone()),
+            )
+            .await;
+        size::received(self.written(&operation, result, None).await?)
+    }
+    #[instrument(
+    fields(
+    db.name = % self.info.database_name ,
+    db.system = "mongodb",
+    db.collection = % self.inner.name(),
+    otel.kind = "client",
+    tenant.id = self.info.tenant.as_deref(),
+    policy.violation = tracing::field::Empty,
+    db.request.size = tracing::field::Empty,
+    db.response.size = tracing::field::Empty,
+    ),
+    skip(self, filter, replacement, options,session)
+    )]
+    pub async fn find_one_and_replace_with_session(
+        &self,
+        filter: Document,
+        replacement: impl Borrow<T>,
+        options: impl Into<Option<FindOneAndReplaceOptions>>,
+        session: &mut ClientSession,
+    ) -> Result<Option<T>> {
+        let replacement = size::sent(replacement.borrow())?;
+        let options = options.into();
+        let operation = Operation::new(OperationKind::FindOneAndReplace, self.inner.namespace())
+            .with_filter(Some(&filter))
+            .with_documents_size(replacement.as_bytes().len())
+            .with_options(options.as_ref())
+            .in_session();
+        let result = self
+            .interceptors
+            .run(
+                &operation,
+                self.raw().find_one_and_replace_with_session(
+                    filter.clone(),
+                    &replacement,
+                    options.clone(),
+                    session,
+                ),
+            )
+            .await;
+        size::received(self.written(&operation, result, Some(session)).await?)
     }
 }
 
@@ -95,6 +786,10 @@ where
     db.system = "mongodb",
     db.collection = % self.inner.name(),
     otel.kind = "client",
+    tenant.id = self.info.tenant.as_deref(),
+    policy.violation = tracing::field::Empty,
+    db.request.size = tracing::field::Empty,
+    db.response.size = tracing::field::Empty,
     ),
     skip(self, docs, options)
     )]
@@ -103,7 +798,7 @@ where
         docs: impl IntoIterator<Item = impl Borrow<T>>,
         options: impl Into<Option<InsertManyOptions>>,
     ) -> Result<InsertManyResult> {
-        self.inner.insert_many(docs, options).await
+        self.run_insert_many(docs, options.into()).await
     }
 
     #[instrument(
@@ -112,6 +807,10 @@ where
     db.system = "mongodb",
     db.collection = % self.inner.name(),
     otel.kind = "client",
+    tenant.id = self.info.tenant.as_deref(),
+    policy.violation = tracing::field::Empty,
+    db.request.size = tracing::field::Empty,
+    db.response.size = tracing::field::Empty,
     ),
     skip(self, docs, options,session)
     )]
@@ -121,16 +820,120 @@ where
         options: impl Into<Option<InsertManyOptions>>,
         session: &mut ClientSession,
     ) -> Result<InsertManyResult> {
-        self.inner
-            .insert_many_with_session(docs, options, session)
-            .await
+        let docs = size::sent_all(docs)?;
+        let sent = docs.iter().map(|doc| doc.as_bytes().len()).sum();
+        let options = options.into();
+        let operation = Operation::new(OperationKind::InsertMany, self.inner.namespace())
+            .with_documents_size(sent)
+            .with_options(options.as_ref())
+            .in_session();
+        let result = self
+            .interceptors
+            .run(
+                &operation,
+                self.raw()
+                    .insert_many_with_session(&docs, options.clone(), session),
+            )
+            .await;
+        self.written(&operation, result, Some(session)).await
+    }
+    /// Inserts `docs` in batches of at most `batch_size` documents, each run through the
+    /// interceptors as an `insert_many` in its own `insert_many_batch` span.
+    ///
+    /// Every document is serialized before the first batch is sent, and one over the 16 MB BSON
+    /// limit fails the call with [`Error::DocumentTooLarge`](crate::Error::DocumentTooLarge)
+    /// naming its index. Inserted ids are keyed by their index in `docs`. A failed batch stops
+    /// the insert unless `ordered` is `false`, in which case the remaining batches are still
+    /// sent; either way the batches that succeeded stay inserted and the call fails with
+    /// [`Error::BatchedInsert`](crate::Error::BatchedInsert), carrying their ids and the write
+    /// errors indexed in `docs`. Documents of a failed batch are only listed among the inserted
+    /// ids when they carry their own `_id`.
+    #[instrument(
+    fields(
+    db.name = % self.info.database_name ,
+    db.system = "mongodb",
+    db.collection = % self.inner.name(),
+    otel.kind = "client",
+    tenant.id = self.info.tenant.as_deref(),
+    db.request.size = tracing::field::Empty,
+    db.batch.count = tracing::field::Empty,
+    ),
+    skip(self, docs, options)
+    )]
+    pub async fn insert_many_batched(
+        &self,
+        docs: impl IntoIterator<Item = impl Borrow<T>>,
+        batch_size: usize,
+        options: impl Into<Option<InsertManyOptions>>,
+    ) -> Result<results::InsertManyResult> {
+        let docs = size::sent_all(docs)?;
+        let options = options.into();
+        let ordered = options
+            .as_ref()
+            .and_then(|options| options.ordered)
+            .unwrap_or(true);
+        let batch_size = batch_size.max(1);
+        let span = tracing::Span::current();
+        let sent: usize = docs.iter().map(|doc| doc.as_bytes().len()).sum();
+        span.record("db.request.size", sent as u64);
+        span.record(
+            "db.batch.count",
+            size::batch_count(docs.len(), batch_size) as u64,
+        );
+
+        let mut batches = Batches::default();
+        for (batch, chunk) in docs.chunks(batch_size).enumerate() {
+            let span = tracing::info_span!(
+                "insert_many_batch",
+                otel.kind = "client",
+                db.batch.index = batch as u64,
+                db.batch.documents = chunk.len() as u64,
+                policy.violation = tracing::field::Empty,
+                db.request.size = tracing::field::Empty,
+            );
+            let offset = batch * batch_size;
+            match self
+                .insert_batch(chunk, options.as_ref())
+                .instrument(span)
+                .await
+            {
+                Ok(result) => batches.inserted(offset, result.into()),
+                Err(error) => {
+                    batches.failed(batch, offset, chunk, ordered, &error);
+                    if ordered {
+                        break;
+                    }
+                }
+            }
+        }
+        batches.finish()
     }
+
+    async fn insert_batch(
+        &self,
+        docs: &[RawDocumentBuf],
+        options: Option<&InsertManyOptions>,
+    ) -> Result<InsertManyResult> {
+        let operation = Operation::new(OperationKind::InsertMany, self.inner.namespace())
+            .with_documents_size(docs.iter().map(|doc| doc.as_bytes().len()).sum())
+            .with_options(options);
+        let result = self
+            .interceptors
+            .run(&operation, self.raw().insert_many(docs, options.cloned()))
+            .await;
+        self.written(&operation, result, None).await
+    }
+
     #[instrument(
     fields(
     db.name = % self.info.database_name ,
     db.system = "mongodb",
     db.collection = % self.inner.name(),
     otel.kind = "client",
+    tenant.id = self.info.tenant.as_deref(),
+    policy.violation = tracing::field::Empty,
+    db.request.size = tracing::field::Empty,
+    db.response.size = tracing::field::Empty,
     ),
     skip(self, doc, options)
     )]
@@ -139,7 +942,7 @@ where
         doc: impl Borrow<T>,
         options: impl Into<Option<InsertOneOptions>>,
     ) -> Result<InsertOneResult> {
-        self.inner.insert_one(doc, options).await
+        self.run_insert_one(doc.borrow(), options.into()).await
     }
     #[instrument(
     fields(
@@ -147,6 +950,10 @@ where
     db.system = "mongodb",
     db.collection = % self.inner.name(),
     otel.kind = "client",
+    tenant.id = self.info.tenant.as_deref(),
+    policy.violation = tracing::field::Empty,
+    db.request.size = tracing::field::Empty,
+    db.response.size = tracing::field::Empty,
     ),
     skip(self, doc, options,session)
     )]
@@ -156,9 +963,21 @@ where
         options: impl Into<Option<InsertOneOptions>>,
         session: &mut ClientSession,
     ) -> Result<InsertOneResult> {
-        self.inner
-            .insert_one_with_session(doc, options, session)
-            .await
+        let doc = size::sent(doc.borrow())?;
+        let options = options.into();
+        let operation = Operation::new(OperationKind::InsertOne, self.inner.namespace())
+            .with_documents_size(doc.as_bytes().len())
+            .with_options(options.as_ref())
+            .in_session();
+        let result = self
+            .interceptors
+            .run(
+                &operation,
+                self.raw()
+                    .insert_one_with_session(&doc, options.clone(), session),
+            )
+            .await;
+        self.written(&operation, result, Some(session)).await
     }
     #[instrument(
     fields(
@@ -166,6 +985,10 @@ where
     db.system = "mongodb",
     db.collection = % self.inner.name(),
     otel.kind = "client",
+    tenant.id = self.info.tenant.as_deref(),
+    policy.violation = tracing::field::Empty,
+    db.request.size = tracing::field::Empty,
+    db.response.size = tracing::field::Empty,
     ),
     skip(self, query, replacement,options)
     )]
@@ -175,7 +998,8 @@ where
         replacement: impl Borrow<T>,
         options: impl Into<Option<ReplaceOptions>>,
     ) -> Result<UpdateResult> {
-        self.inner.replace_one(query, replacement, options).await
+        self.run_replace_one(query, replacement.borrow(), options.into())
+            .await
     }
     #[instrument(
     fields(
@@ -183,6 +1007,10 @@ where
     db.system = "mongodb",
     db.collection = % self.inner.name(),
     otel.kind = "client",
+    tenant.id = self.info.tenant.as_deref(),
+    policy.violation = tracing::field::Empty,
+    db.request.size = tracing::field::Empty,
+    db.response.size = tracing::field::Empty,
     ),
     skip(self, query, replacement,options,session)
     )]
@@ -193,9 +1021,26 @@ where
         options: impl Into<Option<ReplaceOptions>>,
         session: &mut ClientSession,
     ) -> Result<UpdateResult> {
-        self.inner
-            .replace_one_with_session(query, replacement, options, session)
-            .await
+        let replacement = size::sent(replacement.borrow())?;
+        let options = options.into();
+        let operation = Operation::new(OperationKind::ReplaceOne, self.inner.namespace())
+            .with_filter(Some(&query))
+            .with_documents_size(replacement.as_bytes().len())
+            .with_options(options.as_ref())
+            .in_session();
+        let result = self
+            .interceptors
+            .run(
+                &operation,
+                self.raw().replace_one_with_session(
+                    query.clone(),
+                    &replacement,
+                    options.clone(),
+                    session,
+                ),
+            )
+            .await;
+        self.written(&operation, result, Some(session)).await
     }
 }
 
@@ -206,6 +1051,10 @@ impl<T> InstrumentedCollection<T> {
     db.system = "mongodb",
     db.collection = % self.inner.name(),
     otel.kind = "client",
+    tenant.id = self.info.tenant.as_deref(),
+    policy.violation = tracing::field::Empty,
+    db.request.size = tracing::field::Empty,
+    db.response.size = tracing::field::Empty,
     ),
     skip(self, query, update,options)
     )]
@@ -215,7 +1064,8 @@ impl<T> InstrumentedCollection<T> {
         update: impl Into<UpdateModifications>,
         options: impl Into<Option<UpdateOptions>>,
     ) -> Result<UpdateResult> {
-        self.inner.update_one(query, update, options).await
+        self.run_update_one(query, None, update.into(), options.into())
+            .await
     }
     #[instrument(
     fields(
@@ -223,6 +1073,7 @@ impl<T> InstrumentedCollection<T> {
     db.system = "mongodb",
     db.collection = % self.inner.name(),
     otel.kind = "client",
+    tenant.id = self.info.tenant.as_deref(),
     ),
     skip(self)
     )]
@@ -235,6 +1086,7 @@ impl<T> InstrumentedCollection<T> {
     db.system = "mongodb",
     db.collection = % self.inner.name(),
     otel.kind = "client",
+    tenant.id = self.info.tenant.as_deref(),
     ),
     skip(self)
     )]
@@ -247,11 +1099,25 @@ impl<T> InstrumentedCollection<T> {
     db.system = "mongodb",
     db.collection = % self.inner.name(),
     otel.kind = "client",
+    tenant.id = self.info.tenant.as_deref(),
+    policy.violation = tracing::field::Empty,
+    db.request.size = tracing::field::Empty,
+    db.response.size = tracing::field::Empty,
     ),
     skip(self,options)
     )]
     pub async fn drop(&self, options: impl Into<Option<DropCollectionOptions>>) -> Result<()> {
-        self.inner.drop(options).await
+        let options = options.into();
+        let operation = Operation::new(OperationKind::Drop, self.inner.namespace())
+            .with_options(options.as_ref());
+        let result = self
+            .interceptors
+            .run(&operation, self.inner.drop(options.clone()))
+            .await;
+        if let Some(cache) = &self.cache {
+            cache.clear();
+        }
+        result
     }
     #[instrument(
     fields(
@@ -259,6 +1125,10 @@ impl<T> InstrumentedCollection<T> {
     db.system = "mongodb",
     db.collection = % self.inner.name(),
     otel.kind = "client",
+    tenant.id = self.info.tenant.as_deref(),
+    policy.violation = tracing::field::Empty,
+    db.request.size = tracing::field::Empty,
+    db.response.size = tracing::field::Empty,
     ),
     skip(self,options,session)
     )]
@@ -267,7 +1137,21 @@ impl<T> InstrumentedCollection<T> {
         options: impl Into<Option<DropCollectionOptions>>,
         session: &mut ClientSession,
     ) -> Result<()> {
-        self.inner.drop_with_session(options, session).await
+        let options = options.into();
+        let operation = Operation::new(OperationKind::Drop, self.inner.namespace())
+            .with_options(options.as_ref())
+            .in_session();
+        let result = self
+            .interceptors
+            .run(
+                &operation,
+                self.inner.drop_with_session(options.clone(), session),
+            )
+            .await;
+        if let Some(cache) = &self.cache {
+            cache.clear();
+        }
+        result
     }
     #[instrument(
     fields(
@@ -275,6 +1159,15 @@ impl<T> InstrumentedCollection<T> {
     db.system = "mongodb",
     db.collection = % self.inner.name(),
     otel.kind = "client",
+    tenant.id = self.info.tenant.as_deref(),
+    policy.violation = tracing::field::Empty,
+    db.request.size = tracing::field::Empty,
+    db.response.size = tracing::field::Empty,
+    db.pipeline.stages = tracing::field::Empty,
+    db.pipeline.stage_count = tracing::field::Empty,
+    db.pipeline.collections = tracing::field::Empty,
+    db.pipeline.write = tracing::field::Empty,
+    db.decode.failures = tracing::field::Empty,
     ),
     skip(self,pipeline,options)
     )]
@@ -282,15 +1175,55 @@ impl<T> InstrumentedCollection<T> {
         &self,
         pipeline: impl IntoIterator<Item = Document>,
         options: impl Into<Option<AggregateOptions>>,
-    ) -> Result<Cursor<Document>> {
-        self.inner.aggregate(pipeline, options).await
+    ) -> Result<InstrumentedCursor<Document>> {
+        self.run_aggregate(pipeline.into_iter().collect(), options.into())
+            .await
+    }
+
+    /// Like [`InstrumentedCollection::aggregate`], deserializing the results as `U`; a document
+    /// that fails to deserialize is reported with its `_id` in the span.
+    #[instrument(
+    fields(
+    db.name = % self.info.database_name ,
+    db.system = "mongodb",
+    db.collection = % self.inner.name(),
+    otel.kind = "client",
+    tenant.id = self.info.tenant.as_deref(),
+    policy.violation = tracing::field::Empty,
+    db.request.size = tracing::field::Empty,
+    db.response.size = tracing::field::Empty,
+    db.pipeline.stages = tracing::field::Empty,
+    db.pipeline.stage_count = tracing::field::Empty,
+    db.pipeline.collections = tracing::field::Empty,
+    db.pipeline.write = tracing::field::Empty,
+    db.decode.failures = tracing::field::Empty,
+    ),
+    skip(self,pipeline,options)
+    )]
+    pub async fn aggregate_as<U>(
+        &self,
+        pipeline: impl IntoIterator<Item = Document>,
+        options: impl Into<Option<AggregateOptions>>,
+    ) -> Result<InstrumentedCursor<U>> {
+        self.run_aggregate(pipeline.into_iter().collect(), options.into())
+            .await
     }
+
     #[instrument(
     fields(
     db.name = % self.info.database_name ,
     db.system = "mongodb",
     db.collection = % self.inner.name(),
     otel.kind = "client",
+    tenant.id = self.info.tenant.as_deref(),
+    policy.violation = tracing::field::Empty,
+    db.request.size = tracing::field::Empty,
+    db.response.size = tracing::field::Empty,
+    db.pipeline.stages = tracing::field::Empty,
+    db.pipeline.stage_count = tracing::field::Empty,
+    db.pipeline.collections = tracing::field::Empty,
+    db.pipeline.write = tracing::field::Empty,
+    db.decode.failures = tracing::field::Empty,
     ),
     skip(self,pipeline,options,session)
     )]
@@ -299,10 +1232,28 @@ impl<T> InstrumentedCollection<T> {
         pipeline: impl IntoIterator<Item = Document>,
         options: impl Into<Option<AggregateOptions>>,
         session: &mut ClientSession,
-    ) -> Result<SessionCursor<Document>> {
-        self.inner
-            .aggregate_with_session(pipeline, options, session)
+    ) -> Result<InstrumentedSessionCursor<Document>> {
+        let pipeline: Vec<Document> = pipeline.into_iter().collect();
+        let options = options.into();
+        let operation = Operation::new(OperationKind::Aggregate, self.inner.namespace())
+            .with_pipeline(&pipeline)
+            .with_options(options.as_ref())
+            .in_session();
+        pipeline::record(&pipeline);
+        let result = self
+            .interceptors
+            .run(
+                &operation,
+                self.inner
+                    .aggregate_with_session(pipeline.clone(), options.clone(), session),
+            )
+            .await;
+        self.written(&operation, result, Some(session))
             .await
+            .map(|cursor| {
+                InstrumentedSessionCursor::new(cursor.with_type())
+                    .reporting_to(&self.interceptors, &operation)
+            })
     }
     #[instrument(
     fields(
@@ -310,6 +1261,10 @@ impl<T> InstrumentedCollection<T> {
     db.system = "mongodb",
     db.collection = % self.inner.name(),
     otel.kind = "client",
+    tenant.id = self.info.tenant.as_deref(),
+    policy.violation = tracing::field::Empty,
+    db.request.size = tracing::field::Empty,
+    db.response.size = tracing::field::Empty,
     ),
     skip(self,options)
     )]
@@ -317,7 +1272,7 @@ impl<T> InstrumentedCollection<T> {
         &self,
         options: impl Into<Option<EstimatedDocumentCountOptions>>,
     ) -> Result<u64> {
-        self.inner.estimated_document_count(options).await
+        self.run_estimated_document_count(options.into()).await
     }
     #[instrument(
     fields(
@@ -325,6 +1280,10 @@ impl<T> InstrumentedCollection<T> {
     db.system = "mongodb",
     db.collection = % self.inner.name(),
     otel.kind = "client",
+    tenant.id = self.info.tenant.as_deref(),
+    policy.violation = tracing::field::Empty,
+    db.request.size = tracing::field::Empty,
+    db.response.size = tracing::field::Empty,
     ),
     skip(self,filter,options)
     )]
@@ -333,7 +1292,8 @@ impl<T> InstrumentedCollection<T> {
         filter: impl Into<Option<Document>>,
         options: impl Into<Option<CountOptions>>,
     ) -> Result<u64> {
-        self.inner.count_documents(filter, options).await
+        self.run_count_documents(filter.into(), options.into())
+            .await
     }
     #[instrument(
     fields(
@@ -341,6 +1301,10 @@ impl<T> InstrumentedCollection<T> {
     db.system = "mongodb",
     db.collection = % self.inner.name(),
     otel.kind = "client",
+    tenant.id = self.info.tenant.as_deref(),
+    policy.violation = tracing::field::Empty,
+    db.request.size = tracing::field::Empty,
+    db.response.size = tracing::field::Empty,
     ),
     skip(self,filter,options,session)
     )]
@@ -350,8 +1314,18 @@ impl<T> InstrumentedCollection<T> {
         options: impl Into<Option<CountOptions>>,
         session: &mut ClientSession,
     ) -> Result<u64> {
-        self.inner
-            .count_documents_with_session(filter, options, session)
+        let filter = filter.into();
+        let options = options.into();
+        let operation = Operation::new(OperationKind::CountDocuments, self.inner.namespace())
+            .with_filter(filter.as_ref())
+            .with_options(options.as_ref())
+            .in_session();
+        self.interceptors
+            .run(
+                &operation,
+                self.inner
+                    .count_documents_with_session(filter.clone(), options.clone(), session),
+            )
             .await
     }
     #[instrument(
@@ -360,6 +1334,10 @@ impl<T> InstrumentedCollection<T> {
     db.system = "mongodb",
     db.collection = % self.inner.name(),
     otel.kind = "client",
+    tenant.id = self.info.tenant.as_deref(),
+    policy.violation = tracing::field::Empty,
+    db.request.size = tracing::field::Empty,
+    db.response.size = tracing::field::Empty,
     ),
     skip(self,index,options)
     )]
@@ -368,7 +1346,12 @@ impl<T> InstrumentedCollection<T> {
         index: IndexModel,
         options: impl Into<Option<CreateIndexOptions>>,
     ) -> Result<CreateIndexResult> {
-        self.inner.create_index(index, options).await
+        let options = options.into();
+        let operation = Operation::new(OperationKind::CreateIndex, self.inner.namespace())
+            .with_options(options.as_ref());
+        self.interceptors
+            .run(&operation, self.inner.create_index(index, options.clone()))
+            .await
     }
     #[instrument(
     fields(
@@ -376,6 +1359,10 @@ impl<T> InstrumentedCollection<T> {
     db.system = "mongodb",
     db.collection = % self.inner.name(),
     otel.kind = "client",
+    tenant.id = self.info.tenant.as_deref(),
+    policy.violation = tracing::field::Empty,
+    db.request.size = tracing::field::Empty,
+    db.response.size = tracing::field::Empty,
     ),
     skip(self,index,options,session)
     )]
@@ -385,8 +1372,16 @@ impl<T> InstrumentedCollection<T> {
         options: impl Into<Option<CreateIndexOptions>>,
         session: &mut ClientSession,
     ) -> Result<CreateIndexResult> {
-        self.inner
-            .create_index_with_session(index, options, session)
+        let options = options.into();
+        let operation = Operation::new(OperationKind::CreateIndex, self.inner.namespace())
+            .with_options(options.as_ref())
+            .in_session();
+        self.interceptors
+            .run(
+                &operation,
+                self.inner
+                    .create_index_with_session(index, options.clone(), session),
+            )
             .await
     }
     #[instrument(
@@ -395,6 +1390,10 @@ impl<T> InstrumentedCollection<T> {
     db.system = "mongodb",
     db.collection = % self.inner.name(),
     otel.kind = "client",
+    tenant.id = self.info.tenant.as_deref(),
+    policy.violation = tracing::field::Empty,
+    db.request.size = tracing::field::Empty,
+    db.response.size = tracing::field::Empty,
     ),
     skip(self,indexes,options)
     )]
@@ -403,7 +1402,15 @@ impl<T> InstrumentedCollection<T> {
         indexes: impl IntoIterator<Item = IndexModel>,
         options: impl Into<Option<CreateIndexOptions>>,
     ) -> Result<CreateIndexesResult> {
-        self.inner.create_indexes(indexes, options).await
+        let options = options.into();
+        let operation = Operation::new(OperationKind::CreateIndexes, self.inner.namespace())
+            .with_options(options.as_ref());
+        self.interceptors
+            .run(
+                &operation,
+                self.inner.create_indexes(indexes, options.clone()),
+            )
+            .await
     }
     #[instrument(
     fields(
@@ -411,6 +1418,10 @@ impl<T> InstrumentedCollection<T> {
     db.system = "mongodb",
     db.collection = % self.inner.name(),
     otel.kind = "client",
+    tenant.id = self.info.tenant.as_deref(),
+    policy.violation = tracing::field::Empty,
+    db.request.size = tracing::field::Empty,
+    db.response.size = tracing::field::Empty,
     ),
     skip(self,indexes,options,session)
     )]
@@ -420,8 +1431,16 @@ impl<T> InstrumentedCollection<T> {
         options: impl Into<Option<CreateIndexOptions>>,
         session: &mut ClientSession,
     ) -> Result<CreateIndexesResult> {
-        self.inner
-            .create_indexes_with_session(indexes, options, session)
+        let options = options.into();
+        let operation = Operation::new(OperationKind::CreateIndexes, self.inner.namespace())
+            .with_options(options.as_ref())
+            .in_session();
+        self.interceptors
+            .run(
+                &operation,
+                self.inner
+                    .create_indexes_with_session(indexes, options.clone(), session),
+            )
             .await
     }
     #[instrument(
@@ -430,6 +1449,11 @@ impl<T> InstrumentedCollection<T> {
     db.system = "mongodb",
     db.collection = % self.inner.name(),
     otel.kind = "client",
+    tenant.id = self.info.tenant.as_deref(),
+    policy.violation = tracing::field::Empty,
+    db.request.size = tracing::field::Empty,
+    db.response.size = tracing::field::Empty,
+    db.delete.mode = "hard",
     ),
     skip(self,query,options)
     )]
@@ -438,7 +1462,7 @@ impl<T> InstrumentedCollection<T> {
         query: Document,
         options: impl Into<Option<DeleteOptions>>,
     ) -> Result<DeleteResult> {
-        self.inner.delete_many(query, options).await
+        self.run_delete_many(query, None, options.into()).await
     }
     #[instrument(
     fields(
@@ -446,6 +1470,11 @@ impl<T> InstrumentedCollection<T> {
     db.system = "mongodb",
     db.collection = % self.inner.name(),
     otel.kind = "client",
+    tenant.id = self.info.tenant.as_deref(),
+    policy.violation = tracing::field::Empty,
+    db.request.size = tracing::field::Empty,
+    db.response.size = tracing::field::Empty,
+    db.delete.mode = "hard",
     ),
     skip(self,query,options,session)
     )]
@@ -455,9 +1484,20 @@ impl<T> InstrumentedCollection<T> {
         options: impl Into<Option<DeleteOptions>>,
         session: &mut ClientSession,
     ) -> Result<DeleteResult> {
-        self.inner
-            .delete_many_with_session(query, options, session)
-            .await
+        let options = options.into();
+        let operation = Operation::new(OperationKind::DeleteMany, self.inner.namespace())
+            .with_filter(Some(&query))
+            .with_options(options.as_ref())
+            .in_session();
+        let result = self
+            .interceptors
+            .run(
+                &operation,
+                self.inner
+                    .delete_many_with_session(query.clone(), options.clone(), session),
+            )
+            .await;
+        self.written(&operation, result, Some(session)).await
     }
     #[instrument(
     fields(
@@ -465,6 +1505,11 @@ impl<T> InstrumentedCollection<T> {
     db.system = "mongodb",
     db.collection = % self.inner.name(),
     otel.kind = "client",
+    tenant.id = self.info.tenant.as_deref(),
+    policy.violation = tracing::field::Empty,
+    db.request.size = tracing::field::Empty,
+    db.response.size = tracing::field::Empty,
+    db.delete.mode = "hard",
     ),
     skip(self,query,options)
     )]
@@ -473,7 +1518,7 @@ impl<T> InstrumentedCollection<T> {
         query: Document,
         options: impl Into<Option<DeleteOptions>>,
     ) -> Result<DeleteResult> {
-        self.inner.delete_one(query, options).await
+        self.run_delete_one(query, None, options.into()).await
     }
     #[instrument(
     fields(
@@ -481,6 +1526,11 @@ impl<T> InstrumentedCollection<T> {
     db.system = "mongodb",
     db.collection = % self.inner.name(),
     otel.kind = "client",
+    tenant.id = self.info.tenant.as_deref(),
+    policy.violation = tracing::field::Empty,
+    db.request.size = tracing::field::Empty,
+    db.response.size = tracing::field::Empty,
+    db.delete.mode = "hard",
     ),
     skip(self,query,options,session)
     )]
@@ -490,9 +1540,20 @@ impl<T> InstrumentedCollection<T> {
         options: impl Into<Option<DeleteOptions>>,
         session: &mut ClientSession,
     ) -> Result<DeleteResult> {
-        self.inner
-            .delete_one_with_session(query, options, session)
-            .await
+        let options = options.into();
+        let operation = Operation::new(OperationKind::DeleteOne, self.inner.namespace())
+            .with_filter(Some(&query))
+            .with_options(options.as_ref())
+            .in_session();
+        let result = self
+            .interceptors
+            .run(
+                &operation,
+                self.inner
+                    .delete_one_with_session(query.clone(), options.clone(), session),
+            )
+            .await;
+        self.written(&operation, result, Some(session)).await
     }
     #[instrument(
     fields(
@@ -500,6 +1561,10 @@ impl<T> InstrumentedCollection<T> {
     db.system = "mongodb",
     db.collection = % self.inner.name(),
     otel.kind = "client",
+    tenant.id = self.info.tenant.as_deref(),
+    policy.violation = tracing::field::Empty,
+    db.request.size = tracing::field::Empty,
+    db.response.size = tracing::field::Empty,
     ),
     skip(self,field_name,filter,options)
     )]
@@ -509,14 +1574,63 @@ impl<T> InstrumentedCollection<T> {
         filter: impl Into<Option<Document>>,
         options: impl Into<Option<DistinctOptions>>,
     ) -> Result<Vec<Bson>> {
-        self.inner.distinct(field_name, filter, options).await
+        self.run_distinct(field_name.as_ref(), filter.into(), options.into())
+            .await
+    }
+
+    /// Like [`InstrumentedCollection::distinct`], deserializing the values as `U`; the first
+    /// value that fails to deserialize ends the call with an error and is counted in
+    /// `db.decode.failures`.
+    #[instrument(
+    fields(
+    db.name = % self.info.database_name ,
+    db.system = "mongodb",
+    db.collection = % self.inner.name(),
+    otel.kind = "client",
+    tenant.id = self.info.tenant.as_deref(),
+    policy.violation = tracing::field::Empty,
+    db.request.size = tracing::field::Empty,
+    db.response.size = tracing::field::Empty,
+    db.decode.failures = tracing::field::Empty,
+    ),
+    skip(self,field_name,filter,options)
+    )]
+    pub async fn distinct_as<U: DeserializeOwned>(
+        &self,
+        field_name: impl AsRef<str>,
+        filter: impl Into<Option<Document>>,
+        options: impl Into<Option<DistinctOptions>>,
+    ) -> Result<Vec<U>> {
+        let values = self
+            .run_distinct(field_name.as_ref(), filter.into(), options.into())
+            .await?;
+        values
+            .into_iter()
+            .map(|value| {
+                from_bson(value.clone()).map_err(|error| {
+                    Span::current().record("db.decode.failures", 1_u64);
+                    tracing::warn!(
+                        error = %error,
+                        db.value = %value,
+                        monotonic_counter.db.decode.failures = 1_u64,
+                        "could not deserialize a distinct value"
+                    );
+                    error.into()
+                })
+            })
+            .collect()
     }
+
     #[instrument(
     fields(
     db.name = % self.info.database_name ,
     db.system = "mongodb",
     db.collection = % self.inner.name(),
     otel.kind = "client",
+    tenant.id = self.info.tenant.as_deref(),
+    policy.violation = tracing::field::Empty,
+    db.request.size = tracing::field::Empty,
+    db.response.size = tracing::field::Empty,
     ),
     skip(self,field_name,filter,options,session)
     )]
@@ -527,8 +1641,22 @@ impl<T> InstrumentedCollection<T> {
         options: impl Into<Option<DistinctOptions>>,
         session: &mut ClientSession,
     ) -> Result<Vec<Bson>> {
-        self.inner
-            .distinct_with_session(field_name, filter, options, session)
+        let filter = filter.into();
+        let options = options.into();
+        let operation = Operation::new(OperationKind::Distinct, self.inner.namespace())
+            .with_filter(filter.as_ref())
+            .with_options(options.as_ref())
+            .in_session();
+        self.interceptors
+            .run(
+                &operation,
+                self.inner.distinct_with_session(
+                    field_name,
+                    filter.clone(),
+                    options.clone(),
+                    session,
+                ),
+            )
             .await
     }
     #[instrument(
@@ -537,6 +1665,10 @@ impl<T> InstrumentedCollection<T> {
     db.system = "mongodb",
     db.collection = % self.inner.name(),
     otel.kind = "client",
+    tenant.id = self.info.tenant.as_deref(),
+    policy.violation = tracing::field::Empty,
+    db.request.size = tracing::field::Empty,
+    db.response.size = tracing::field::Empty,
     ),
     skip(self,name,options)
     )]
@@ -545,7 +1677,12 @@ impl<T> InstrumentedCollection<T> {
         name: impl AsRef<str>,
         options: impl Into<Option<DropIndexOptions>>,
     ) -> Result<()> {
-        self.inner.drop_index(name, options).await
+        let options = options.into();
+        let operation = Operation::new(OperationKind::DropIndex, self.inner.namespace())
+            .with_options(options.as_ref());
+        self.interceptors
+            .run(&operation, self.inner.drop_index(name, options.clone()))
+            .await
     }
     #[instrument(
     fields(
@@ -553,6 +1690,10 @@ impl<T> InstrumentedCollection<T> {
     db.system = "mongodb",
     db.collection = % self.inner.name(),
     otel.kind = "client",
+    tenant.id = self.info.tenant.as_deref(),
+    policy.violation = tracing::field::Empty,
+    db.request.size = tracing::field::Empty,
+    db.response.size = tracing::field::Empty,
     ),
     skip(self,name,options,session)
     )]
@@ -562,8 +1703,16 @@ impl<T> InstrumentedCollection<T> {
         options: impl Into<Option<DropIndexOptions>>,
         session: &mut ClientSession,
     ) -> Result<()> {
-        self.inner
-            .drop_index_with_session(name, options, session)
+        let options = options.into();
+        let operation = Operation::new(OperationKind::DropIndex, self.inner.namespace())
+            .with_options(options.as_ref())
+            .in_session();
+        self.interceptors
+            .run(
+                &operation,
+                self.inner
+                    .drop_index_with_session(name, options.clone(), session),
+            )
             .await
     }
     #[instrument(
@@ -572,11 +1721,20 @@ impl<T> InstrumentedCollection<T> {
     db.system = "mongodb",
     db.collection = % self.inner.name(),
     otel.kind = "client",
+    tenant.id = self.info.tenant.as_deref(),
+    policy.violation = tracing::field::Empty,
+    db.request.size = tracing::field::Empty,
+    db.response.size = tracing::field::Empty,
     ),
     skip(self,options)
     )]
     pub async fn drop_indexes(&self, options: impl Into<Option<DropIndexOptions>>) -> Result<()> {
-        self.inner.drop_indexes(options).await
+        let options = options.into();
+        let operation = Operation::new(OperationKind::DropIndexes, self.inner.namespace())
+            .with_options(options.as_ref());
+        self.interceptors
+            .run(&operation, self.inner.drop_indexes(options.clone()))
+            .await
     }
     #[instrument(
     fields(
@@ -584,6 +1742,10 @@ impl<T> InstrumentedCollection<T> {
     db.system = "mongodb",
     db.collection = % self.inner.name(),
     otel.kind = "client",
+    tenant.id = self.info.tenant.as_deref(),
+    policy.violation = tracing::field::Empty,
+    db.request.size = tracing::field::Empty,
+    db.response.size = tracing::field::Empty,
     ),
     skip(self,options,session)
     )]
@@ -592,7 +1754,17 @@ impl<T> InstrumentedCollection<T> {
         options: impl Into<Option<DropIndexOptions>>,
         session: &mut ClientSession,
     ) -> Result<()> {
-        self.inner.drop_indexes_with_session(options, session).await
+        let options = options.into();
+        let operation = Operation::new(OperationKind::DropIndexes, self.inner.namespace())
+            .with_options(options.as_ref())
+            .in_session();
+        self.interceptors
+            .run(
+                &operation,
+                self.inner
+                    .drop_indexes_with_session(options.clone(), session),
+            )
+            .await
     }
     #[instrument(
     fields(
@@ -600,6 +1772,10 @@ impl<T> InstrumentedCollection<T> {
     db.system = "mongodb",
     db.collection = % self.inner.name(),
     otel.kind = "client",
+    tenant.id = self.info.tenant.as_deref(),
+    policy.violation = tracing::field::Empty,
+    db.request.size = tracing::field::Empty,
+    db.response.size = tracing::field::Empty,
     ),
     skip(self,options)
     )]
@@ -607,7 +1783,12 @@ impl<T> InstrumentedCollection<T> {
         &self,
         options: impl Into<Option<ListIndexesOptions>>,
     ) -> Result<Cursor<IndexModel>> {
-        self.inner.list_indexes(options).await
+        let options = options.into();
+        let operation = Operation::new(OperationKind::ListIndexes, self.inner.namespace())
+            .with_options(options.as_ref());
+        self.interceptors
+            .run(&operation, self.inner.list_indexes(options.clone()))
+            .await
     }
     #[instrument(
     fields(
@@ -615,6 +1796,10 @@ impl<T> InstrumentedCollection<T> {
     db.system = "mongodb",
     db.collection = % self.inner.name(),
     otel.kind = "client",
+    tenant.id = self.info.tenant.as_deref(),
+    policy.violation = tracing::field::Empty,
+    db.request.size = tracing::field::Empty,
+    db.response.size = tracing::field::Empty,
     ),
     skip(self,options,session)
     )]
@@ -623,7 +1808,17 @@ impl<T> InstrumentedCollection<T> {
         options: impl Into<Option<ListIndexesOptions>>,
         session: &mut ClientSession,
     ) -> Result<SessionCursor<IndexModel>> {
-        self.inner.list_indexes_with_session(options, session).await
+        let options = options.into();
+        let operation = Operation::new(OperationKind::ListIndexes, self.inner.namespace())
+            .with_options(options.as_ref())
+            .in_session();
+        self.interceptors
+            .run(
+                &operation,
+                self.inner
+                    .list_indexes_with_session(options.clone(), session),
+            )
+            .await
     }
     #[instrument(
     fields(
@@ -631,11 +1826,18 @@ impl<T> InstrumentedCollection<T> {
     db.system = "mongodb",
     db.collection = % self.inner.name(),
     otel.kind = "client",
+    tenant.id = self.info.tenant.as_deref(),
+    policy.violation = tracing::field::Empty,
+    db.request.size = tracing::field::Empty,
+    db.response.size = tracing::field::Empty,
     ),
     skip(self)
     )]
     pub async fn list_index_names(&self) -> Result<Vec<String>> {
-        self.inner.list_index_names().await
+        let operation = Operation::new(OperationKind::ListIndexNames, self.inner.namespace());
+        self.interceptors
+            .run(&operation, self.inner.list_index_names())
+            .await
     }
     #[instrument(
     fields(
@@ -643,6 +1845,10 @@ impl<T> InstrumentedCollection<T> {
     db.system = "mongodb",
     db.collection = % self.inner.name(),
     otel.kind = "client",
+    tenant.id = self.info.tenant.as_deref(),
+    policy.violation = tracing::field::Empty,
+    db.request.size = tracing::field::Empty,
+    db.response.size = tracing::field::Empty,
     ),
     skip(self,session)
     )]
@@ -650,7 +1856,14 @@ impl<T> InstrumentedCollection<T> {
         &self,
         session: &mut ClientSession,
     ) -> Result<Vec<String>> {
-        self.inner.list_index_names_with_session(session).await
+        let operation =
+            Operation::new(OperationKind::ListIndexNames, self.inner.namespace()).in_session();
+        self.interceptors
+            .run(
+                &operation,
+                self.inner.list_index_names_with_session(session),
+            )
+            .await
     }
     #[instrument(
     fields(
@@ -658,6 +1871,10 @@ impl<T> InstrumentedCollection<T> {
     db.system = "mongodb",
     db.collection = % self.inner.name(),
     otel.kind = "client",
+    tenant.id = self.info.tenant.as_deref(),
+    policy.violation = tracing::field::Empty,
+    db.request.size = tracing::field::Empty,
+    db.response.size = tracing::field::Empty,
     ),
     skip(self,query,update,options)
     )]
@@ -667,7 +1884,8 @@ impl<T> InstrumentedCollection<T> {
         update: impl Into<UpdateModifications>,
         options: impl Into<Option<UpdateOptions>>,
     ) -> Result<UpdateResult> {
-        self.inner.update_many(query, update, options).await
+        self.run_update_many(query, None, update.into(), options.into())
+            .await
     }
     #[instrument(
     fields(
@@ -675,6 +1893,10 @@ impl<T> InstrumentedCollection<T> {
     db.system = "mongodb",
     db.collection = % self.inner.name(),
     otel.kind = "client",
+    tenant.id = self.info.tenant.as_deref(),
+    policy.violation = tracing::field::Empty,
+    db.request.size = tracing::field::Empty,
+    db.response.size = tracing::field::Empty,
     ),
     skip(self,query,update,options,session)
     )]
@@ -685,9 +1907,26 @@ impl<T> InstrumentedCollection<T> {
         options: impl Into<Option<UpdateOptions>>,
         session: &mut ClientSession,
     ) -> Result<UpdateResult> {
-        self.inner
-            .update_many_with_session(query, update, options, session)
-            .await
+        let update = update.into();
+        let options = options.into();
+        let operation = Operation::new(OperationKind::UpdateMany, self.inner.namespace())
+            .with_filter(Some(&query))
+            .with_update(&update)
+            .with_options(options.as_ref())
+            .in_session();
+        let result = self
+            .interceptors
+            .run(
+                &operation,
+                self.inner.update_many_with_session(
+                    query.clone(),
+                    update.clone(),
+                    options.clone(),
+                    session,
+                ),
+            )
+            .await;
+        self.written(&operation, result, Some(session)).await
     }
     #[instrument(
     fields(
@@ -695,6 +1934,10 @@ impl<T> InstrumentedCollection<T> {
     db.system = "mongodb",
     db.collection = % self.inner.name(),
     otel.kind = "client",
+    tenant.id = self.info.tenant.as_deref(),
+    policy.violation = tracing::field::Empty,
+    db.request.size = tracing::field::Empty,
+    db.response.size = tracing::field::Empty,
     ),
     skip(self,query,update,options,session)
     )]
@@ -705,9 +1948,26 @@ impl<T> InstrumentedCollection<T> {
         options: impl Into<Option<UpdateOptions>>,
         session: &mut ClientSession,
     ) -> Result<UpdateResult> {
-        self.inner
-            .update_one_with_session(query, update, options, session)
-            .await
+        let update = update.into();
+        let options = options.into();
+        let operation = Operation::new(OperationKind::UpdateOne, self.inner.namespace())
+            .with_filter(Some(&query))
+            .with_update(&update)
+            .with_options(options.as_ref())
+            .in_session();
+        let result = self
+            .interceptors
+            .run(
+                &operation,
+                self.inner.update_one_with_session(
+                    query.clone(),
+                    update.clone(),
+                    options.clone(),
+                    session,
+                ),
+            )
+            .await;
+        self.written(&operation, result, Some(session)).await
     }
     #[instrument(
     fields(
@@ -715,6 +1975,10 @@ impl<T> InstrumentedCollection<T> {
     db.system = "mongodb",
     db.collection = % self.inner.name(),
     otel.kind = "client",
+    tenant.id = self.info.tenant.as_deref(),
+    policy.violation = tracing::field::Empty,
+    db.request.size = tracing::field::Empty,
+    db.response.size = tracing::field::Empty,
     ),
     skip(self,pipeline,options)
     )]
@@ -726,7 +1990,17 @@ impl<T> InstrumentedCollection<T> {
     where
         T: DeserializeOwned + Unpin + Send + Sync,
     {
-        self.inner.watch(pipeline, options).await
+        let pipeline: Vec<Document> = pipeline.into_iter().collect();
+        let options = options.into();
+        let operation = Operation::new(OperationKind::Watch, self.inner.namespace())
+            .with_pipeline(&pipeline)
+            .with_options(options.as_ref());
+        self.interceptors
+            .run(
+                &operation,
+                self.inner.watch(pipeline.clone(), options.clone()),
+            )
+            .await
     }
     #[instrument(
     fields(
@@ -734,6 +2008,10 @@ impl<T> InstrumentedCollection<T> {
     db.system = "mongodb",
     db.collection = % self.inner.name(),
     otel.kind = "client",
+    tenant.id = self.info.tenant.as_deref(),
+    policy.violation = tracing::field::Empty,
+    db.request.size = tracing::field::Empty,
+    db.response.size = tracing::field::Empty,
     ),
     skip(self,pipeline,options,session)
     )]
@@ -746,8 +2024,18 @@ impl<T> InstrumentedCollection<T> {
     where
         T: DeserializeOwned + Unpin + Send + Sync,
     {
-        self.inner
-            .watch_with_session(pipeline, options, session)
+        let pipeline: Vec<Document> = pipeline.into_iter().collect();
+        let options = options.into();
+        let operation = Operation::new(OperationKind::Watch, self.inner.namespace())
+            .with_pipeline(&pipeline)
+            .with_options(options.as_ref())
+            .in_session();
+        self.interceptors
+            .run(
+                &operation,
+                self.inner
+                    .watch_with_session(pipeline.clone(), options.clone(), session),
+            )
             .await
     }
     #[instrument(
@@ -756,6 +2044,12 @@ impl<T> InstrumentedCollection<T> {
     db.system = "mongodb",
     db.collection = % self.inner.name(),
     otel.kind = "client",
+    tenant.id = self.info.tenant.as_deref(),
+    policy.violation = tracing::field::Empty,
+    db.request.size = tracing::field::Empty,
+    db.response.size = tracing::field::Empty,
+    db.projection = tracing::field::Empty,
+    db.decode.failures = tracing::field::Empty,
     ),
     skip(self,filter,options)
     )]
@@ -763,8 +2057,8 @@ impl<T> InstrumentedCollection<T> {
         &self,
         filter: impl Into<Option<Document>>,
         options: impl Into<Option<FindOptions>>,
-    ) -> Result<Cursor<T>> {
-        self.inner.find(filter, options).await
+    ) -> Result<InstrumentedCursor<T>> {
+        self.run_find(filter.into(), options.into()).await
     }
     #[instrument(
     fields(
@@ -772,6 +2066,12 @@ impl<T> InstrumentedCollection<T> {
     db.system = "mongodb",
     db.collection = % self.inner.name(),
     otel.kind = "client",
+    tenant.id = self.info.tenant.as_deref(),
+    policy.violation = tracing::field::Empty,
+    db.request.size = tracing::field::Empty,
+    db.response.size = tracing::field::Empty,
+    db.projection = tracing::field::Empty,
+    db.decode.failures = tracing::field::Empty,
     ),
     skip(self,filter,options,session)
     )]
@@ -780,7 +2080,334 @@ impl<T> InstrumentedCollection<T> {
         filter: impl Into<Option<Document>>,
         options: impl Into<Option<FindOptions>>,
         session: &mut ClientSession,
-    ) -> Result<SessionCursor<T>> {
-        self.inner.find_with_session(filter, options, session).await
+    ) -> Result<InstrumentedSessionCursor<T>> {
+        let filter = filter.into();
+        let mut options = options.into();
+        if let Some(projection) = self.auto_projection(
+            options
+                .as_ref()
+                .and_then(|options| options.projection.as_ref()),
+        ) {
+            options.get_or_insert_with(FindOptions::default).projection = Some(projection);
+        }
+        let operation = Operation::new(OperationKind::Find, self.inner.namespace())
+            .with_filter(filter.as_ref())
+            .with_options(options.as_ref())
+            .in_session();
+        self.interceptors
+            .run(
+                &operation,
+                self.raw()
+                    .find_with_session(filter.clone(), options.clone(), session),
+            )
+            .await
+            .map(|cursor| {
+                InstrumentedSessionCursor::new(cursor).reporting_to(&self.interceptors, &operation)
+            })
+    }
+
+    /// Like [`InstrumentedCollection::find`], yielding documents as raw BSON; use
+    /// [`InstrumentedCursor::current`] to read them without copying.
+    #[instrument(
+    fields(
+    db.name = % self.info.database_name ,
+    db.system = "mongodb",
+    db.collection = % self.inner.name(),
+    otel.kind = "client",
+    tenant.id = self.info.tenant.as_deref(),
+    policy.violation = tracing::field::Empty,
+    db.request.size = tracing::field::Empty,
+    db.response.size = tracing::field::Empty,
+    db.decode.failures = tracing::field::Empty,
+    ),
+    skip(self,filter,options)
+    )]
+    pub async fn find_raw(
+        &self,
+        filter: impl Into<Option<Document>>,
+        options: impl Into<Option<FindOptions>>,
+    ) -> Result<InstrumentedCursor<RawDocumentBuf>> {
+        let filter = filter.into();
+        let options = options.into();
+        let operation = Operation::new(OperationKind::Find, self.inner.namespace())
+            .with_filter(filter.as_ref())
+            .with_options(options.as_ref());
+        self.interceptors
+            .run(&operation, self.raw().find(filter.clone(), options.clone()))
+            .await
+            .map(|cursor| {
+                InstrumentedCursor::new(cursor).reporting_to(&self.interceptors, &operation)
+            })
+    }
+
+    /// Like [`InstrumentedCollection::find_one`] without deserializing; never served from the
+    /// cache.
+    #[instrument(
+    fields(
+    db.name = % self.info.database_name ,
+    db.system = "mongodb",
+    db.collection = % self.inner.name(),
+    otel.kind = "client",
+    tenant.id = self.info.tenant.as_deref(),
+    policy.violation = tracing::field::Empty,
+    db.request.size = tracing::field::Empty,
+    db.response.size = tracing::field::Empty,
+    ),
+    skip(self,filter,options)
+    )]
+    pub async fn find_one_raw(
+        &self,
+        filter: impl Into<Option<Document>>,
+        options: impl Into<Option<FindOneOptions>>,
+    ) -> Result<Option<RawDocumentBuf>> {
+        let filter = filter.into();
+        let options = options.into();
+        let operation = Operation::new(OperationKind::FindOne, self.inner.namespace())
+            .with_filter(filter.as_ref())
+            .with_options(options.as_ref());
+        self.interceptors
+            .run(
+                &operation,
+                self.raw().find_one(filter.clone(), options.clone()),
+            )
+            .await
+            .map(size::received_raw)
+    }
+
+    /// Like [`InstrumentedCollection::aggregate`], yielding documents as raw BSON.
+    #[instrument(
+    fields(
+    db.name = % self.info.database_name ,
+    db.system = "mongodb",
+    db.collection = % self.inner.name(),
+    otel.kind = "client",
+    tenant.id = self.info.tenant.as_deref(),
+    policy.violation = tracing::field::Empty,
+    db.request.size = tracing::field::Empty,
+    db.response.size = tracing::field::Empty,
+    db.pipeline.stages = tracing::field::Empty,
+    db.pipeline.stage_count = tracing::field::Empty,
+    db.pipeline.collections = tracing::field::Empty,
+    db.pipeline.write = tracing::field::Empty,
+    db.decode.failures = tracing::field::Empty,
+    ),
+    skip(self,pipeline,options)
+    )]
+    pub async fn aggregate_raw(
+        &self,
+        pipeline: impl IntoIterator<Item = Document>,
+        options: impl Into<Option<AggregateOptions>>,
+    ) -> Result<InstrumentedCursor<RawDocumentBuf>> {
+        self.run_aggregate(pipeline.into_iter().collect(), options.into())
+            .await
+    }
+}
+
+/// What the batches of an `insert_many_batched` inserted and how they failed, keyed by index in
+/// the inserted documents.
+#[derive(Default)]
+struct Batches {
+    inserted_ids: HashMap<usize, Bson>,
+    write_errors: Vec<BulkWriteError>,
+    errors: Vec<String>,
+}
+
+impl Batches {
+    fn inserted(&mut self, offset: usize, result: results::InsertManyResult) {
+        self.inserted_ids.extend(
+            result
+                .inserted_ids
+                .into_iter()
+                .map(|(index, id)| (offset + index, id)),
+        );
+    }
+
+    /// Records the failure of the batch `chunk`, starting at `offset`. The server inserted the
+    /// documents without a write error, stopping at the first one when `ordered`.
+    fn failed(
+        &mut self,
+        batch: usize,
+        offset: usize,
+        chunk: &[RawDocumentBuf],
+        ordered: bool,
+        error: &mongodb::error::Error,
+    ) {
+        let failure = match &*error.kind {
+            ErrorKind::BulkWrite(failure) => failure,
+            _ => {
+                let error = match crate::Error::from_mongo(error) {
+                    Some(error) => error.to_string(),
+                    None => error.to_string(),
+                };
+                self.errors.push(format!("batch {}: {}", batch, error));
+                return;
+            }
+        };
+        let write_errors = failure.write_errors.clone().unwrap_or_default();
+        let first = write_errors.iter().map(|error| error.index).min();
+        for (index, doc) in chunk.iter().enumerate() {
+            let rejected = write_errors.iter().any(|error| error.index == index);
+            let unreached = ordered && first.is_some_and(|first| index > first);
+            if rejected || unreached {
+                continue;
+            }
+            if let Some(id) = doc
+                .get("_id")
+                .ok()
+                .flatten()
+                .and_then(|id| Bson::try_from(id).ok())
+            {
+                self.inserted_ids.insert(offset + index, id);
+            }
+        }
+        self.write_errors
+            .extend(write_errors.into_iter().map(|mut error| {
+                error.index += offset;
+                error
+            }));
+        if let Some(error) = &failure.write_concern_error {
+            self.errors.push(format!(
+                "batch {}: write concern error: {}",
+                batch, error.message
+            ));
+        }
+    }
+
+    fn finish(self) -> Result<results::InsertManyResult> {
+        if self.write_errors.is_empty() && self.errors.is_empty() {
+            return Ok(results::InsertManyResult {
+                inserted_ids: self.inserted_ids,
+            });
+        }
+        Err(crate::Error::BatchedInsert {
+            inserted_ids: self.inserted_ids,
+            write_errors: self.write_errors,
+            errors: self.errors,
+        }
+        .into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mongodb::bson::{doc, from_document, to_raw_document_buf};
+    use mongodb::error::BulkWriteFailure;
+
+    use super::*;
+    use crate::Error;
+
+    fn chunk(ids: &[i32]) -> Vec<RawDocumentBuf> {
+        ids.iter()
+            .map(|id| to_raw_document_buf(&doc! { "_id": id }).unwrap())
+            .collect()
+    }
+
+    fn rejected(indexes: &[i32]) -> mongodb::error::Error {
+        let write_errors: Vec<Document> = indexes
+            .iter()
+            .map(|index| doc! { "index": index, "code": 11000, "errmsg": "duplicate" })
+            .collect();
+        let failure: BulkWriteFailure =
+            from_document(doc! { "writeErrors": write_errors }).unwrap();
+        ErrorKind::BulkWrite(failure).into()
+    }
+
+    fn batched(batches: Batches) -> (HashMap<usize, Bson>, Vec<usize>, Vec<String>) {
+        match Error::from_mongo(&batches.finish().unwrap_err()) {
+            Some(Error::BatchedInsert {
+                inserted_ids,
+                write_errors,
+                errors,
+            }) => (
+                inserted_ids.clone(),
+                write_errors.iter().map(|error| error.index).collect(),
+                errors.clone(),
+            ),
+            other => panic!("expected a batched insert error, got {:?}", other),
+        }
+    }
+
+    fn ids(pairs: &[(usize, i32)]) -> HashMap<usize, Bson> {
+        pairs
+            .iter()
+            .map(|(index, id)| (*index, Bson::Int32(*id)))
+            .collect()
+    }
+
+    #[test]
+    fn successful_batches_are_keyed_by_their_index_in_the_documents() {
+        let mut batches = Batches::default();
+        batches.inserted(
+            0,
+            results::InsertManyResult {
+                inserted_ids: ids(&[(0, 1), (1, 2)]),
+            },
+        );
+        batches.inserted(
+            2,
+            results::InsertManyResult {
+                inserted_ids: ids(&[(0, 3)]),
+            },
+        );
+
+        assert_eq!(
+            batches.finish().unwrap().inserted_ids,
+            ids(&[(0, 1), (1, 2), (2, 3)])
+        );
+    }
+
+    #[test]
+    fn ordered_failures_keep_the_documents_before_the_first_write_error() {
+        let mut batches = Batches::default();
+        batches.inserted(
+            0,
+            results::InsertManyResult {
+                inserted_ids: ids(&[(0, 1), (1, 2)]),
+            },
+        );
+        batches.failed(1, 2, &chunk(&[3, 4, 5]), true, &rejected(&[1]));
+
+        let (inserted_ids, write_errors, errors) = batched(batches);
+        assert_eq!(inserted_ids, ids(&[(0, 1), (1, 2), (2, 3)]));
+        assert_eq!(write_errors, vec![3]);
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn unordered_failures_keep_every_document_without_a_write_error() {
+        let mut batches = Batches::default();
+        batches.failed(0, 0, &chunk(&[1, 2, 3]), false, &rejected(&[0]));
+        batches.inserted(
+            3,
+            results::InsertManyResult {
+                inserted_ids: ids(&[(0, 4)]),
+            },
+        );
+        batches.failed(2, 4, &chunk(&[5, 6]), false, &rejected(&[1]));
+
+        let (inserted_ids, write_errors, _) = batched(batches);
+        assert_eq!(inserted_ids, ids(&[(1, 2), (2, 3), (3, 4), (4, 5)]));
+        assert_eq!(write_errors, vec![0, 5]);
+    }
+
+    #[test]
+    fn other_failures_are_described_by_batch() {
+        let mut batches = Batches::default();
+        batches.inserted(
+            0,
+            results::InsertManyResult {
+                inserted_ids: ids(&[(0, 1)]),
+            },
+        );
+        let error: mongodb::error::Error = Error::Replay {
+            reason: "down".to_string(),
+        }
+        .into();
+        batches.failed(1, 1, &chunk(&[2]), true, &error);
+
+        let (inserted_ids, write_errors, errors) = batched(batches);
+        assert_eq!(inserted_ids, ids(&[(0, 1)]));
+        assert!(write_errors.is_empty());
+        assert_eq!(errors, vec!["batch 1: replay failed: down".to_string()]);
     }
 }
@@ -0,0 +1,542 @@
+use std::fmt;
+
+use mongodb::bson::{doc, Bson, Document};
+use mongodb::error::Result;
+
+use crate::error::Error;
+use crate::{query, shape};
+
+/// Stages writing their results to a collection; they must come last.
+const WRITE_STAGES: &[&str] = &["$out", "$merge"];
+
+/// Stages that may not appear inside `$facet`.
+const NOT_IN_FACET: &[&str] = &[
+    "$out",
+    "$merge",
+    "$facet",
+    "$geoNear",
+    "$collStats",
+    "$indexStats",
+    "$changeStream",
+];
+
+/// Stages that may not appear in the `pipeline` of `$lookup` or `$unionWith`.
+const NOT_IN_SUB_PIPELINE: &[&str] = &["$out", "$merge", "$changeStream"];
+
+/// An accumulator of a `$group` stage.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Accumulator {
+    Sum(Bson),
+    Avg(Bson),
+    Min(Bson),
+    Max(Bson),
+    First(Bson),
+    Last(Bson),
+    Push(Bson),
+    AddToSet(Bson),
+    /// The number of documents in the group.
+    Count,
+}
+
+impl From<Accumulator> for Bson {
+    fn from(accumulator: Accumulator) -> Self {
+        let (operator, expression) = match accumulator {
+            Accumulator::Sum(expression) => ("$sum", expression),
+            Accumulator::Avg(expression) => ("$avg", expression),
+            Accumulator::Min(expression) => ("$min", expression),
+            Accumulator::Max(expression) => ("$max", expression),
+            Accumulator::First(expression) => ("$first", expression),
+            Accumulator::Last(expression) => ("$last", expression),
+            Accumulator::Push(expression) => ("$push", expression),
+            Accumulator::AddToSet(expression) => ("$addToSet", expression),
+            Accumulator::Count => ("$sum", Bson::Int32(1)),
+        };
+        Bson::Document(doc! { operator: expression })
+    }
+}
+
+/// A fluent aggregation pipeline builder; [`Pipeline::build`] checks the stage ordering rules
+/// and returns the stages to pass to [`aggregate`](crate::InstrumentedCollection::aggregate).
+///
+/// `Display` renders the stages as relaxed extended JSON, [`Pipeline::shape`] without their
+/// values for logs that must not carry data.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Pipeline {
+    stages: Vec<Document>,
+    /// Why a stage could not be built, returned by [`Pipeline::build`].
+    invalid: Option<String>,
+}
+
+impl Pipeline {
+    pub fn new() -> Self {
+        Pipeline::default()
+    }
+
+    /// Appends a stage the builder has no method for.
+    pub fn stage(mut self, stage: Document) -> Self {
+        self.stages.push(stage);
+        self
+    }
+
+    pub fn match_(self, filter: Document) -> Self {
+        self.stage(doc! { "$match": filter })
+    }
+
+    pub fn project(self, projection: Document) -> Self {
+        self.stage(doc! { "$project": projection })
+    }
+
+    /// `$group` on `id`, computing each named accumulator.
+    pub fn group<K: Into<String>>(
+        self,
+        id: impl Into<Bson>,
+        accumulators: impl IntoIterator<Item = (K, Accumulator)>,
+    ) -> Self {
+        let mut group = doc! { "_id": id.into() };
+        for (name, accumulator) in accumulators {
+            group.insert(name, accumulator);
+        }
+        self.stage(doc! { "$group": group })
+    }
+
+    /// `$lookup` joining the documents of `from` whose `foreign_field` equals `local_field`.
+    pub fn lookup(self, from: &str, local_field: &str, foreign_field: &str, as_: &str) -> Self {
+        self.stage(doc! {
+            "$lookup": {
+                "from": from,
+                "localField": local_field,
+                "foreignField": foreign_field,
+                "as": as_,
+            }
+        })
+    }
+
+    /// `$unwind` of the array at `path`, given with or without its leading `$`.
+    pub fn unwind(self, path: &str) -> Self {
+        let path = if path.starts_with('$') {
+            path.to_string()
+        } else {
+            format!("${}", path)
+        };
+        self.stage(doc! { "$unwind": path })
+    }
+
+    pub fn sort(self, sort: Document) -> Self {
+        self.stage(doc! { "$sort": sort })
+    }
+
+    /// `$skip`; a count over `i64::MAX` makes [`Pipeline::build`] fail.
+    pub fn skip(self, skip: u64) -> Self {
+        self.count("$skip", skip)
+    }
+
+    /// `$limit`; a limit of 0, which the server rejects, or over `i64::MAX` makes
+    /// [`Pipeline::build`] fail.
+    pub fn limit(self, limit: u64) -> Self {
+        self.count("$limit", limit)
+    }
+
+    /// `$facet` running each named sub-pipeline on the same input; a sub-pipeline that could
+    /// not be built makes [`Pipeline::build`] fail.
+    pub fn facet<K: Into<String>>(
+        mut self,
+        facets: impl IntoIterator<Item = (K, Pipeline)>,
+    ) -> Self {
+        let mut facet = Document::new();
+        for (name, pipeline) in facets {
+            let name = name.into();
+            if let Some(reason) = pipeline.invalid {
+                self.invalid
+                    .get_or_insert_with(|| format!("{} in $facet {}", reason, name));
+            }
+            facet.insert(name, pipeline.stages);
+        }
+        self.stage(doc! { "$facet": facet })
+    }
+
+    /// `$merge` into the collection `into` with the server's default behaviour.
+    pub fn merge(self, into: &str) -> Self {
+        self.stage(doc! { "$merge": { "into": into } })
+    }
+
+    pub fn out(self, collection: &str) -> Self {
+        self.stage(doc! { "$out": collection })
+    }
+
+    pub fn stages(&self) -> &[Document] {
+        &self.stages
+    }
+
+    /// The stages, once checked against the ordering rules.
+    pub fn build(self) -> Result<Vec<Document>> {
+        if let Some(reason) = self.invalid {
+            return Err(Error::InvalidPipeline { reason }.into());
+        }
+        check(&self.stages, None)?;
+        Ok(self.stages)
+    }
+
+    /// The stages as relaxed extended JSON with every value replaced by `"?"`.
+    pub fn shape(&self) -> String {
+        render(self.stages.iter().map(shape::shape))
+    }
+
+    fn count(mut self, operator: &str, count: u64) -> Self {
+        let stage = self.stages.len();
+        let reason = match i64::try_from(count) {
+            Ok(0) if operator == "$limit" => format!("{} of stage {} is 0", operator, stage),
+            Ok(count) => return self.stage(doc! { operator: count }),
+            Err(_) => format!("{} of stage {} is over {}", operator, stage, i64::MAX),
+        };
+        self.invalid.get_or_insert(reason);
+        self
+    }
+}
+
+impl fmt::Display for Pipeline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&render(self.stages.iter().cloned()))
+    }
+}
+
+fn render(stages: impl Iterator<Item = Document>) -> String {
+    Bson::Array(stages.map(Bson::Document).collect())
+        .into_relaxed_extjson()
+        .to_string()
+}
+
+/// Checks the ordering rules of `stages`, the pipeline of the stage `inside` if any, and of
+/// the pipelines nested in them.
+fn check(stages: &[Document], inside: Option<&str>) -> Result<()> {
+    let invalid = |reason: String| Err(Error::InvalidPipeline { reason }.into());
+    for (index, stage) in stages.iter().enumerate() {
+        if stage.len() != 1 {
+            return invalid(format!("stage {} must have exactly one operator", index));
+        }
+        let Some(name) = stage_name(stage) else {
+            continue;
+        };
+        if let Some(inside) = inside {
+            let forbidden = match inside {
+                "$facet" => NOT_IN_FACET,
+                _ => NOT_IN_SUB_PIPELINE,
+            };
+            if forbidden.contains(&name) {
+                return invalid(format!("{} cannot be used inside {}", name, inside));
+            }
+        }
+        if WRITE_STAGES.contains(&name) && index + 1 != stages.len() {
+            return invalid(format!("{} must be the last stage", name));
+        }
+        if query::must_come_first(name) && index != 0 {
+            return invalid(format!("{} must be the first stage", name));
+        }
+        match (name, stage.get(name)) {
+            ("$facet", Some(Bson::Document(facets))) => {
+                for facet in facets.values() {
+                    check(&sub_pipeline(facet), Some(name))?;
+                }
+            }
+            ("$lookup" | "$unionWith", Some(Bson::Document(spec))) => {
+                if let Some(pipeline) = spec.get("pipeline") {
+                    check(&sub_pipeline(pipeline), Some(name))?;
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// The operator of a stage.
+fn stage_name(stage: &Document) -> Option<&str> {
+    stage.keys().next().map(String::as_str)
+}
+
+/// Whether `pipeline` writes its results, i.e. ends in `$out` or `$merge`.
+pub(crate) fn is_write(pipeline: &[Document]) -> bool {
+    pipeline
+        .last()
+        .and_then(stage_name)
+        .is_some_and(|name| WRITE_STAGES.contains(&name))
+}
+
+/// Records the stages of `pipeline` and the collections it reads or writes on the current
+/// span, leaving every value out.
+pub(crate) fn record(pipeline: &[Document]) {
+    let span = tracing::Span::current();
+    if span.is_disabled() {
+        return;
+    }
+    let stages: Vec<&str> = pipeline.iter().filter_map(stage_name).collect();
+    let mut collections = Vec::new();
+    referenced(pipeline, &mut collections);
+    span.record("db.pipeline.stages", stages.join(",").as_str());
+    span.record("db.pipeline.stage_count", stages.len() as u64);
+    span.record("db.pipeline.collections", collections.join(",").as_str());
+    span.record("db.pipeline.write", is_write(pipeline));
+}
+
+/// Adds the collections named by the stages of `pipeline` and of its sub-pipelines.
+fn referenced(pipeline: &[Document], collections: &mut Vec<String>) {
+    for stage in pipeline {
+        for (name, spec) in stage {
+            let (collection, nested) = match (name.as_str(), spec) {
+                ("$lookup" | "$graphLookup", Bson::Document(spec)) => (
+                    spec.get_str("from").ok().map(str::to_string),
+                    spec.get("pipeline"),
+                ),
+                ("$unionWith", Bson::String(collection)) => (Some(collection.clone()), None),
+                ("$unionWith", Bson::Document(spec)) => (
+                    spec.get_str("coll").ok().map(str::to_string),
+                    spec.get("pipeline"),
+                ),
+                ("$out", target) => (namespace(target, "coll"), None),
+                ("$merge", Bson::Document(spec)) => (
+                    spec.get("into").and_then(|into| namespace(into, "coll")),
+                    None,
+                ),
+                ("$merge", target) => (namespace(target, "coll"), None),
+                ("$facet", Bson::Document(facets)) => {
+                    for facet in facets.values() {
+                        referenced(&sub_pipeline(facet), collections);
+                    }
+                    (None, None)
+                }
+                _ => (None, None),
+            };
+            if let Some(collection) = collection {
+                if !collections.contains(&collection) {
+                    collections.push(collection);
+                }
+            }
+            if let Some(nested) = nested {
+                referenced(&sub_pipeline(nested), collections);
+            }
+        }
+    }
+}
+
+/// A collection given as its name or as `{ db, <key> }`, the latter as `db.collection`.
+fn namespace(target: &Bson, key: &str) -> Option<String> {
+    match target {
+        Bson::String(collection) => Some(collection.clone()),
+        Bson::Document(target) => {
+            let collection = target.get_str(key).ok()?;
+            Some(match target.get_str("db") {
+                Ok(db) => format!("{}.{}", db, collection),
+                Err(_) => collection.to_string(),
+            })
+        }
+        _ => None,
+    }
+}
+
+fn sub_pipeline(stages: &Bson) -> Vec<Document> {
+    match stages {
+        Bson::Array(stages) => stages
+            .iter()
+            .filter_map(|stage| stage.as_document().cloned())
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn collections(pipeline: &[Document]) -> Vec<String> {
+        let mut collections = Vec::new();
+        referenced(pipeline, &mut collections);
+        collections
+    }
+
+    #[test]
+    fn referenced_collections_include_sub_pipelines() {
+        let pipeline = [
+            doc! { "$lookup": { "from": "customers", "as": "customer", "pipeline": [
+                { "$lookup": { "from": "addresses", "as": "address" } },
+            ] } },
+            doc! { "$graphLookup": { "from": "employees", "startWith": "$boss" } },
+            doc! { "$unionWith": "archive" },
+            doc! { "$unionWith": { "coll": "legacy", "pipeline": [
+                { "$unionWith": "older" },
+            ] } },
+            doc! { "$facet": { "a": [{ "$lookup": { "from": "customers" } }] } },
+            doc! { "$merge": { "into": { "db": "reports", "coll": "daily" } } },
+        ];
+        assert_eq!(
+            collections(&pipeline),
+            vec![
+                "customers",
+                "addresses",
+                "employees",
+                "archive",
+                "legacy",
+                "older",
+                "reports.daily"
+            ]
+        );
+    }
+
+    #[test]
+    fn referenced_collections_of_out_targets() {
+        assert_eq!(collections(&[doc! { "$out": "totals" }]), vec!["totals"]);
+        assert_eq!(
+            collections(&[doc! { "$out": { "db": "reports", "coll": "totals" } }]),
+            vec!["reports.totals"]
+        );
+        assert_eq!(collections(&[doc! { "$merge": "totals" }]), vec!["totals"]);
+        assert!(collections(&[doc! { "$match": { "from": "x" } }]).is_empty());
+    }
+
+    #[test]
+    fn writes_end_in_out_or_merge() {
+        assert!(is_write(&[doc! { "$match": {} }, doc! { "$out": "a" }]));
+        assert!(is_write(&[doc! { "$merge": { "into": "a" } }]));
+        assert!(!is_write(&[doc! { "$out": "a" }, doc! { "$match": {} }]));
+        assert!(!is_write(&[]));
+    }
+
+    fn reason(pipeline: Pipeline) -> Option<String> {
+        match pipeline.build() {
+            Ok(_) => None,
+            Err(error) => match Error::from_mongo(&error) {
+                Some(Error::InvalidPipeline { reason }) => Some(reason.clone()),
+                _ => panic!("unexpected error: {error}"),
+            },
+        }
+    }
+
+    #[test]
+    fn stage_order_is_checked() {
+        let cases = [
+            (Pipeline::new().match_(doc! {}).out("a"), None),
+            (
+                Pipeline::new().out("a").match_(doc! {}),
+                Some("$out must be the last stage"),
+            ),
+            (
+                Pipeline::new()
+                    .match_(doc! {})
+                    .stage(doc! { "$geoNear": { "near": [0, 0] } }),
+                Some("$geoNear must be the first stage"),
+            ),
+            (
+                Pipeline::new()
+                    .stage(doc! { "$search": { "text": { "query": "a", "path": "b" } } })
+                    .limit(5),
+                None,
+            ),
+            (
+                Pipeline::new()
+                    .match_(doc! {})
+                    .stage(doc! { "$vectorSearch": { "index": "a", "limit": 5 } }),
+                Some("$vectorSearch must be the first stage"),
+            ),
+            (
+                Pipeline::new()
+                    .sort(doc! { "a": 1 })
+                    .stage(doc! { "$searchMeta": { "count": {} } }),
+                Some("$searchMeta must be the first stage"),
+            ),
+            (
+                Pipeline::new()
+                    .match_(doc! {})
+                    .stage(doc! { "$documents": [{ "a": 1 }] }),
+                Some("$documents must be the first stage"),
+            ),
+            (
+                Pipeline::new().stage(doc! { "$match": {}, "$sort": { "a": 1 } }),
+                Some("stage 0 must have exactly one operator"),
+            ),
+            (
+                Pipeline::new().facet([("a", Pipeline::new().merge("b"))]),
+                Some("$merge cannot be used inside $facet"),
+            ),
+            (
+                Pipeline::new().facet([("a", Pipeline::new().skip(1).limit(2))]),
+                None,
+            ),
+        ];
+        for (pipeline, expected) in cases {
+            assert_eq!(reason(pipeline).as_deref(), expected);
+        }
+    }
+
+    #[test]
+    fn lookup_and_union_pipelines_are_checked() {
+        let cases = [
+            (
+                doc! { "$lookup": { "from": "a", "as": "b", "pipeline": [{ "$out": "c" }] } },
+                Some("$out cannot be used inside $lookup"),
+            ),
+            (
+                doc! { "$unionWith": { "coll": "a", "pipeline": [{ "$merge": "c" }] } },
+                Some("$merge cannot be used inside $unionWith"),
+            ),
+            (
+                doc! { "$lookup": { "from": "a", "as": "b", "pipeline": [
+                    { "$facet": { "x": [{ "$facet": {} }] } },
+                ] } },
+                Some("$facet cannot be used inside $facet"),
+            ),
+            (
+                doc! { "$unionWith": { "coll": "a", "pipeline": [
+                    { "$match": {} },
+                    { "$geoNear": { "near": [0, 0] } },
+                ] } },
+                Some("$geoNear must be the first stage"),
+            ),
+            (
+                doc! { "$lookup": { "from": "a", "as": "b", "pipeline": [
+                    { "$match": {} },
+                    { "$lookup": { "from": "c", "as": "d", "pipeline": [{ "$limit": 1 }] } },
+                ] } },
+                None,
+            ),
+        ];
+        for (stage, expected) in cases {
+            assert_eq!(reason(Pipeline::new().stage(stage)).as_deref(), expected);
+        }
+    }
+
+    #[test]
+    fn zero_limits_are_rejected() {
+        assert_eq!(
+            reason(Pipeline::new().match_(doc! {}).limit(0)).as_deref(),
+            Some("$limit of stage 1 is 0")
+        );
+        assert_eq!(
+            Pipeline::new().skip(0).build().unwrap(),
+            vec![doc! { "$skip": 0_i64 }]
+        );
+    }
+
+    #[test]
+    fn counts_over_the_bson_range_are_rejected() {
+        assert_eq!(
+            Pipeline::new().skip(5).limit(10).build().unwrap(),
+            vec![doc! { "$skip": 5_i64 }, doc! { "$limit": 10_i64 }]
+        );
+        assert_eq!(
+            reason(
+                Pipeline::new()
+                    .match_(doc! {})
+                    .limit(u64::MAX)
+                    .skip(u64::MAX)
+            )
+            .as_deref(),
+            Some("$limit of stage 1 is over 9223372036854775807")
+        );
+        assert_eq!(
+            reason(Pipeline::new().facet([
+                ("page", Pipeline::new().skip(1).limit(u64::MAX)),
+                ("all", Pipeline::new().skip(u64::MAX)),
+            ]))
+            .as_deref(),
+            Some("$limit of stage 1 is over 9223372036854775807 in $facet page")
+        );
+    }
+}
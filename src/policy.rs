@@ -0,0 +1,460 @@
+use std::fmt;
+
+use mongodb::bson::{Bson, Document};
+use mongodb::error::Result;
+use mongodb::options::UpdateModifications;
+
+use crate::error::Error;
+use crate::interceptor::{Interceptor, Operation, OperationKind, OperationOptions};
+use crate::mongo_tracing::InstrumentedCollection;
+
+/// What a [`Policy`] does when one of its rules matches.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    Allow,
+    Warn,
+    Reject,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PolicyRule {
+    /// `update_many`/`delete_many` without a filter, including soft deletes and writes through a
+    /// tenant view.
+    EmptyFilterWrite,
+    /// `drop`/`drop_indexes` outside the allowed environments.
+    Drop,
+    /// A regular expression not anchored with `^` or `\A`, which cannot use an index.
+    UnanchoredRegex,
+    /// `$where`, `$function` or `$accumulator` in a filter, pipeline or update.
+    ServerSideJavascript,
+    /// `find` without a limit on a collection that requires one.
+    UnboundedFind,
+}
+
+impl PolicyRule {
+    pub fn name(&self) -> &'static str {
+        match self {
+            PolicyRule::EmptyFilterWrite => "empty_filter_write",
+            PolicyRule::Drop => "drop",
+            PolicyRule::UnanchoredRegex => "unanchored_regex",
+            PolicyRule::ServerSideJavascript => "server_side_javascript",
+            PolicyRule::UnboundedFind => "unbounded_find",
+        }
+    }
+}
+
+impl fmt::Display for PolicyRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Guardrails checked before each operation of a collection, see
+/// [`InstrumentedCollection::with_policy`].
+///
+/// By default empty-filter multi-writes, drops and server-side JavaScript are rejected,
+/// unanchored regexes only warn, and no collection requires a limit on `find`.
+#[derive(Clone, Debug)]
+pub struct Policy {
+    environment: Option<String>,
+    empty_filter_writes: Action,
+    drops: Action,
+    drop_environments: Vec<String>,
+    unanchored_regexes: Action,
+    server_side_javascript: Action,
+    unbounded_finds: Action,
+    limited_collections: Vec<String>,
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Policy {
+            environment: None,
+            empty_filter_writes: Action::Reject,
+            drops: Action::Reject,
+            drop_environments: Vec::new(),
+            unanchored_regexes: Action::Warn,
+            server_side_javascript: Action::Reject,
+            unbounded_finds: Action::Reject,
+            limited_collections: Vec::new(),
+        }
+    }
+}
+
+impl Policy {
+    pub fn new() -> Self {
+        Policy::default()
+    }
+
+    /// The environment this process runs in, matched against [`Policy::allow_drops_in`].
+    pub fn environment(mut self, environment: impl Into<String>) -> Self {
+        self.environment = Some(environment.into());
+        self
+    }
+
+    pub fn empty_filter_writes(mut self, action: Action) -> Self {
+        self.empty_filter_writes = action;
+        self
+    }
+
+    pub fn drops(mut self, action: Action) -> Self {
+        self.drops = action;
+        self
+    }
+
+    pub fn allow_drops_in<I, S>(mut self, environments: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.drop_environments = environments.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn unanchored_regexes(mut self, action: Action) -> Self {
+        self.unanchored_regexes = action;
+        self
+    }
+
+    pub fn server_side_javascript(mut self, action: Action) -> Self {
+        self.server_side_javascript = action;
+        self
+    }
+
+    pub fn unbounded_finds(mut self, action: Action) -> Self {
+        self.unbounded_finds = action;
+        self
+    }
+
+    /// Collections on which `find` must set a limit.
+    pub fn require_limit_on<I, S>(mut self, collections: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.limited_collections = collections.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// The rules `operation` breaks, with the action configured for each.
+    pub fn check(&self, operation: &Operation<'_>) -> Vec<(PolicyRule, Action)> {
+        let mut violations = Vec::new();
+        let kind = operation.kind();
+
+        // Views restrict the caller's filter, which is what decides whether the write is bounded.
+        let empty_filter = operation.requested_filter().is_none_or(Document::is_empty);
+        if matches!(kind, OperationKind::UpdateMany | OperationKind::DeleteMany) && empty_filter {
+            violations.push((PolicyRule::EmptyFilterWrite, self.empty_filter_writes));
+        }
+
+        let drop_allowed = match &self.environment {
+            Some(environment) => self.drop_environments.contains(environment),
+            None => false,
+        };
+        if matches!(kind, OperationKind::Drop | OperationKind::DropIndexes) && !drop_allowed {
+            violations.push((PolicyRule::Drop, self.drops));
+        }
+
+        let mut scan = Scan::default();
+        if let Some(filter) = operation.filter() {
+            scan.document(filter);
+        }
+        for stage in operation.pipeline().unwrap_or_default() {
+            scan.document(stage);
+        }
+        match operation.update() {
+            Some(UpdateModifications::Document(update)) => scan.document(update),
+            Some(UpdateModifications::Pipeline(stages)) => {
+                stages.iter().for_each(|stage| scan.document(stage))
+            }
+            _ => {}
+        }
+        if scan.javascript {
+            violations.push((
+                PolicyRule::ServerSideJavascript,
+                self.server_side_javascript,
+            ));
+        }
+        if scan.unanchored_regex {
+            violations.push((PolicyRule::UnanchoredRegex, self.unanchored_regexes));
+        }
+
+        if kind == OperationKind::Find
+            && self
+                .limited_collections
+                .iter()
+                .any(|collection| *collection == operation.namespace().coll)
+        {
+            let limited = matches!(
+                operation.options(),
+                Some(OperationOptions::Find(options)) if options.limit.is_some_and(|limit| limit != 0)
+            );
+            if !limited {
+                violations.push((PolicyRule::UnboundedFind, self.unbounded_finds));
+            }
+        }
+
+        violations.retain(|(_, action)| *action != Action::Allow);
+        violations
+    }
+}
+
+impl Interceptor for Policy {
+    fn before(&self, operation: &Operation<'_>) -> Result<()> {
+        let violations = self.check(operation);
+        if violations.is_empty() {
+            return Ok(());
+        }
+
+        let rules: Vec<&str> = violations.iter().map(|(rule, _)| rule.name()).collect();
+        tracing::Span::current().record("policy.violation", rules.join(",").as_str());
+
+        let mut rejected = None;
+        for (rule, action) in violations {
+            match action {
+                Action::Reject => {
+                    rejected.get_or_insert(rule);
+                }
+                _ => tracing::warn!(
+                    policy.violation = rule.name(),
+                    db.operation = operation.kind().name(),
+                    "operation breaks the collection policy"
+                ),
+            }
+        }
+        match rejected {
+            Some(rule) => Err(Error::PolicyViolation {
+                rule,
+                operation: operation.kind(),
+            }
+            .into()),
+            None => Ok(()),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Scan {
+    javascript: bool,
+    unanchored_regex: bool,
+}
+
+impl Scan {
+    fn document(&mut self, document: &Document) {
+        for (key, value) in document {
+            match key.as_str() {
+                "$where" | "$function" | "$accumulator" => self.javascript = true,
+                "$regex" => {
+                    if let Bson::String(pattern) = value {
+                        self.pattern(pattern);
+                    }
+                }
+                _ => {}
+            }
+            self.value(value);
+        }
+    }
+
+    fn value(&mut self, value: &Bson) {
+        match value {
+            Bson::Document(document) => self.document(document),
+            Bson::Array(values) => values.iter().for_each(|value| self.value(value)),
+            Bson::RegularExpression(regex) => self.pattern(&regex.pattern),
+            Bson::JavaScriptCode(_) | Bson::JavaScriptCodeWithScope(_) => self.javascript = true,
+            _ => {}
+        }
+    }
+
+    fn pattern(&mut self, pattern: &str) {
+        if !(pattern.starts_with('^') || pattern.starts_with("\\A")) {
+            self.unanchored_regex = true;
+        }
+    }
+}
+
+impl<T> InstrumentedCollection<T> {
+    pub fn with_policy(self, policy: Policy) -> Self {
+        self.with_interceptor(policy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mongodb::bson::{doc, Regex};
+    use mongodb::options::FindOptions;
+    use mongodb::Namespace;
+
+    use super::*;
+
+    fn scan(document: Document) -> (bool, bool) {
+        let mut scan = Scan::default();
+        scan.document(&document);
+        (scan.javascript, scan.unanchored_regex)
+    }
+
+    fn namespace() -> Namespace {
+        Namespace {
+            db: "app".to_string(),
+            coll: "users".to_string(),
+        }
+    }
+
+    #[test]
+    fn scan_finds_server_side_javascript() {
+        assert_eq!(scan(doc! { "$where": "this.a > 1" }), (true, false));
+        assert_eq!(
+            scan(doc! { "$expr": { "$function": { "body": "f", "args": [], "lang": "js" } } }),
+            (true, false)
+        );
+        assert_eq!(
+            scan(doc! { "$or": [{ "a": 1 }, { "b": Bson::JavaScriptCode("f".into()) }] }),
+            (true, false)
+        );
+        assert_eq!(scan(doc! { "a": "$where" }), (false, false));
+    }
+
+    #[test]
+    fn scan_finds_unanchored_regexes() {
+        assert_eq!(scan(doc! { "name": { "$regex": "^ada" } }), (false, false));
+        assert_eq!(
+            scan(doc! { "name": { "$regex": "\\Aada" } }),
+            (false, false)
+        );
+        assert_eq!(scan(doc! { "name": { "$regex": "ada" } }), (false, true));
+        let regex = Regex {
+            pattern: "ada".to_string(),
+            options: "i".to_string(),
+        };
+        assert_eq!(
+            scan(doc! { "$and": [{ "name": { "$in": [regex] } }] }),
+            (false, true)
+        );
+    }
+
+    #[test]
+    fn empty_filter_multi_writes_are_rejected() {
+        let policy = Policy::new();
+        let empty = doc! {};
+        let operation =
+            Operation::new(OperationKind::DeleteMany, namespace()).with_filter(Some(&empty));
+        assert_eq!(
+            policy.check(&operation),
+            vec![(PolicyRule::EmptyFilterWrite, Action::Reject)]
+        );
+        let operation =
+            Operation::new(OperationKind::DeleteOne, namespace()).with_filter(Some(&empty));
+        assert!(policy.check(&operation).is_empty());
+        let filter = doc! { "active": false };
+        let operation =
+            Operation::new(OperationKind::UpdateMany, namespace()).with_filter(Some(&filter));
+        assert!(policy.check(&operation).is_empty());
+    }
+
+    #[test]
+    fn views_do_not_hide_empty_filters() {
+        let requested = doc! {};
+        let scoped = doc! { "deletedAt": null };
+        let operation = Operation::new(OperationKind::UpdateMany, namespace())
+            .with_filter(Some(&scoped))
+            .with_requested_filter(Some(&requested));
+        assert_eq!(
+            Policy::new().check(&operation),
+            vec![(PolicyRule::EmptyFilterWrite, Action::Reject)]
+        );
+    }
+
+    #[test]
+    fn drops_are_allowed_in_listed_environments() {
+        let operation = Operation::new(OperationKind::Drop, namespace());
+        assert_eq!(
+            Policy::new().check(&operation),
+            vec![(PolicyRule::Drop, Action::Reject)]
+        );
+        let policy = Policy::new()
+            .environment("test")
+            .allow_drops_in(["test", "dev"]);
+        assert!(policy.check(&operation).is_empty());
+        let policy = Policy::new().environment("prod").allow_drops_in(["test"]);
+        assert_eq!(policy.check(&operation).len(), 1);
+    }
+
+    #[test]
+    fn allowed_rules_are_not_reported() {
+        let filter = doc! { "name": { "$regex": "ada" } };
+        let operation = Operation::new(OperationKind::Find, namespace()).with_filter(Some(&filter));
+        assert_eq!(
+            Policy::new().check(&operation),
+            vec![(PolicyRule::UnanchoredRegex, Action::Warn)]
+        );
+        let policy = Policy::new().unanchored_regexes(Action::Allow);
+        assert!(policy.check(&operation).is_empty());
+    }
+
+    #[test]
+    fn pipelines_are_scanned() {
+        let pipeline = [doc! { "$group": { "_id": null, "n": { "$accumulator": {} } } }];
+        let operation =
+            Operation::new(OperationKind::Aggregate, namespace()).with_pipeline(&pipeline);
+        assert_eq!(
+            Policy::new().check(&operation),
+            vec![(PolicyRule::ServerSideJavascript, Action::Reject)]
+        );
+    }
+
+    #[test]
+    fn updates_are_scanned() {
+        let update = UpdateModifications::Document(doc! {
+            "$set": { "score": { "$function": { "body": "f", "args": [], "lang": "js" } } }
+        });
+        let filter = doc! { "$expr": { "$where": "this.a > 1" } };
+        let operation = Operation::new(OperationKind::UpdateMany, namespace())
+            .with_filter(Some(&filter))
+            .with_update(&update);
+        assert_eq!(
+            Policy::new().check(&operation),
+            vec![(PolicyRule::ServerSideJavascript, Action::Reject)]
+        );
+
+        let update = UpdateModifications::Pipeline(vec![doc! {
+            "$set": { "total": { "$accumulator": {} } }
+        }]);
+        let filter = doc! { "_id": 1 };
+        let operation = Operation::new(OperationKind::FindOneAndUpdate, namespace())
+            .with_filter(Some(&filter))
+            .with_update(&update);
+        assert_eq!(
+            Policy::new().check(&operation),
+            vec![(PolicyRule::ServerSideJavascript, Action::Reject)]
+        );
+
+        let update = UpdateModifications::Document(doc! { "$set": { "name": "$where" } });
+        let operation = Operation::new(OperationKind::UpdateOne, namespace())
+            .with_filter(Some(&filter))
+            .with_update(&update);
+        assert!(Policy::new().check(&operation).is_empty());
+    }
+
+    #[test]
+    fn finds_on_limited_collections_need_a_limit() {
+        let policy = Policy::new().require_limit_on(["users"]);
+        let unbounded = Operation::new(OperationKind::Find, namespace());
+        assert_eq!(
+            policy.check(&unbounded),
+            vec![(PolicyRule::UnboundedFind, Action::Reject)]
+        );
+        let options = FindOptions::builder().limit(0).build();
+        let zero = Operation::new(OperationKind::Find, namespace()).with_options(Some(&options));
+        assert_eq!(policy.check(&zero).len(), 1);
+        let options = FindOptions::builder().limit(10).build();
+        let limited = Operation::new(OperationKind::Find, namespace()).with_options(Some(&options));
+        assert!(policy.check(&limited).is_empty());
+        assert!(Policy::new().check(&unbounded).is_empty());
+    }
+
+    #[test]
+    fn rejections_fail_the_operation() {
+        let operation = Operation::new(OperationKind::Drop, namespace());
+        assert!(Policy::new().before(&operation).is_err());
+        assert!(Policy::new().drops(Action::Warn).before(&operation).is_ok());
+    }
+}
@@ -0,0 +1,146 @@
+use std::fmt;
+
+use mongodb::bson::{Bson, Document};
+use serde::de::{self, DeserializeOwned, Deserializer, Visitor};
+use serde::forward_to_deserialize_any;
+
+use crate::mongo_tracing::InstrumentedCollection;
+
+/// The inclusion projection of the fields `T` deserializes, following renames; `None` when `T`
+/// is not a struct or has flattened fields.
+///
+/// serde's derive reads a struct with flattened fields as a map, without naming the fields, so
+/// such types get no projection rather than one leaving out the fields they collect.
+pub(crate) fn projection<T: DeserializeOwned>() -> Option<Document> {
+    match T::deserialize(Probe) {
+        Err(Probed::Struct(fields)) if !fields.is_empty() => Some(
+            fields
+                .iter()
+                .map(|field| (field.to_string(), Bson::Int32(1)))
+                .collect(),
+        ),
+        _ => None,
+    }
+}
+
+/// A deserializer that only finds out the fields of the struct asked for.
+struct Probe;
+
+#[derive(Debug)]
+enum Probed {
+    Struct(&'static [&'static str]),
+    Other,
+}
+
+impl fmt::Display for Probed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("probed")
+    }
+}
+
+impl std::error::Error for Probed {}
+
+impl de::Error for Probed {
+    fn custom<M: fmt::Display>(_message: M) -> Self {
+        Probed::Other
+    }
+}
+
+impl<'de> Deserializer<'de> for Probe {
+    type Error = Probed;
+
+    fn deserialize_any<V: Visitor<'de>>(
+        self,
+        _visitor: V,
+    ) -> std::result::Result<V::Value, Probed> {
+        Err(Probed::Other)
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        _visitor: V,
+    ) -> std::result::Result<V::Value, Probed> {
+        Err(Probed::Struct(fields))
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf
+        option unit unit_struct newtype_struct seq tuple tuple_struct map enum identifier
+        ignored_any
+    }
+}
+
+impl<T> InstrumentedCollection<T> {
+    /// Fetches only the fields of `T` in `find` and `find_one` calls that set no projection,
+    /// recording it in the span's `db.projection`; types that are not structs or have
+    /// flattened fields get none.
+    pub fn with_auto_projection(mut self) -> Self
+    where
+        T: DeserializeOwned,
+    {
+        self.projection = projection::<T>();
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use mongodb::bson::doc;
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[allow(dead_code)]
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Summary {
+        #[serde(rename = "_id")]
+        id: i32,
+        display_name: String,
+        status: Status,
+        #[serde(deserialize_with = "lowercase")]
+        email: String,
+        #[serde(skip)]
+        cached: bool,
+    }
+
+    #[allow(dead_code)]
+    #[derive(Deserialize)]
+    enum Status {
+        Active,
+        Blocked { reason: String },
+    }
+
+    fn lowercase<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+        String::deserialize(deserializer).map(|email| email.to_lowercase())
+    }
+
+    #[allow(dead_code)]
+    #[derive(Deserialize)]
+    struct WithFlattened {
+        name: String,
+        #[serde(flatten)]
+        rest: HashMap<String, String>,
+    }
+
+    #[test]
+    fn projection_lists_the_fields_read() {
+        assert_eq!(
+            projection::<Summary>(),
+            Some(doc! { "_id": 1, "displayName": 1, "status": 1, "email": 1 })
+        );
+    }
+
+    #[test]
+    fn projection_is_none_for_flattened_and_non_struct_types() {
+        assert_eq!(projection::<WithFlattened>(), None);
+        assert_eq!(projection::<HashMap<String, String>>(), None);
+        assert_eq!(projection::<serde_json::Value>(), None);
+        assert_eq!(projection::<Status>(), None);
+        assert_eq!(projection::<String>(), None);
+    }
+}
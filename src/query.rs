@@ -0,0 +1,70 @@
+use mongodb::bson::{doc, Bson, Document};
+
+/// Stages that must come first and report on the whole collection rather than on documents.
+pub(crate) const COLLECTION_STAGES: &[&str] = &[
+    "$collStats",
+    "$indexStats",
+    "$planCacheStats",
+    "$searchMeta",
+];
+
+/// Stages that must come first and output documents, which a `$match` can then follow.
+const LEADING_STAGES: &[&str] = &["$search", "$vectorSearch", "$documents"];
+
+/// Whether `stage` must be the first stage of a pipeline.
+pub(crate) fn must_come_first(stage: &str) -> bool {
+    matches!(stage, "$geoNear" | "$changeStream")
+        || COLLECTION_STAGES.contains(&stage)
+        || LEADING_STAGES.contains(&stage)
+}
+
+/// `filter` restricted to the documents also matching `predicate`.
+pub(crate) fn and(filter: Option<Document>, predicate: Document) -> Document {
+    match filter {
+        Some(filter) if !filter.is_empty() => doc! { "$and": [filter, predicate] },
+        _ => predicate,
+    }
+}
+
+/// The name of the first stage of `pipeline`.
+pub(crate) fn first_stage(pipeline: &[Document]) -> Option<&str> {
+    pipeline
+        .first()
+        .and_then(|stage| stage.keys().next())
+        .map(String::as_str)
+}
+
+/// `pipeline` restricted to the documents matching `predicate`, applied before the first stage
+/// passing documents on: in the `query` of a leading `$geoNear`, in a `$match` right after a
+/// leading `$search`, `$vectorSearch` or `$documents`, or else in a `$match` ahead of the
+/// pipeline. Pipelines starting with one of the [`COLLECTION_STAGES`] are left unchanged.
+pub(crate) fn match_first(mut pipeline: Vec<Document>, predicate: Document) -> Vec<Document> {
+    match first_stage(&pipeline) {
+        Some(stage) if COLLECTION_STAGES.contains(&stage) => {}
+        Some("$geoNear") => {
+            if let Ok(near) = pipeline[0].get_document_mut("$geoNear") {
+                let query = match near.remove("query") {
+                    Some(Bson::Document(query)) => Some(query),
+                    _ => None,
+                };
+                near.insert("query", and(query, predicate));
+            }
+        }
+        Some(stage) if LEADING_STAGES.contains(&stage) => {
+            pipeline.insert(1, doc! { "$match": predicate });
+        }
+        _ => pipeline.insert(0, doc! { "$match": predicate }),
+    }
+    pipeline
+}
+
+/// Whether `path` is `field` itself or one of its sub-fields.
+pub(crate) fn touches(path: &str, field: &str) -> bool {
+    path == field
+        || path
+            .strip_prefix(field)
+            .is_some_and(|rest| rest.starts_with('.'))
+        || field
+            .strip_prefix(path)
+            .is_some_and(|rest| rest.starts_with('.'))
+}
@@ -0,0 +1,68 @@
+use std::collections::HashMap;
+
+use mongodb::bson::Bson;
+use mongodb::results;
+use serde::{Deserialize, Serialize};
+
+/// Result of an insert through a [`CollectionBackend`](crate::backend::CollectionBackend).
+///
+/// The driver's result types cannot be built outside of it, so backends return these mirrors,
+/// converted from the driver's by [`InstrumentedCollection`](crate::InstrumentedCollection).
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InsertOneResult {
+    pub inserted_id: Bson,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct InsertManyResult {
+    pub inserted_ids: HashMap<usize, Bson>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateResult {
+    pub matched_count: u64,
+    pub modified_count: u64,
+    pub upserted_id: Option<Bson>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeleteResult {
+    pub deleted_count: u64,
+}
+
+impl From<results::InsertOneResult> for InsertOneResult {
+    fn from(result: results::InsertOneResult) -> Self {
+        InsertOneResult {
+            inserted_id: result.inserted_id,
+        }
+    }
+}
+
+impl From<results::InsertManyResult> for InsertManyResult {
+    fn from(result: results::InsertManyResult) -> Self {
+        InsertManyResult {
+            inserted_ids: result.inserted_ids,
+        }
+    }
+}
+
+impl From<results::UpdateResult> for UpdateResult {
+    fn from(result: results::UpdateResult) -> Self {
+        UpdateResult {
+            matched_count: result.matched_count,
+            modified_count: result.modified_count,
+            upserted_id: result.upserted_id,
+        }
+    }
+}
+
+impl From<results::DeleteResult> for DeleteResult {
+    fn from(result: results::DeleteResult) -> Self {
+        DeleteResult {
+            deleted_count: result.deleted_count,
+        }
+    }
+}
@@ -0,0 +1,500 @@
+use std::collections::BTreeMap;
+
+use futures_util::TryStreamExt;
+use mongodb::bson::{doc, to_bson, Bson, Document};
+use mongodb::error::Result;
+use mongodb::options::CreateCollectionOptions;
+use schemars::gen::{SchemaGenerator, SchemaSettings};
+use schemars::schema::{Schema, SchemaObject};
+use schemars::JsonSchema;
+use tracing::instrument;
+
+use crate::mongo_tracing::InstrumentedCollection;
+
+/// Keywords schemars emits that MongoDB's `$jsonSchema` rejects.
+const UNSUPPORTED: &[&str] = &[
+    "$schema",
+    "$id",
+    "$comment",
+    "definitions",
+    "format",
+    "default",
+    "examples",
+    "readOnly",
+    "writeOnly",
+];
+
+/// The `$jsonSchema` document describing `T`, with `$ref`s inlined and JSON types mapped to
+/// BSON types.
+pub fn json_schema<T: JsonSchema>() -> Document {
+    let root = SchemaSettings::draft07()
+        .into_generator()
+        .into_root_schema_for::<T>();
+    let definitions = match to_bson(&root.definitions) {
+        Ok(Bson::Document(definitions)) => definitions,
+        _ => Document::new(),
+    };
+    match to_bson(&root.schema) {
+        Ok(Bson::Document(schema)) => convert(schema, &definitions, &mut Vec::new()),
+        _ => Document::new(),
+    }
+}
+
+/// A collection validator enforcing [`json_schema`] of `T`.
+pub fn validator<T: JsonSchema>() -> Document {
+    doc! { "$jsonSchema": json_schema::<T>() }
+}
+
+fn convert(schema: Document, definitions: &Document, resolving: &mut Vec<String>) -> Document {
+    if let Ok(reference) = schema.get_str("$ref") {
+        let name = reference.trim_start_matches("#/definitions/").to_string();
+        if resolving.contains(&name) {
+            // MongoDB has no references, recursive types are only checked to be objects.
+            return doc! { "bsonType": "object" };
+        }
+        let definition = match definitions.get_document(&name) {
+            Ok(definition) => definition.clone(),
+            Err(_) => return Document::new(),
+        };
+        resolving.push(name);
+        let converted = convert(definition, definitions, resolving);
+        resolving.pop();
+        return converted;
+    }
+
+    let explicit_type = schema.get("bsonType").cloned();
+    let mut converted = Document::new();
+    for (key, value) in schema {
+        match key.as_str() {
+            key if UNSUPPORTED.contains(&key) => {}
+            "bsonType" => {}
+            "type" => {
+                if explicit_type.is_none() {
+                    converted.insert("bsonType", bson_types(&value));
+                }
+            }
+            "const" => {
+                converted.insert("enum", vec![value]);
+            }
+            "properties" | "patternProperties" => {
+                if let Bson::Document(properties) = value {
+                    let properties: Document = properties
+                        .into_iter()
+                        .map(|(name, property)| {
+                            (name, convert_bson(property, definitions, resolving))
+                        })
+                        .collect();
+                    converted.insert(key, properties);
+                }
+            }
+            "items" | "additionalItems" | "additionalProperties" | "not" => {
+                converted.insert(key, convert_bson(value, definitions, resolving));
+            }
+            "allOf" | "anyOf" | "oneOf" => {
+                converted.insert(key, convert_bson(value, definitions, resolving));
+            }
+            _ => {
+                converted.insert(key, value);
+            }
+        }
+    }
+    if let Some(bson_type) = explicit_type {
+        converted.insert("bsonType", bson_type);
+    }
+    converted
+}
+
+fn convert_bson(value: Bson, definitions: &Document, resolving: &mut Vec<String>) -> Bson {
+    match value {
+        Bson::Document(schema) => Bson::Document(convert(schema, definitions, resolving)),
+        Bson::Array(schemas) => Bson::Array(
+            schemas
+                .into_iter()
+                .map(|schema| convert_bson(schema, definitions, resolving))
+                .collect(),
+        ),
+        other => other,
+    }
+}
+
+fn bson_types(json_type: &Bson) -> Bson {
+    let json_types = match json_type {
+        Bson::String(json_type) => vec![json_type.as_str()],
+        Bson::Array(json_types) => json_types.iter().filter_map(Bson::as_str).collect(),
+        _ => return json_type.clone(),
+    };
+    let mut bson_types: Vec<&str> = Vec::new();
+    for json_type in json_types {
+        let mapped: &[&str] = match json_type {
+            // The driver writes narrow integers as int and wide ones as long, and existing data
+            // may hold either, so both are accepted.
+            "integer" => &["int", "long"],
+            "number" => &["number"],
+            "boolean" => &["bool"],
+            "string" => &["string"],
+            "object" => &["object"],
+            "array" => &["array"],
+            "null" => &["null"],
+            other => {
+                bson_types.push(other);
+                continue;
+            }
+        };
+        for bson_type in mapped {
+            if !bson_types.contains(bson_type) {
+                bson_types.push(bson_type);
+            }
+        }
+    }
+    match bson_types.as_slice() {
+        [single] => Bson::String(single.to_string()),
+        _ => Bson::Array(bson_types.into_iter().map(Bson::from).collect()),
+    }
+}
+
+macro_rules! bson_type_schema {
+    ($(#[$doc:meta])* $name:ident, $schema_name:literal, $bson_type:literal) => {
+        $(#[$doc])*
+        pub struct $name;
+
+        impl JsonSchema for $name {
+            fn is_referenceable() -> bool {
+                false
+            }
+
+            fn schema_name() -> String {
+                $schema_name.to_string()
+            }
+
+            fn json_schema(_: &mut SchemaGenerator) -> Schema {
+                let mut schema = SchemaObject::default();
+                schema
+                    .extensions
+                    .insert("bsonType".to_string(), $bson_type.into());
+                schema.into()
+            }
+        }
+    };
+}
+
+bson_type_schema!(
+    /// Use with `#[schemars(with = "mongo_tracing::schema::ObjectIdSchema")]` on `ObjectId`
+    /// fields.
+    ObjectIdSchema,
+    "ObjectId",
+    "objectId"
+);
+bson_type_schema!(
+    /// Use with `#[schemars(with = "mongo_tracing::schema::DateTimeSchema")]` on BSON `DateTime`
+    /// fields.
+    DateTimeSchema,
+    "DateTime",
+    "date"
+);
+bson_type_schema!(
+    /// Use with `#[schemars(with = "mongo_tracing::schema::Decimal128Schema")]` on `Decimal128`
+    /// fields.
+    Decimal128Schema,
+    "Decimal128",
+    "decimal"
+);
+bson_type_schema!(
+    /// Use with `#[schemars(with = "mongo_tracing::schema::BinarySchema")]` on `Binary`
+    /// fields.
+    BinarySchema,
+    "Binary",
+    "binData"
+);
+bson_type_schema!(
+    /// Use with `#[schemars(with = "mongo_tracing::schema::TimestampSchema")]` on `Timestamp`
+    /// fields.
+    TimestampSchema,
+    "Timestamp",
+    "timestamp"
+);
+
+/// Paths of the `$jsonSchema` properties that differ between two validators, `$` being the
+/// document itself and `[]` the items of an array.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ValidatorDiff {
+    pub created: bool,
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub changed: Vec<String>,
+}
+
+impl ValidatorDiff {
+    pub fn is_empty(&self) -> bool {
+        !self.created && self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+pub fn diff(current: Option<&Document>, wanted: &Document) -> ValidatorDiff {
+    let mut before = BTreeMap::new();
+    if let Some(schema) = current.and_then(|v| v.get_document("$jsonSchema").ok()) {
+        flatten(schema, "$", true, &mut before);
+    }
+    let mut after = BTreeMap::new();
+    if let Ok(schema) = wanted.get_document("$jsonSchema") {
+        flatten(schema, "$", true, &mut after);
+    }
+
+    let mut diff = ValidatorDiff::default();
+    for (path, node) in &after {
+        match before.get(path) {
+            None => diff.added.push(path.clone()),
+            Some(previous) if previous != node => diff.changed.push(path.clone()),
+            Some(_) => {}
+        }
+    }
+    diff.removed = before
+        .keys()
+        .filter(|path| !after.contains_key(*path))
+        .cloned()
+        .collect();
+    diff
+}
+
+fn flatten(schema: &Document, path: &str, required: bool, nodes: &mut BTreeMap<String, Document>) {
+    let mut node: Document = schema
+        .iter()
+        .filter(|(key, _)| !matches!(key.as_str(), "properties" | "items" | "required"))
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect();
+    node.insert("required", required);
+    nodes.insert(path.to_string(), node);
+
+    let required_fields: Vec<&str> = schema
+        .get_array("required")
+        .map(|fields| fields.iter().filter_map(Bson::as_str).collect())
+        .unwrap_or_default();
+    if let Ok(properties) = schema.get_document("properties") {
+        for (name, property) in properties {
+            if let Bson::Document(property) = property {
+                let child = format!("{}.{}", path, name);
+                flatten(
+                    property,
+                    &child,
+                    required_fields.contains(&name.as_str()),
+                    nodes,
+                );
+            }
+        }
+    }
+    // `Option<Struct>` and tagged enums nest their properties in the non-null branches.
+    for branch in ["anyOf", "oneOf"]
+        .iter()
+        .filter_map(|key| schema.get_array(key).ok())
+        .flatten()
+    {
+        if let Bson::Document(branch) = branch {
+            if branch.contains_key("properties") {
+                let mut children = BTreeMap::new();
+                flatten(branch, path, required, &mut children);
+                children.remove(path);
+                nodes.extend(children);
+            }
+        }
+    }
+    if let Ok(items) = schema.get_document("items") {
+        flatten(items, &format!("{}[]", path), true, nodes);
+    }
+}
+
+impl<T> InstrumentedCollection<T>
+where
+    T: JsonSchema,
+{
+    #[instrument(
+    fields(
+    db.name = % self.info.database_name ,
+    db.system = "mongodb",
+    db.collection = % self.inner.name(),
+    otel.kind = "client",
+    tenant.id = self.info.tenant.as_deref(),
+    ),
+    skip(self)
+    )]
+    pub async fn validator_diff(&self) -> Result<ValidatorDiff> {
+        let wanted = validator::<T>();
+        let current = self.current_validator().await?;
+        let mut diff = diff(current.as_ref().and_then(Option::as_ref), &wanted);
+        diff.created = current.is_none();
+        Ok(diff)
+    }
+
+    /// Creates the collection with the validator derived from `T`, or `collMod`s an existing one
+    /// when its validator differs.
+    #[instrument(
+    fields(
+    db.name = % self.info.database_name ,
+    db.system = "mongodb",
+    db.collection = % self.inner.name(),
+    otel.kind = "client",
+    tenant.id = self.info.tenant.as_deref(),
+    ),
+    skip(self)
+    )]
+    pub async fn apply_validator(&self) -> Result<ValidatorDiff> {
+        let wanted = validator::<T>();
+        let current = self.current_validator().await?;
+        let mut diff = diff(current.as_ref().and_then(Option::as_ref), &wanted);
+        match current {
+            None => {
+                diff.created = true;
+                self.info
+                    .database
+                    .create_collection(
+                        self.inner.name(),
+                        CreateCollectionOptions::builder().validator(wanted).build(),
+                    )
+                    .await?;
+            }
+            Some(current) => {
+                if current.as_ref() != Some(&wanted) {
+                    self.info
+                        .database
+                        .run_command(
+                            doc! { "collMod": self.inner.name(), "validator": wanted },
+                            None,
+                        )
+                        .await?;
+                }
+            }
+        }
+        Ok(diff)
+    }
+
+    /// `None` when the collection does not exist, `Some(None)` when it has no validator.
+    async fn current_validator(&self) -> Result<Option<Option<Document>>> {
+        let specification = self
+            .info
+            .database
+            .list_collections(doc! { "name": self.inner.name() }, None)
+            .await?
+            .try_next()
+            .await?;
+        Ok(specification.map(|specification| specification.options.validator))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+
+    #[allow(dead_code)]
+    #[derive(Deserialize, Serialize, JsonSchema)]
+    struct Address {
+        city: String,
+    }
+
+    #[allow(dead_code)]
+    #[derive(JsonSchema)]
+    struct Node {
+        children: Vec<Node>,
+    }
+
+    #[allow(dead_code)]
+    #[derive(JsonSchema)]
+    struct User {
+        #[schemars(with = "ObjectIdSchema")]
+        id: String,
+        name: String,
+        age: u32,
+        nickname: Option<String>,
+        address: Address,
+        tags: Vec<String>,
+        tree: Node,
+    }
+
+    fn property<'a>(schema: &'a Document, name: &str) -> &'a Document {
+        schema
+            .get_document("properties")
+            .and_then(|properties| properties.get_document(name))
+            .unwrap()
+    }
+
+    #[test]
+    fn json_schema_maps_json_types_to_bson_types() {
+        let schema = json_schema::<User>();
+        assert_eq!(schema.get_str("bsonType"), Ok("object"));
+        assert!(!schema.contains_key("$schema"));
+        assert_eq!(property(&schema, "id"), &doc! { "bsonType": "objectId" });
+        assert_eq!(
+            property(&schema, "age").get("bsonType"),
+            Some(&Bson::from(vec!["int", "long"]))
+        );
+        assert!(!property(&schema, "age").contains_key("format"));
+        assert_eq!(
+            property(&schema, "nickname"),
+            &doc! { "bsonType": ["string", "null"] }
+        );
+        assert_eq!(
+            property(&schema, "tags"),
+            &doc! { "bsonType": "array", "items": { "bsonType": "string" } }
+        );
+    }
+
+    #[test]
+    fn json_schema_inlines_references() {
+        let schema = json_schema::<User>();
+        assert!(!schema.contains_key("definitions"));
+        assert_eq!(
+            property(&schema, "address"),
+            &doc! {
+                "bsonType": "object",
+                "required": ["city"],
+                "properties": { "city": { "bsonType": "string" } },
+            }
+        );
+        // The recursion stops at the first repeated reference.
+        assert_eq!(
+            property(property(&schema, "tree"), "children"),
+            &doc! { "bsonType": "array", "items": { "bsonType": "object" } }
+        );
+    }
+
+    #[test]
+    fn diff_of_identical_validators_is_empty() {
+        let wanted = validator::<User>();
+        assert!(diff(Some(&wanted), &wanted).is_empty());
+    }
+
+    #[test]
+    fn diff_reports_paths() {
+        let current = doc! { "$jsonSchema": {
+            "bsonType": "object",
+            "required": ["name", "age"],
+            "properties": {
+                "name": { "bsonType": "string" },
+                "age": { "bsonType": "int" },
+                "tags": { "bsonType": "array", "items": { "bsonType": "string" } },
+            },
+        } };
+        let wanted = doc! { "$jsonSchema": {
+            "bsonType": "object",
+            "required": ["name"],
+            "properties": {
+                "name": { "bsonType": "string" },
+                "age": { "bsonType": "int" },
+                "tags": { "bsonType": "array", "items": { "bsonType": "int" } },
+                "email": { "bsonType": "string" },
+            },
+        } };
+        let diff = diff(Some(&current), &wanted);
+        assert_eq!(diff.added, vec!["$.email"]);
+        assert_eq!(diff.changed, vec!["$.age", "$.tags[]"]);
+        assert!(diff.removed.is_empty());
+
+        let diff = super::diff(Some(&wanted), &current);
+        assert_eq!(diff.removed, vec!["$.email"]);
+    }
+
+    #[test]
+    fn diff_without_current_validator_adds_everything() {
+        let diff = diff(None, &validator::<Address>());
+        assert_eq!(diff.added, vec!["$", "$.city"]);
+    }
+}
@@ -0,0 +1,445 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use mongodb::bson::{Bson, Document};
+use mongodb::error::{Error, Result};
+use mongodb::options::{
+    AggregateOptions, CountOptions, DeleteOptions, DistinctOptions, EstimatedDocumentCountOptions,
+    FindOneOptions, FindOptions, InsertManyOptions, InsertOneOptions, ReplaceOptions,
+    UpdateModifications, UpdateOptions,
+};
+use mongodb::results::{DeleteResult, InsertManyResult, InsertOneResult, UpdateResult};
+use mongodb::Namespace;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tower_layer::Layer;
+use tower_service::Service;
+use tracing::instrument::Instrumented;
+use tracing::{Instrument, Span};
+
+use crate::cursor::InstrumentedCursor;
+use crate::interceptor::OperationKind;
+use crate::mongo_tracing::InstrumentedCollection;
+
+/// A collection operation as a value, for use with [`MongoService`].
+#[derive(Debug)]
+pub enum MongoRequest<T> {
+    Find {
+        filter: Option<Document>,
+        options: Option<FindOptions>,
+    },
+    FindOne {
+        filter: Option<Document>,
+        options: Option<FindOneOptions>,
+    },
+    InsertOne {
+        document: T,
+        options: Option<InsertOneOptions>,
+    },
+    InsertMany {
+        documents: Vec<T>,
+        options: Option<InsertManyOptions>,
+    },
+    ReplaceOne {
+        query: Document,
+        replacement: T,
+        options: Option<ReplaceOptions>,
+    },
+    UpdateOne {
+        query: Document,
+        update: UpdateModifications,
+        options: Option<UpdateOptions>,
+    },
+    UpdateMany {
+        query: Document,
+        update: UpdateModifications,
+        options: Option<UpdateOptions>,
+    },
+    DeleteOne {
+        query: Document,
+        options: Option<DeleteOptions>,
+    },
+    DeleteMany {
+        query: Document,
+        options: Option<DeleteOptions>,
+    },
+    CountDocuments {
+        filter: Option<Document>,
+        options: Option<CountOptions>,
+    },
+    EstimatedDocumentCount {
+        options: Option<EstimatedDocumentCountOptions>,
+    },
+    Aggregate {
+        pipeline: Vec<Document>,
+        options: Option<AggregateOptions>,
+    },
+    Distinct {
+        field_name: String,
+        filter: Option<Document>,
+        options: Option<DistinctOptions>,
+    },
+}
+
+impl<T> MongoRequest<T> {
+    pub fn kind(&self) -> OperationKind {
+        match self {
+            MongoRequest::Find { .. } => OperationKind::Find,
+            MongoRequest::FindOne { .. } => OperationKind::FindOne,
+            MongoRequest::InsertOne { .. } => OperationKind::InsertOne,
+            MongoRequest::InsertMany { .. } => OperationKind::InsertMany,
+            MongoRequest::ReplaceOne { .. } => OperationKind::ReplaceOne,
+            MongoRequest::UpdateOne { .. } => OperationKind::UpdateOne,
+            MongoRequest::UpdateMany { .. } => OperationKind::UpdateMany,
+            MongoRequest::DeleteOne { .. } => OperationKind::DeleteOne,
+            MongoRequest::DeleteMany { .. } => OperationKind::DeleteMany,
+            MongoRequest::CountDocuments { .. } => OperationKind::CountDocuments,
+            MongoRequest::EstimatedDocumentCount { .. } => OperationKind::EstimatedDocumentCount,
+            MongoRequest::Aggregate { .. } => OperationKind::Aggregate,
+            MongoRequest::Distinct { .. } => OperationKind::Distinct,
+        }
+    }
+}
+
+/// The result of a [`MongoRequest`], one variant per result type.
+#[derive(Debug)]
+pub enum MongoResponse<T> {
+    Find(InstrumentedCursor<T>),
+    FindOne(Option<T>),
+    InsertOne(InsertOneResult),
+    InsertMany(InsertManyResult),
+    Update(UpdateResult),
+    Delete(DeleteResult),
+    Count(u64),
+    Aggregate(InstrumentedCursor<Document>),
+    Distinct(Vec<Bson>),
+}
+
+/// Serves [`MongoRequest`]s against a collection, running its interceptors.
+///
+/// It creates no spans of its own: wrap it in [`MongoTracingLayer`] at the point of the stack
+/// where the operation span should start.
+pub struct MongoService<T> {
+    collection: InstrumentedCollection<T>,
+}
+
+impl<T> Clone for MongoService<T> {
+    fn clone(&self) -> Self {
+        MongoService {
+            collection: self.collection.clone(),
+        }
+    }
+}
+
+impl<T> MongoService<T> {
+    pub fn new(collection: InstrumentedCollection<T>) -> Self {
+        MongoService { collection }
+    }
+}
+
+impl<T> Service<MongoRequest<T>> for MongoService<T>
+where
+    T: Serialize + DeserializeOwned + Unpin + Send + Sync + 'static,
+{
+    type Response = MongoResponse<T>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<MongoResponse<T>>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: MongoRequest<T>) -> Self::Future {
+        let collection = self.collection.clone();
+        Box::pin(async move { collection.dispatch(request).await })
+    }
+}
+
+impl<T> InstrumentedCollection<T>
+where
+    T: Serialize + DeserializeOwned + Unpin + Send + Sync,
+{
+    /// This collection as a tower service, traced like the collection methods.
+    pub fn service(&self) -> MongoTracing<MongoService<T>> {
+        self.tracing_layer().layer(MongoService::new(self.clone()))
+    }
+
+    pub fn tracing_layer(&self) -> MongoTracingLayer {
+        MongoTracingLayer {
+            namespace: self.inner.namespace(),
+            tenant: self.info.tenant.clone(),
+        }
+    }
+
+    async fn dispatch(&self, request: MongoRequest<T>) -> Result<MongoResponse<T>> {
+        match request {
+            MongoRequest::Find { filter, options } => self
+                .run_find(filter, options)
+                .await
+                .map(MongoResponse::Find),
+            MongoRequest::FindOne { filter, options } => self
+                .run_find_one(filter, options)
+                .await
+                .map(MongoResponse::FindOne),
+            MongoRequest::InsertOne { document, options } => self
+                .run_insert_one(&document, options)
+                .await
+                .map(MongoResponse::InsertOne),
+            MongoRequest::InsertMany { documents, options } => self
+                .run_insert_many(&documents, options)
+                .await
+                .map(MongoResponse::InsertMany),
+            MongoRequest::ReplaceOne {
+                query,
+                replacement,
+                options,
+            } => self
+                .run_replace_one(query, &replacement, options)
+                .await
+                .map(MongoResponse::Update),
+            MongoRequest::UpdateOne {
+                query,
+                update,
+                options,
+            } => self
+                .run_update_one(query, None, update, options)
+                .await
+                .map(MongoResponse::Update),
+            MongoRequest::UpdateMany {
+                query,
+                update,
+                options,
+            } => self
+                .run_update_many(query, None, update, options)
+                .await
+                .map(MongoResponse::Update),
+            MongoRequest::DeleteOne { query, options } => self
+                .run_delete_one(query, None, options)
+                .await
+                .map(MongoResponse::Delete),
+            MongoRequest::DeleteMany { query, options } => self
+                .run_delete_many(query, None, options)
+                .await
+                .map(MongoResponse::Delete),
+            MongoRequest::CountDocuments { filter, options } => self
+                .run_count_documents(filter, options)
+                .await
+                .map(MongoResponse::Count),
+            MongoRequest::EstimatedDocumentCount { options } => self
+                .run_estimated_document_count(options)
+                .await
+                .map(MongoResponse::Count),
+            MongoRequest::Aggregate { pipeline, options } => self
+                .run_aggregate(pipeline, options)
+                .await
+                .map(MongoResponse::Aggregate),
+            MongoRequest::Distinct {
+                field_name,
+                filter,
+                options,
+            } => self
+                .run_distinct(&field_name, filter, options)
+                .await
+                .map(MongoResponse::Distinct),
+        }
+    }
+}
+
+/// Opens the same span the collection methods do around each request of the wrapped service.
+#[derive(Clone, Debug)]
+pub struct MongoTracingLayer {
+    namespace: Namespace,
+    tenant: Option<String>,
+}
+
+impl MongoTracingLayer {
+    pub fn new(namespace: Namespace) -> Self {
+        MongoTracingLayer {
+            namespace,
+            tenant: None,
+        }
+    }
+}
+
+impl<S> Layer<S> for MongoTracingLayer {
+    type Service = MongoTracing<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        MongoTracing {
+            inner,
+            namespace: self.namespace.clone(),
+            tenant: self.tenant.clone(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct MongoTracing<S> {
+    inner: S,
+    namespace: Namespace,
+    tenant: Option<String>,
+}
+
+impl<S, T> Service<MongoRequest<T>> for MongoTracing<S>
+where
+    S: Service<MongoRequest<T>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Instrumented<S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<std::result::Result<(), S::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: MongoRequest<T>) -> Self::Future {
+        let span = request_span(request.kind(), &self.namespace, self.tenant.as_deref());
+        let future = span.in_scope(|| self.inner.call(request));
+        future.instrument(span)
+    }
+}
+
+/// A span with the fields the collection method for `kind` declares in its `#[instrument]`, so
+/// what the service records on it is kept.
+fn request_span(kind: OperationKind, namespace: &Namespace, tenant: Option<&str>) -> Span {
+    use tracing::field::Empty;
+
+    macro_rules! span {
+        ($name:literal $(, $($field:ident).+ = $value:expr)* $(,)?) => {
+            tracing::info_span!(
+                $name,
+                db.name = %namespace.db,
+                db.system = "mongodb",
+                db.collection = %namespace.coll,
+                otel.kind = "client",
+                tenant.id = tenant,
+                policy.violation = Empty,
+                db.request.size = Empty,
+                db.response.size = Empty,
+                $($($field).+ = $value,)*
+            )
+        };
+    }
+    match kind {
+        OperationKind::Find => span!("find", db.projection = Empty, db.decode.failures = Empty),
+        OperationKind::FindOne => span!("find_one", db.projection = Empty, cache.hit = Empty),
+        OperationKind::FindOneAndDelete => span!("find_one_and_delete"),
+        OperationKind::FindOneAndUpdate => span!("find_one_and_update"),
+        OperationKind::FindOneAndReplace => span!("find_one_and_replace"),
+        OperationKind::InsertOne => span!("insert_one"),
+        OperationKind::InsertMany => span!("insert_many"),
+        OperationKind::ReplaceOne => span!("replace_one"),
+        OperationKind::UpdateOne => span!("update_one"),
+        OperationKind::UpdateMany => span!("update_many"),
+        OperationKind::DeleteOne => span!("delete_one", db.delete.mode = "hard"),
+        OperationKind::DeleteMany => span!("delete_many", db.delete.mode = "hard"),
+        OperationKind::Aggregate => span!(
+            "aggregate",
+            db.pipeline.stages = Empty,
+            db.pipeline.stage_count = Empty,
+            db.pipeline.collections = Empty,
+            db.pipeline.write = Empty,
+            db.decode.failures = Empty,
+        ),
+        OperationKind::CountDocuments => span!("count_documents"),
+        OperationKind::EstimatedDocumentCount => span!("estimated_document_count"),
+        OperationKind::Distinct => span!("distinct"),
+        OperationKind::Drop => span!("drop"),
+        OperationKind::CreateIndex => span!("create_index"),
+        OperationKind::CreateIndexes => span!("create_indexes"),
+        OperationKind::DropIndex => span!("drop_index"),
+        OperationKind::DropIndexes => span!("drop_indexes"),
+        OperationKind::ListIndexes => span!("list_indexes"),
+        OperationKind::ListIndexNames => span!("list_index_names"),
+        OperationKind::Watch => span!("watch"),
+        OperationKind::Explain => span!("explain"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::{ready, Ready};
+
+    use mongodb::bson::doc;
+
+    use super::*;
+    use crate::testing::Spans;
+
+    /// Records on the current span what the collection methods record for `kind`.
+    struct Recording;
+
+    impl Service<MongoRequest<Document>> for Recording {
+        type Response = ();
+        type Error = Error;
+        type Future = Ready<Result<()>>;
+
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, request: MongoRequest<Document>) -> Self::Future {
+            let span = Span::current();
+            span.record("db.response.size", 42_u64);
+            match request.kind() {
+                OperationKind::FindOne => {
+                    span.record("cache.hit", true);
+                }
+                OperationKind::Aggregate => {
+                    span.record("db.pipeline.stage_count", 1_u64);
+                }
+                _ => {}
+            }
+            ready(Ok(()))
+        }
+    }
+
+    #[tokio::test]
+    async fn requests_get_the_spans_of_the_collection_methods() {
+        let spans = Spans::default();
+        let _default = tracing::subscriber::set_default(spans.clone());
+        let namespace = Namespace {
+            db: "app".to_string(),
+            coll: "orders".to_string(),
+        };
+        let mut service = MongoTracingLayer::new(namespace).layer(Recording);
+        let requests = vec![
+            MongoRequest::DeleteOne {
+                query: doc! { "_id": 1 },
+                options: None,
+            },
+            MongoRequest::DeleteMany {
+                query: doc! {},
+                options: None,
+            },
+            MongoRequest::FindOne {
+                filter: None,
+                options: None,
+            },
+            MongoRequest::Aggregate {
+                pipeline: vec![doc! { "$match": {} }],
+                options: None,
+            },
+        ];
+        for request in requests {
+            service.call(request).await.unwrap();
+        }
+
+        let spans = spans.spans.lock().unwrap();
+        let names: Vec<_> = spans.iter().map(|(metadata, _)| metadata.name()).collect();
+        assert_eq!(
+            names,
+            ["delete_one", "delete_many", "find_one", "aggregate"]
+        );
+        for (_, fields) in spans.iter() {
+            assert_eq!(fields["db.collection"].as_deref(), Some("orders"));
+            assert_eq!(fields["db.response.size"].as_deref(), Some("42"));
+        }
+        assert_eq!(spans[0].1["db.delete.mode"].as_deref(), Some("hard"));
+        assert_eq!(spans[1].1["db.delete.mode"].as_deref(), Some("hard"));
+        assert_eq!(spans[2].1["cache.hit"].as_deref(), Some("true"));
+        assert!(!spans[2].1.contains_key("db.pipeline.stages"));
+        assert_eq!(spans[3].1["db.pipeline.stage_count"].as_deref(), Some("1"));
+        assert!(!spans[3].1.contains_key("db.delete.mode"));
+    }
+}
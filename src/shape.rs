@@ -0,0 +1,86 @@
+use mongodb::bson::{Bson, Document};
+
+/// `document` with every value replaced by `"?"`, keeping field names and operators.
+pub(crate) fn redact(document: &Document) -> Document {
+    document
+        .iter()
+        .map(|(key, value)| (key.clone(), redact_value(value)))
+        .collect()
+}
+
+pub(crate) fn redact_value(value: &Bson) -> Bson {
+    match value {
+        Bson::Document(document) => Bson::Document(redact(document)),
+        Bson::Array(values) => Bson::Array(values.iter().map(redact_value).collect()),
+        _ => Bson::String("?".to_string()),
+    }
+}
+
+/// The structure of a filter or stage regardless of its values: like [`redact`], but lists of
+/// plain values such as `$in` operands collapse to a single `"?"`.
+pub(crate) fn shape(document: &Document) -> Document {
+    document
+        .iter()
+        .map(|(key, value)| (key.clone(), shape_value(value)))
+        .collect()
+}
+
+fn shape_value(value: &Bson) -> Bson {
+    match value {
+        Bson::Document(document) => Bson::Document(shape(document)),
+        Bson::Array(values) if values.iter().any(|value| value.as_document().is_some()) => {
+            Bson::Array(values.iter().map(shape_value).collect())
+        }
+        _ => Bson::String("?".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mongodb::bson::doc;
+
+    use super::*;
+
+    #[test]
+    fn shapes_keep_fields_and_operators() {
+        let cases = [
+            (doc! { "a": 1 }, doc! { "a": "?" }),
+            (
+                doc! { "a": { "$gt": 1, "$lt": 5 }, "b.c": "x" },
+                doc! { "a": { "$gt": "?", "$lt": "?" }, "b.c": "?" },
+            ),
+            (
+                doc! { "a": { "$in": [1, 2, 3] } },
+                doc! { "a": { "$in": "?" } },
+            ),
+            (
+                doc! { "$or": [{ "a": 1 }, { "b": { "$in": ["x"] } }] },
+                doc! { "$or": [{ "a": "?" }, { "b": { "$in": "?" } }] },
+            ),
+            (
+                doc! { "$group": { "_id": "$a", "n": { "$sum": 1 } } },
+                doc! { "$group": { "_id": "?", "n": { "$sum": "?" } } },
+            ),
+        ];
+        for (document, expected) in cases {
+            assert_eq!(shape(&document), expected, "{document}");
+        }
+    }
+
+    #[test]
+    fn filters_differing_only_in_values_share_a_shape() {
+        assert_eq!(
+            shape(&doc! { "a": { "$in": [1] }, "b": null }),
+            shape(&doc! { "a": { "$in": [1, 2, 3] }, "b": "x" })
+        );
+        assert_ne!(shape(&doc! { "a": 1 }), shape(&doc! { "b": 1 }));
+    }
+
+    #[test]
+    fn redacting_keeps_every_element() {
+        assert_eq!(
+            redact(&doc! { "a": { "$in": [1, 2] }, "b": [{ "c": 1 }] }),
+            doc! { "a": { "$in": ["?", "?"] }, "b": [{ "c": "?" }] }
+        );
+    }
+}
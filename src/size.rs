@@ -0,0 +1,119 @@
+use std::borrow::Borrow;
+
+use mongodb::bson::{from_slice, to_raw_document_buf, Document, RawDocumentBuf};
+use mongodb::error::Result;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::error::Error;
+
+/// The server's limit on the size of a single document.
+pub(crate) const MAX_DOCUMENT_SIZE: usize = 16 * 1024 * 1024;
+
+/// Documents from this size on are reported as approaching the limit.
+const WARNING_SIZE: usize = MAX_DOCUMENT_SIZE / 16 * 15;
+
+/// The BSON size of `document` in bytes.
+pub(crate) fn of(document: &Document) -> usize {
+    let mut bytes = Vec::new();
+    match document.to_writer(&mut bytes) {
+        Ok(()) => bytes.len(),
+        Err(_) => 0,
+    }
+}
+
+/// Warns, in the current span, about a document of `size` bytes close to the limit.
+pub(crate) fn check(size: usize) {
+    if size >= WARNING_SIZE {
+        tracing::warn!(
+            db.document.size = size as u64,
+            "document of {} bytes is close to the {} bytes BSON limit",
+            size,
+            MAX_DOCUMENT_SIZE
+        );
+    }
+}
+
+/// Serializes a document about to be sent, checking its size.
+pub(crate) fn sent(document: &impl Serialize) -> Result<RawDocumentBuf> {
+    let document = to_raw_document_buf(document)?;
+    check(document.as_bytes().len());
+    Ok(document)
+}
+
+/// Serializes the documents of an insert, failing on the first one over the limit.
+pub(crate) fn sent_all<D: Serialize>(
+    documents: impl IntoIterator<Item = impl Borrow<D>>,
+) -> Result<Vec<RawDocumentBuf>> {
+    documents
+        .into_iter()
+        .enumerate()
+        .map(|(index, document)| {
+            let document = to_raw_document_buf(document.borrow())?;
+            let size = document.as_bytes().len();
+            if size > MAX_DOCUMENT_SIZE {
+                return Err(Error::DocumentTooLarge { index, size }.into());
+            }
+            check(size);
+            Ok(document)
+        })
+        .collect()
+}
+
+/// The number of batches of at most `batch_size` documents holding `documents` documents.
+pub(crate) fn batch_count(documents: usize, batch_size: usize) -> usize {
+    documents.div_ceil(batch_size.max(1))
+}
+
+/// Records the size of a returned document on the current span.
+pub(crate) fn received_raw(document: Option<RawDocumentBuf>) -> Option<RawDocumentBuf> {
+    if let Some(document) = &document {
+        let size = document.as_bytes().len();
+        tracing::Span::current().record("db.response.size", size as u64);
+        check(size);
+    }
+    document
+}
+
+/// Records the size of a returned document on the current span, then deserializes it.
+pub(crate) fn received<T: DeserializeOwned>(document: Option<RawDocumentBuf>) -> Result<Option<T>> {
+    match received_raw(document) {
+        Some(document) => Ok(Some(from_slice(document.as_bytes())?)),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mongodb::bson::doc;
+
+    use super::*;
+
+    #[test]
+    fn sending_reports_the_first_document_over_the_limit() {
+        let small = doc! { "a": 1 };
+        let large = doc! { "blob": "x".repeat(MAX_DOCUMENT_SIZE) };
+        let expected = to_raw_document_buf(&large).unwrap().as_bytes().len();
+
+        let error = sent_all::<Document>([&small, &large, &large]).unwrap_err();
+
+        assert_eq!(
+            Error::from_mongo(&error),
+            Some(&Error::DocumentTooLarge {
+                index: 1,
+                size: expected
+            })
+        );
+        assert!(expected > MAX_DOCUMENT_SIZE);
+        assert_eq!(sent_all::<Document>([&small, &small]).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn batches_round_up_and_hold_at_least_one_document() {
+        assert_eq!(batch_count(0, 10), 0);
+        assert_eq!(batch_count(10, 10), 1);
+        assert_eq!(batch_count(11, 10), 2);
+        assert_eq!(batch_count(3, 1), 3);
+        assert_eq!(batch_count(3, 0), 3);
+    }
+}
@@ -0,0 +1,444 @@
+use std::borrow::Borrow;
+
+use mongodb::bson::{doc, Bson, DateTime, Document};
+use mongodb::error::Result;
+use mongodb::options::{
+    AggregateOptions, CountOptions, DeleteOptions, DistinctOptions, FindOneOptions, FindOptions,
+    ReplaceOptions, UpdateModifications, UpdateOptions,
+};
+use mongodb::results::UpdateResult;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tracing::instrument;
+
+use crate::cursor::InstrumentedCursor;
+use crate::mongo_tracing::InstrumentedCollection;
+use crate::query;
+use crate::results::DeleteResult;
+
+/// A view of an [`InstrumentedCollection`] where deleting a document sets its `deletedAt` field
+/// instead of removing it, and soft-deleted documents are left out of every read and update.
+///
+/// Soft deletes run as updates in a `delete_one`/`delete_many` span with
+/// `db.delete.mode = "soft"`, and count the documents they marked as deleted; deletes reaching
+/// the server are marked `"hard"`. Interceptors see
+/// the caller's filter as [`Operation::requested_filter`], so a policy still rejects
+/// `delete_many` with an empty filter.
+///
+/// [`Operation::requested_filter`]: crate::interceptor::Operation::requested_filter
+pub struct SoftDeleteCollection<T> {
+    inner: InstrumentedCollection<T>,
+    field: String,
+}
+
+impl<T> InstrumentedCollection<T> {
+    /// Soft-deletes through the `deletedAt` field unless changed with
+    /// [`SoftDeleteCollection::deleted_field`].
+    pub fn soft_delete(&self) -> SoftDeleteCollection<T> {
+        SoftDeleteCollection {
+            inner: self.clone(),
+            field: "deletedAt".to_string(),
+        }
+    }
+}
+
+impl<T> Clone for SoftDeleteCollection<T> {
+    fn clone(&self) -> Self {
+        SoftDeleteCollection {
+            inner: self.inner.clone(),
+            field: self.field.clone(),
+        }
+    }
+}
+
+impl<T> SoftDeleteCollection<T> {
+    pub fn deleted_field(mut self, field: impl Into<String>) -> Self {
+        self.field = field.into();
+        self
+    }
+
+    /// The underlying collection, including soft-deleted documents.
+    pub fn with_deleted(&self) -> &InstrumentedCollection<T> {
+        &self.inner
+    }
+
+    fn live(&self, filter: impl Into<Option<Document>>) -> Document {
+        live(&self.field, filter.into())
+    }
+
+    fn mark_deleted(&self) -> Document {
+        doc! { "$set": { &self.field: DateTime::now() } }
+    }
+
+    pub async fn find(
+        &self,
+        filter: impl Into<Option<Document>>,
+        options: impl Into<Option<FindOptions>>,
+    ) -> Result<InstrumentedCursor<T>> {
+        self.inner.find(self.live(filter), options).await
+    }
+
+    pub async fn count_documents(
+        &self,
+        filter: impl Into<Option<Document>>,
+        options: impl Into<Option<CountOptions>>,
+    ) -> Result<u64> {
+        self.inner.count_documents(self.live(filter), options).await
+    }
+
+    pub async fn distinct(
+        &self,
+        field_name: impl AsRef<str>,
+        filter: impl Into<Option<Document>>,
+        options: impl Into<Option<DistinctOptions>>,
+    ) -> Result<Vec<Bson>> {
+        self.inner
+            .distinct(field_name, self.live(filter), options)
+            .await
+    }
+
+    pub async fn aggregate(
+        &self,
+        pipeline: impl IntoIterator<Item = Document>,
+        options: impl Into<Option<AggregateOptions>>,
+    ) -> Result<InstrumentedCursor<Document>> {
+        let pipeline = live_pipeline(&self.field, pipeline.into_iter().collect());
+        self.inner.aggregate(pipeline, options).await
+    }
+
+    #[instrument(
+    name = "update_one",
+    fields(
+    db.name = % self.inner.info.database_name ,
+    db.system = "mongodb",
+    db.collection = % self.inner.name(),
+    otel.kind = "client",
+    tenant.id = self.inner.info.tenant.as_deref(),
+    policy.violation = tracing::field::Empty,
+    db.request.size = tracing::field::Empty,
+    db.response.size = tracing::field::Empty,
+    ),
+    skip(self,query,update,options)
+    )]
+    pub async fn update_one(
+        &self,
+        query: Document,
+        update: impl Into<UpdateModifications>,
+        options: impl Into<Option<UpdateOptions>>,
+    ) -> Result<UpdateResult> {
+        self.inner
+            .run_update_one(
+                self.live(query.clone()),
+                Some(&query),
+                update.into(),
+                options.into(),
+            )
+            .await
+    }
+
+    #[instrument(
+    name = "update_many",
+    fields(
+    db.name = % self.inner.info.database_name ,
+    db.system = "mongodb",
+    db.collection = % self.inner.name(),
+    otel.kind = "client",
+    tenant.id = self.inner.info.tenant.as_deref(),
+    policy.violation = tracing::field::Empty,
+    db.request.size = tracing::field::Empty,
+    db.response.size = tracing::field::Empty,
+    ),
+    skip(self,query,update,options)
+    )]
+    pub async fn update_many(
+        &self,
+        query: Document,
+        update: impl Into<UpdateModifications>,
+        options: impl Into<Option<UpdateOptions>>,
+    ) -> Result<UpdateResult> {
+        self.inner
+            .run_update_many(
+                self.live(query.clone()),
+                Some(&query),
+                update.into(),
+                options.into(),
+            )
+            .await
+    }
+
+    #[instrument(
+    name = "delete_one",
+    fields(
+    db.name = % self.inner.info.database_name ,
+    db.system = "mongodb",
+    db.collection = % self.inner.name(),
+    otel.kind = "client",
+    tenant.id = self.inner.info.tenant.as_deref(),
+    policy.violation = tracing::field::Empty,
+    db.request.size = tracing::field::Empty,
+    db.response.size = tracing::field::Empty,
+    db.delete.mode = "soft",
+    ),
+    skip(self,query,options)
+    )]
+    pub async fn delete_one(
+        &self,
+        query: Document,
+        options: impl Into<Option<DeleteOptions>>,
+    ) -> Result<DeleteResult> {
+        self.inner
+            .run_update_one(
+                self.live(query.clone()),
+                Some(&query),
+                self.mark_deleted().into(),
+                update_options(options),
+            )
+            .await
+            .map(deleted)
+    }
+
+    #[instrument(
+    name = "delete_many",
+    fields(
+    db.name = % self.inner.info.database_name ,
+    db.system = "mongodb",
+    db.collection = % self.inner.name(),
+    otel.kind = "client",
+    tenant.id = self.inner.info.tenant.as_deref(),
+    policy.violation = tracing::field::Empty,
+    db.request.size = tracing::field::Empty,
+    db.response.size = tracing::field::Empty,
+    db.delete.mode = "soft",
+    ),
+    skip(self,query,options)
+    )]
+    pub async fn delete_many(
+        &self,
+        query: Document,
+        options: impl Into<Option<DeleteOptions>>,
+    ) -> Result<DeleteResult> {
+        self.inner
+            .run_update_many(
+                self.live(query.clone()),
+                Some(&query),
+                self.mark_deleted().into(),
+                update_options(options),
+            )
+            .await
+            .map(deleted)
+    }
+
+    /// Removes the soft-deleted documents matching `query` for good.
+    pub async fn purge(
+        &self,
+        query: impl Into<Option<Document>>,
+        options: impl Into<Option<DeleteOptions>>,
+    ) -> Result<DeleteResult> {
+        let deleted = query::and(query.into(), doc! { &self.field: { "$ne": Bson::Null } });
+        self.inner
+            .delete_many(deleted, options)
+            .await
+            .map(Into::into)
+    }
+}
+
+impl<T> SoftDeleteCollection<T>
+where
+    T: DeserializeOwned + Unpin + Send + Sync,
+{
+    pub async fn find_one(
+        &self,
+        filter: impl Into<Option<Document>>,
+        options: impl Into<Option<FindOneOptions>>,
+    ) -> Result<Option<T>> {
+        self.inner.find_one(self.live(filter), options).await
+    }
+}
+
+impl<T> SoftDeleteCollection<T>
+where
+    T: Serialize,
+{
+    pub async fn replace_one(
+        &self,
+        query: Document,
+        replacement: impl Borrow<T>,
+        options: impl Into<Option<ReplaceOptions>>,
+    ) -> Result<UpdateResult> {
+        self.inner
+            .replace_one(self.live(query), replacement, options)
+            .await
+    }
+}
+
+fn live(field: &str, filter: Option<Document>) -> Document {
+    query::and(filter, doc! { field: Bson::Null })
+}
+
+/// Leaves soft-deleted documents out of `pipeline`, after any stage that must come first.
+fn live_pipeline(field: &str, pipeline: Vec<Document>) -> Vec<Document> {
+    query::match_first(pipeline, live(field, None))
+}
+
+/// A soft delete counts the documents it marked as deleted.
+fn deleted(marked: UpdateResult) -> DeleteResult {
+    DeleteResult {
+        deleted_count: marked.modified_count,
+    }
+}
+
+fn update_options(options: impl Into<Option<DeleteOptions>>) -> Option<UpdateOptions> {
+    options.into().map(|options| {
+        UpdateOptions::builder()
+            .collation(options.collation)
+            .hint(options.hint)
+            .write_concern(options.write_concern)
+            .let_vars(options.let_vars)
+            .comment(options.comment)
+            .build()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::error::Error;
+    use crate::interceptor::{Interceptor, Operation};
+    use crate::testing::test_database;
+
+    /// Keeps the filters and update of the operations it refuses, so they never reach a server.
+    #[derive(Default)]
+    struct Refused(Mutex<Vec<(Document, Document, Document)>>);
+
+    impl Interceptor for Arc<Refused> {
+        fn before(&self, operation: &Operation<'_>) -> Result<()> {
+            let update = match operation.update() {
+                Some(UpdateModifications::Document(update)) => update.clone(),
+                _ => Document::new(),
+            };
+            self.0.lock().unwrap().push((
+                operation.filter().cloned().unwrap_or_default(),
+                operation.requested_filter().cloned().unwrap_or_default(),
+                update,
+            ));
+            Err(Error::Unsupported {
+                feature: "refused".to_string(),
+            }
+            .into())
+        }
+    }
+
+    fn orders(refused: &Arc<Refused>) -> SoftDeleteCollection<Document> {
+        test_database("app")
+            .collection::<Document>("orders")
+            .with_interceptor(refused.clone())
+            .soft_delete()
+    }
+
+    #[tokio::test]
+    async fn deletes_mark_live_documents() {
+        let refused = Arc::new(Refused::default());
+        let orders = orders(&refused).deleted_field("removedAt");
+        let paid = doc! { "status": "paid" };
+        assert!(orders.delete_one(paid.clone(), None).await.is_err());
+        assert!(orders.delete_many(Document::new(), None).await.is_err());
+
+        let operations = std::mem::take(&mut *refused.0.lock().unwrap());
+        let live = doc! { "$and": [{ "status": "paid" }, { "removedAt": Bson::Null }] };
+        let (filter, requested, update) = &operations[0];
+        assert_eq!((filter, requested), (&live, &paid));
+        assert!(update
+            .get_document("$set")
+            .unwrap()
+            .get_datetime("removedAt")
+            .is_ok());
+        // The caller's empty filter stays visible to policies.
+        let (filter, requested, _) = &operations[1];
+        assert_eq!(filter, &doc! { "removedAt": Bson::Null });
+        assert_eq!(requested, &Document::new());
+    }
+
+    #[tokio::test]
+    async fn updates_skip_deleted_documents() {
+        let refused = Arc::new(Refused::default());
+        let orders = orders(&refused);
+        let set = doc! { "$set": { "status": "shipped" } };
+        let paid = doc! { "status": "paid" };
+        assert!(orders
+            .update_one(paid.clone(), set.clone(), None)
+            .await
+            .is_err());
+        assert!(orders
+            .update_many(paid.clone(), set.clone(), None)
+            .await
+            .is_err());
+
+        let live = doc! { "$and": [{ "status": "paid" }, { "deletedAt": Bson::Null }] };
+        for operation in refused.0.lock().unwrap().iter() {
+            assert_eq!(operation, &(live.clone(), paid.clone(), set.clone()));
+        }
+    }
+
+    #[test]
+    fn live_match_comes_first_by_default() {
+        let pipeline = live_pipeline("deletedAt", vec![doc! { "$group": { "_id": "$status" } }]);
+        assert_eq!(
+            pipeline,
+            vec![
+                doc! { "$match": { "deletedAt": Bson::Null } },
+                doc! { "$group": { "_id": "$status" } },
+            ]
+        );
+    }
+
+    #[test]
+    fn live_match_follows_leading_stages() {
+        for leading in [
+            doc! { "$search": { "text": { "query": "paid", "path": "status" } } },
+            doc! { "$vectorSearch": { "index": "embedding", "limit": 5 } },
+            doc! { "$documents": [{ "status": "paid" }] },
+        ] {
+            let pipeline = live_pipeline("removedAt", vec![leading.clone(), doc! { "$limit": 5 }]);
+            assert_eq!(
+                pipeline,
+                vec![
+                    leading,
+                    doc! { "$match": { "removedAt": Bson::Null } },
+                    doc! { "$limit": 5 },
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn live_predicate_joins_the_geo_near_query() {
+        let pipeline = live_pipeline(
+            "deletedAt",
+            vec![doc! { "$geoNear": {
+                "near": [0, 0],
+                "distanceField": "distance",
+                "query": { "status": "paid" },
+            } }],
+        );
+        assert_eq!(
+            pipeline,
+            vec![doc! { "$geoNear": {
+                "near": [0, 0],
+                "distanceField": "distance",
+                "query": { "$and": [{ "status": "paid" }, { "deletedAt": Bson::Null }] },
+            } }]
+        );
+    }
+
+    #[test]
+    fn collection_stages_are_left_alone() {
+        for stage in [
+            doc! { "$collStats": { "count": {} } },
+            doc! { "$indexStats": {} },
+        ] {
+            assert_eq!(live_pipeline("deletedAt", vec![stage.clone()]), vec![stage]);
+        }
+    }
+}
@@ -0,0 +1,528 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, VecDeque};
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use mongodb::bson::Bson;
+use mongodb::error::Error;
+use mongodb::options::UpdateModifications;
+use tokio::task::JoinHandle;
+
+use crate::interceptor::{CursorRead, Interceptor, Operation, OperationKind, Outcome};
+use crate::mongo_tracing::InstrumentedCollection;
+use crate::shape;
+
+/// Latencies kept per fingerprint to estimate percentiles.
+const SAMPLES: usize = 1024;
+
+/// Shapes tracked by default, as in `pg_stat_statements`.
+const MAX_ENTRIES: usize = 5000;
+
+/// The shape of the buckets gathering the statistics of evicted shapes.
+const OTHER: &str = "other";
+
+/// Aggregates of the operations sharing a shape, as returned by [`QueryStats::snapshot`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct QueryStat {
+    pub fingerprint: u64,
+    pub operation: OperationKind,
+    pub namespace: String,
+    /// The filter or pipeline with every value replaced by `"?"`, as extended JSON; `"other"`
+    /// for the bucket of the shapes of `operation` evicted beyond [`QueryStats::max_entries`],
+    /// whose namespace is `"*"`.
+    pub shape: String,
+    /// The shape of the update document or pipeline, for updates.
+    pub update: Option<String>,
+    pub calls: u64,
+    pub errors: u64,
+    pub total: Duration,
+    pub min: Duration,
+    pub max: Duration,
+    /// Estimated over the last 1024 calls.
+    pub p50: Duration,
+    pub p99: Duration,
+    /// Documents found, inserted, modified or deleted, or read from the cursors of `find` and
+    /// `aggregate`, counted when the cursor is dropped.
+    pub documents: u64,
+    /// Batches read from cursors, the first one of each included.
+    pub batches: u64,
+}
+
+struct Entry {
+    operation: OperationKind,
+    namespace: String,
+    shape: String,
+    update: Option<String>,
+    /// When the shape was last recorded, on the clock of [`Entries`].
+    seen: u64,
+    calls: u64,
+    errors: u64,
+    total: Duration,
+    min: Duration,
+    max: Duration,
+    samples: VecDeque<Duration>,
+    documents: u64,
+    batches: u64,
+}
+
+impl Entry {
+    fn new(
+        operation: OperationKind,
+        namespace: String,
+        shape: String,
+        update: Option<String>,
+    ) -> Self {
+        Entry {
+            operation,
+            namespace,
+            shape,
+            update,
+            seen: 0,
+            calls: 0,
+            errors: 0,
+            total: Duration::ZERO,
+            min: Duration::MAX,
+            max: Duration::ZERO,
+            samples: VecDeque::with_capacity(SAMPLES),
+            documents: 0,
+            batches: 0,
+        }
+    }
+
+    /// Adds the statistics of `evicted` to this bucket.
+    fn absorb(&mut self, evicted: Entry) {
+        self.calls += evicted.calls;
+        self.errors += evicted.errors;
+        self.total += evicted.total;
+        self.min = self.min.min(evicted.min);
+        self.max = self.max.max(evicted.max);
+        for sample in evicted.samples {
+            if self.samples.len() == SAMPLES {
+                self.samples.pop_front();
+            }
+            self.samples.push_back(sample);
+        }
+        self.documents += evicted.documents;
+        self.batches += evicted.batches;
+    }
+}
+
+#[derive(Default)]
+struct Entries {
+    values: HashMap<u64, Entry>,
+    /// Counts the operations recorded, to find the least recently seen shape.
+    clock: u64,
+}
+
+/// Per-shape statistics of the operations of the collections it is attached to, in the spirit
+/// of `pg_stat_statements`. Clones share their statistics.
+///
+/// At most [`QueryStats::max_entries`] shapes are tracked: beyond that, the least recently
+/// seen one is evicted and its statistics added to the `"other"` bucket of its operation.
+#[derive(Clone)]
+pub struct QueryStats {
+    entries: Arc<Mutex<Entries>>,
+    max_entries: usize,
+}
+
+impl Default for QueryStats {
+    fn default() -> Self {
+        QueryStats {
+            entries: Arc::default(),
+            max_entries: MAX_ENTRIES,
+        }
+    }
+}
+
+impl QueryStats {
+    pub fn new() -> Self {
+        QueryStats::default()
+    }
+
+    /// Tracks at most `max_entries` shapes, 5000 by default; the `"other"` buckets are not
+    /// counted.
+    pub fn max_entries(mut self, max_entries: usize) -> Self {
+        self.max_entries = max_entries.max(1);
+        self
+    }
+
+    /// The statistics gathered so far, by decreasing total time.
+    pub fn snapshot(&self) -> Vec<QueryStat> {
+        let mut stats: Vec<QueryStat> = self
+            .entries()
+            .values
+            .iter()
+            .map(|(fingerprint, entry)| {
+                let mut samples: Vec<Duration> = entry.samples.iter().copied().collect();
+                samples.sort();
+                QueryStat {
+                    fingerprint: *fingerprint,
+                    operation: entry.operation,
+                    namespace: entry.namespace.clone(),
+                    shape: entry.shape.clone(),
+                    update: entry.update.clone(),
+                    calls: entry.calls,
+                    errors: entry.errors,
+                    total: entry.total,
+                    min: entry.min,
+                    max: entry.max,
+                    p50: percentile(&samples, 50),
+                    p99: percentile(&samples, 99),
+                    documents: entry.documents,
+                    batches: entry.batches,
+                }
+            })
+            .collect();
+        stats.sort_by_key(|stat| std::cmp::Reverse(stat.total));
+        stats
+    }
+
+    pub fn reset(&self) {
+        self.entries().values.clear();
+    }
+
+    /// Emits one `mongo_tracing::stats` event per shape.
+    pub fn emit(&self) {
+        for stat in self.snapshot() {
+            tracing::info!(
+                target: "mongo_tracing::stats",
+                calls = stat.calls,
+                db.query.fingerprint = %format!("{:016x}", stat.fingerprint),
+                db.operation = stat.operation.name(),
+                db.namespace = %stat.namespace,
+                db.query.shape = %stat.shape,
+                db.query.update = stat.update.as_deref(),
+                errors = stat.errors,
+                total_ms = stat.total.as_secs_f64() * 1000.0,
+                min_ms = stat.min.as_secs_f64() * 1000.0,
+                max_ms = stat.max.as_secs_f64() * 1000.0,
+                p50_ms = stat.p50.as_secs_f64() * 1000.0,
+                p99_ms = stat.p99.as_secs_f64() * 1000.0,
+                documents = stat.documents,
+                batches = stat.batches,
+            );
+        }
+    }
+
+    /// Calls [`QueryStats::emit`] every `period` in the background.
+    pub fn emit_every(&self, period: Duration) -> JoinHandle<()> {
+        let stats = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(period);
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                stats.emit();
+            }
+        })
+    }
+
+    fn entries(&self) -> MutexGuard<'_, Entries> {
+        self.entries
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn record(&self, operation: &Operation<'_>, failed: bool, documents: u64) {
+        let Fingerprint {
+            fingerprint,
+            namespace,
+            shape,
+            update,
+        } = fingerprint(operation);
+        let elapsed = operation.elapsed();
+        let mut entries = self.entries();
+        if !entries.values.contains_key(&fingerprint) {
+            self.evict(&mut entries);
+        }
+        entries.clock += 1;
+        let seen = entries.clock;
+        let entry = entries
+            .values
+            .entry(fingerprint)
+            .or_insert_with(|| Entry::new(operation.kind(), namespace, shape, update));
+        entry.seen = seen;
+        entry.calls += 1;
+        entry.errors += u64::from(failed);
+        entry.total += elapsed;
+        entry.min = entry.min.min(elapsed);
+        entry.max = entry.max.max(elapsed);
+        if entry.samples.len() == SAMPLES {
+            entry.samples.pop_front();
+        }
+        entry.samples.push_back(elapsed);
+        entry.documents += documents;
+    }
+
+    /// Makes room for a new shape, moving the least recently seen one to its `"other"` bucket.
+    fn evict(&self, entries: &mut Entries) {
+        let tracked = entries
+            .values
+            .values()
+            .filter(|entry| entry.shape != OTHER)
+            .count();
+        if tracked < self.max_entries {
+            return;
+        }
+        let oldest = entries
+            .values
+            .iter()
+            .filter(|(_, entry)| entry.shape != OTHER)
+            .min_by_key(|(_, entry)| entry.seen)
+            .map(|(fingerprint, _)| *fingerprint);
+        let Some(evicted) = oldest.and_then(|oldest| entries.values.remove(&oldest)) else {
+            return;
+        };
+        let mut hasher = DefaultHasher::new();
+        (evicted.operation, OTHER).hash(&mut hasher);
+        let other = entries.values.entry(hasher.finish()).or_insert_with(|| {
+            Entry::new(evicted.operation, "*".to_string(), OTHER.to_string(), None)
+        });
+        other.seen = other.seen.max(evicted.seen);
+        other.absorb(evicted);
+    }
+}
+
+/// What groups an operation with the ones alike.
+struct Fingerprint {
+    fingerprint: u64,
+    namespace: String,
+    shape: String,
+    update: Option<String>,
+}
+
+fn fingerprint(operation: &Operation<'_>) -> Fingerprint {
+    let shape = match (operation.filter(), operation.pipeline()) {
+        (_, Some(pipeline)) => Bson::Array(
+            pipeline
+                .iter()
+                .map(|stage| Bson::Document(shape::shape(stage)))
+                .collect(),
+        ),
+        (Some(filter), None) => Bson::Document(shape::shape(filter)),
+        (None, None) => Bson::Null,
+    }
+    .into_relaxed_extjson()
+    .to_string();
+    let update = operation.update().map(|update| {
+        match update {
+            UpdateModifications::Document(update) => Bson::Document(shape::shape(update)),
+            UpdateModifications::Pipeline(stages) => Bson::Array(
+                stages
+                    .iter()
+                    .map(|stage| Bson::Document(shape::shape(stage)))
+                    .collect(),
+            ),
+            _ => Bson::Null,
+        }
+        .into_relaxed_extjson()
+        .to_string()
+    });
+    let namespace = operation.namespace().to_string();
+    let mut hasher = DefaultHasher::new();
+    (operation.kind(), &namespace, &shape, &update).hash(&mut hasher);
+    Fingerprint {
+        fingerprint: hasher.finish(),
+        namespace,
+        shape,
+        update,
+    }
+}
+
+fn percentile(sorted: &[Duration], percent: usize) -> Duration {
+    match sorted.len() {
+        0 => Duration::ZERO,
+        len => sorted[((len - 1) * percent) / 100],
+    }
+}
+
+impl Interceptor for QueryStats {
+    fn after(&self, operation: &Operation<'_>, outcome: &Outcome) {
+        let documents = match outcome {
+            Outcome::Found { found, .. } => u64::from(*found),
+            Outcome::Inserted { ids } => ids.len() as u64,
+            Outcome::Updated { modified, .. } => *modified,
+            Outcome::Deleted { count } => *count,
+            Outcome::Values(count) => *count as u64,
+            _ => 0,
+        };
+        self.record(operation, false, documents);
+    }
+
+    fn on_error(&self, operation: &Operation<'_>, _error: &Error) {
+        self.record(operation, true, 0);
+    }
+
+    fn after_cursor(&self, operation: &Operation<'_>, read: &CursorRead) {
+        let fingerprint = fingerprint(operation).fingerprint;
+        if let Some(entry) = self.entries().values.get_mut(&fingerprint) {
+            entry.documents += read.documents;
+            entry.batches += read.batches;
+        }
+    }
+}
+
+impl<T> InstrumentedCollection<T> {
+    /// Adds the operations of this collection to `stats`.
+    pub fn with_query_stats(self, stats: &QueryStats) -> Self {
+        self.with_interceptor(stats.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mongodb::bson::{doc, Document};
+    use mongodb::error::ErrorKind;
+    use mongodb::Namespace;
+
+    use super::*;
+
+    fn find(filter: &Document) -> Operation<'_> {
+        let namespace = Namespace {
+            db: "app".to_string(),
+            coll: "users".to_string(),
+        };
+        Operation::new(OperationKind::Find, namespace).with_filter(Some(filter))
+    }
+
+    #[test]
+    fn operations_are_grouped_by_shape() {
+        let stats = QueryStats::new();
+        let (ada, bob, other) = (
+            doc! { "name": "ada" },
+            doc! { "name": "bob" },
+            doc! { "age": 3 },
+        );
+        stats.after(&find(&ada), &Outcome::Cursor);
+        stats.after(&find(&bob), &Outcome::Cursor);
+        stats.on_error(&find(&other), &ErrorKind::Custom(Arc::new(())).into());
+        let snapshot = stats.snapshot();
+        assert_eq!(snapshot.len(), 2);
+        let names = snapshot
+            .iter()
+            .find(|stat| stat.shape == r#"{"name":"?"}"#)
+            .unwrap();
+        assert_eq!((names.calls, names.errors), (2, 0));
+        assert_eq!(names.namespace, "app.users");
+        let ages = snapshot
+            .iter()
+            .find(|stat| stat.shape == r#"{"age":"?"}"#)
+            .unwrap();
+        assert_eq!((ages.calls, ages.errors), (1, 1));
+    }
+
+    #[test]
+    fn documents_read_from_cursors_are_counted() {
+        let stats = QueryStats::new();
+        let filter = doc! { "name": "ada" };
+        stats.after(&find(&filter), &Outcome::Cursor);
+        stats.after_cursor(
+            &find(&filter),
+            &CursorRead {
+                documents: 150,
+                batches: 2,
+            },
+        );
+        stats.after_cursor(
+            &find(&doc! { "name": "bob" }),
+            &CursorRead {
+                documents: 1,
+                batches: 1,
+            },
+        );
+        let stat = &stats.snapshot()[0];
+        assert_eq!((stat.calls, stat.documents, stat.batches), (1, 151, 3));
+    }
+
+    #[test]
+    fn cursors_outliving_a_reset_are_ignored() {
+        let stats = QueryStats::new();
+        let filter = doc! { "name": "ada" };
+        stats.after(&find(&filter), &Outcome::Cursor);
+        stats.reset();
+        stats.after_cursor(&find(&filter), &CursorRead::default());
+        assert!(stats.snapshot().is_empty());
+    }
+
+    #[test]
+    fn updates_are_grouped_by_update_shape() {
+        let stats = QueryStats::new();
+        let filter = doc! { "name": "ada" };
+        let namespace = find(&filter).namespace().clone();
+        let set = UpdateModifications::Document(doc! { "$set": { "age": 3 } });
+        let inc = UpdateModifications::Document(doc! { "$inc": { "age": 1 } });
+        for update in [&set, &inc, &set] {
+            let operation = Operation::new(OperationKind::UpdateOne, namespace.clone())
+                .with_filter(Some(&filter))
+                .with_update(update);
+            stats.after(&operation, &Outcome::Done);
+        }
+        let mut updates: Vec<_> = stats
+            .snapshot()
+            .into_iter()
+            .map(|stat| (stat.update.unwrap(), stat.calls))
+            .collect();
+        updates.sort();
+        assert_eq!(
+            updates,
+            [
+                (r#"{"$inc":{"age":"?"}}"#.to_string(), 1),
+                (r#"{"$set":{"age":"?"}}"#.to_string(), 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn least_recently_seen_shapes_move_to_the_other_bucket() {
+        let stats = QueryStats::new().max_entries(2);
+        let (a, b, c) = (doc! { "a": 1 }, doc! { "b": 1 }, doc! { "c": 1 });
+        stats.after(
+            &find(&a),
+            &Outcome::Found {
+                found: true,
+                id: None,
+            },
+        );
+        stats.after(&find(&b), &Outcome::Cursor);
+        stats.after(&find(&a), &Outcome::Cursor);
+        stats.on_error(&find(&c), &ErrorKind::Custom(Arc::new(())).into());
+        stats.after(&find(&c), &Outcome::Cursor);
+        // a was seen after b, so b goes first; then a, which is the oldest after c.
+        stats.after(&find(&b), &Outcome::Cursor);
+
+        let snapshot = stats.snapshot();
+        let calls = |shape: &str| {
+            snapshot
+                .iter()
+                .find(|stat| stat.shape == shape)
+                .map(|stat| (stat.calls, stat.errors, stat.documents))
+        };
+        assert_eq!(snapshot.len(), 3);
+        assert_eq!(calls(r#"{"a":"?"}"#), None);
+        assert_eq!(calls(r#"{"b":"?"}"#), Some((1, 0, 0)));
+        assert_eq!(calls(r#"{"c":"?"}"#), Some((2, 1, 0)));
+        assert_eq!(calls("other"), Some((3, 0, 1)));
+        let other = snapshot.iter().find(|stat| stat.shape == "other").unwrap();
+        assert_eq!(
+            (other.operation, other.namespace.as_str()),
+            (OperationKind::Find, "*")
+        );
+    }
+
+    #[test]
+    fn writes_count_affected_documents() {
+        let stats = QueryStats::new();
+        let filter = doc! { "name": "ada" };
+        let namespace = find(&filter).namespace().clone();
+        let operation =
+            Operation::new(OperationKind::UpdateMany, namespace).with_filter(Some(&filter));
+        let outcome = Outcome::Updated {
+            matched: 3,
+            modified: 2,
+            upserted_id: None,
+        };
+        stats.after(&operation, &outcome);
+        assert_eq!(stats.snapshot()[0].documents, 2);
+    }
+}
@@ -0,0 +1,583 @@
+use std::borrow::Borrow;
+
+use mongodb::bson::{doc, to_document, Bson, Document};
+use mongodb::error::Result;
+use mongodb::options::{
+    AggregateOptions, CountOptions, DeleteOptions, DistinctOptions, FindOneOptions, FindOptions,
+    InsertManyOptions, InsertOneOptions, ReplaceOptions, UpdateModifications, UpdateOptions,
+};
+use mongodb::results::{DeleteResult, InsertManyResult, InsertOneResult, UpdateResult};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tracing::instrument;
+
+use crate::cursor::InstrumentedCursor;
+use crate::error::Error;
+use crate::mongo_tracing::InstrumentedCollection;
+use crate::query;
+
+/// Stages that read or write other collections, which the tenant predicate cannot cover.
+const CROSS_COLLECTION_STAGES: &[&str] =
+    &["$lookup", "$graphLookup", "$unionWith", "$out", "$merge"];
+
+/// A view of an [`InstrumentedCollection`] restricted to the documents of one tenant.
+///
+/// Every filter is combined with the tenant predicate, pipelines start with a `$match` on it (or
+/// carry it in the `query` of a leading `$geoNear`, or follow a leading `$search`,
+/// `$vectorSearch` or `$documents` with it) and written documents carry it; operations that
+/// could reach other tenants' documents are refused with [`Error::TenantViolation`]. Spans of the
+/// underlying collection record `tenant.id`.
+pub struct TenantCollection<T> {
+    inner: InstrumentedCollection<T>,
+    field: String,
+    tenant: Bson,
+}
+
+impl<T> InstrumentedCollection<T> {
+    /// Scopes the collection to `tenant`, stored in the `tenantId` field unless changed with
+    /// [`TenantCollection::tenant_field`].
+    pub fn for_tenant(&self, tenant: impl Into<Bson>) -> TenantCollection<T> {
+        let tenant = tenant.into();
+        let mut inner = self.clone();
+        inner.info.tenant = Some(match &tenant {
+            Bson::String(tenant) => tenant.clone(),
+            other => other.to_string(),
+        });
+        TenantCollection {
+            inner,
+            field: "tenantId".to_string(),
+            tenant,
+        }
+    }
+}
+
+impl<T> Clone for TenantCollection<T> {
+    fn clone(&self) -> Self {
+        TenantCollection {
+            inner: self.inner.clone(),
+            field: self.field.clone(),
+            tenant: self.tenant.clone(),
+        }
+    }
+}
+
+impl<T> TenantCollection<T> {
+    pub fn tenant_field(mut self, field: impl Into<String>) -> Self {
+        self.field = field.into();
+        self
+    }
+
+    pub fn tenant(&self) -> &Bson {
+        &self.tenant
+    }
+
+    fn scope(&self, filter: impl Into<Option<Document>>) -> Document {
+        query::and(filter.into(), doc! { &self.field: self.tenant.clone() })
+    }
+
+    fn violation(&self, reason: &'static str) -> mongodb::error::Error {
+        Error::TenantViolation {
+            tenant: self.inner.info.tenant.clone().unwrap_or_default(),
+            reason,
+        }
+        .into()
+    }
+
+    /// Sets the tenant field of a document about to be written, refusing another tenant's value.
+    /// A dotted field is set in the embedded documents it names.
+    fn stamp(&self, mut document: Document) -> Result<Document> {
+        let (parents, name) = match self.field.rsplit_once('.') {
+            Some((parents, name)) => (parents.split('.').collect(), name),
+            None => (Vec::new(), self.field.as_str()),
+        };
+        let mut target = &mut document;
+        for parent in parents {
+            if !target.contains_key(parent) {
+                target.insert(parent, Document::new());
+            }
+            target = match target.get_mut(parent) {
+                Some(Bson::Document(embedded)) => embedded,
+                _ => return Err(self.violation("tenant field is inside a non-document value")),
+            };
+        }
+        match target.get(name) {
+            Some(tenant) if *tenant != self.tenant => {
+                Err(self.violation("document belongs to another tenant"))
+            }
+            _ => {
+                target.insert(name, self.tenant.clone());
+                Ok(document)
+            }
+        }
+    }
+
+    /// Whether a `$set`-like list of assignments leaves the tenant field alone.
+    fn keeps_tenant(&self, assignments: &Document) -> bool {
+        assignments.iter().all(|(path, value)| {
+            !query::touches(path, &self.field) || (*path == self.field && *value == self.tenant)
+        })
+    }
+
+    fn check_update(&self, update: &UpdateModifications) -> Result<()> {
+        let allowed = match update {
+            UpdateModifications::Document(update) => update.iter().all(|(operator, value)| {
+                let fields = match value {
+                    Bson::Document(fields) => fields,
+                    _ => return true,
+                };
+                match operator.as_str() {
+                    "$set" | "$setOnInsert" => self.keeps_tenant(fields),
+                    "$rename" => fields.iter().all(|(from, to)| {
+                        !query::touches(from, &self.field)
+                            && to
+                                .as_str()
+                                .is_none_or(|to| !query::touches(to, &self.field))
+                    }),
+                    _ => fields.keys().all(|path| !query::touches(path, &self.field)),
+                }
+            }),
+            UpdateModifications::Pipeline(stages) => stages.iter().all(|stage| {
+                stage
+                    .iter()
+                    .all(|(name, value)| match (name.as_str(), value) {
+                        ("$set" | "$addFields", Bson::Document(fields)) => {
+                            self.keeps_tenant(fields)
+                        }
+                        ("$unset", Bson::String(path)) => !query::touches(path, &self.field),
+                        ("$unset", Bson::Array(paths)) => paths.iter().all(|path| {
+                            path.as_str()
+                                .is_none_or(|path| !query::touches(path, &self.field))
+                        }),
+                        ("$project" | "$replaceRoot" | "$replaceWith", _) => false,
+                        _ => true,
+                    })
+            }),
+            _ => false,
+        };
+        if allowed {
+            Ok(())
+        } else {
+            Err(self.violation("update modifies the tenant field"))
+        }
+    }
+
+    fn check_pipeline(&self, pipeline: &[Document]) -> Result<()> {
+        for stage in pipeline {
+            for (name, value) in stage {
+                if CROSS_COLLECTION_STAGES.contains(&name.as_str()) {
+                    return Err(self.violation("pipeline reaches another collection"));
+                }
+                if let ("$facet", Bson::Document(facets)) = (name.as_str(), value) {
+                    for facet in facets.values() {
+                        if let Bson::Array(stages) = facet {
+                            let stages: Vec<Document> = stages
+                                .iter()
+                                .filter_map(|stage| stage.as_document().cloned())
+                                .collect();
+                            self.check_pipeline(&stages)?;
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Applies the tenant predicate before the first stage passing documents on.
+    fn scope_pipeline(&self, pipeline: Vec<Document>) -> Result<Vec<Document>> {
+        self.check_pipeline(&pipeline)?;
+        match query::first_stage(&pipeline) {
+            Some(stage) if query::COLLECTION_STAGES.contains(&stage) => {
+                Err(self.violation("pipeline reports on the whole collection"))
+            }
+            _ => Ok(query::match_first(pipeline, self.scope(None))),
+        }
+    }
+
+    pub async fn find(
+        &self,
+        filter: impl Into<Option<Document>>,
+        options: impl Into<Option<FindOptions>>,
+    ) -> Result<InstrumentedCursor<T>> {
+        self.inner.find(self.scope(filter), options).await
+    }
+
+    pub async fn count_documents(
+        &self,
+        filter: impl Into<Option<Document>>,
+        options: impl Into<Option<CountOptions>>,
+    ) -> Result<u64> {
+        self.inner
+            .count_documents(self.scope(filter), options)
+            .await
+    }
+
+    pub async fn distinct(
+        &self,
+        field_name: impl AsRef<str>,
+        filter: impl Into<Option<Document>>,
+        options: impl Into<Option<DistinctOptions>>,
+    ) -> Result<Vec<Bson>> {
+        self.inner
+            .distinct(field_name, self.scope(filter), options)
+            .await
+    }
+
+    pub async fn aggregate(
+        &self,
+        pipeline: impl IntoIterator<Item = Document>,
+        options: impl Into<Option<AggregateOptions>>,
+    ) -> Result<InstrumentedCursor<Document>> {
+        let pipeline = self.scope_pipeline(pipeline.into_iter().collect())?;
+        self.inner.aggregate(pipeline, options).await
+    }
+
+    #[instrument(
+    name = "update_one",
+    fields(
+    db.name = % self.inner.info.database_name ,
+    db.system = "mongodb",
+    db.collection = % self.inner.name(),
+    otel.kind = "client",
+    tenant.id = self.inner.info.tenant.as_deref(),
+    policy.violation = tracing::field::Empty,
+    db.request.size = tracing::field::Empty,
+    db.response.size = tracing::field::Empty,
+    ),
+    skip(self,query,update,options)
+    )]
+    pub async fn update_one(
+        &self,
+        query: Document,
+        update: impl Into<UpdateModifications>,
+        options: impl Into<Option<UpdateOptions>>,
+    ) -> Result<UpdateResult> {
+        let update = update.into();
+        self.check_update(&update)?;
+        self.inner
+            .run_update_one(
+                self.scope(query.clone()),
+                Some(&query),
+                update,
+                options.into(),
+            )
+            .await
+    }
+
+    #[instrument(
+    name = "update_many",
+    fields(
+    db.name = % self.inner.info.database_name ,
+    db.system = "mongodb",
+    db.collection = % self.inner.name(),
+    otel.kind = "client",
+    tenant.id = self.inner.info.tenant.as_deref(),
+    policy.violation = tracing::field::Empty,
+    db.request.size = tracing::field::Empty,
+    db.response.size = tracing::field::Empty,
+    ),
+    skip(self,query,update,options)
+    )]
+    pub async fn update_many(
+        &self,
+        query: Document,
+        update: impl Into<UpdateModifications>,
+        options: impl Into<Option<UpdateOptions>>,
+    ) -> Result<UpdateResult> {
+        let update = update.into();
+        self.check_update(&update)?;
+        self.inner
+            .run_update_many(
+                self.scope(query.clone()),
+                Some(&query),
+                update,
+                options.into(),
+            )
+            .await
+    }
+
+    #[instrument(
+    name = "delete_one",
+    fields(
+    db.name = % self.inner.info.database_name ,
+    db.system = "mongodb",
+    db.collection = % self.inner.name(),
+    otel.kind = "client",
+    tenant.id = self.inner.info.tenant.as_deref(),
+    policy.violation = tracing::field::Empty,
+    db.request.size = tracing::field::Empty,
+    db.response.size = tracing::field::Empty,
+    db.delete.mode = "hard",
+    ),
+    skip(self,query,options)
+    )]
+    pub async fn delete_one(
+        &self,
+        query: Document,
+        options: impl Into<Option<DeleteOptions>>,
+    ) -> Result<DeleteResult> {
+        self.inner
+            .run_delete_one(self.scope(query.clone()), Some(&query), options.into())
+            .await
+    }
+
+    #[instrument(
+    name = "delete_many",
+    fields(
+    db.name = % self.inner.info.database_name ,
+    db.system = "mongodb",
+    db.collection = % self.inner.name(),
+    otel.kind = "client",
+    tenant.id = self.inner.info.tenant.as_deref(),
+    policy.violation = tracing::field::Empty,
+    db.request.size = tracing::field::Empty,
+    db.response.size = tracing::field::Empty,
+    db.delete.mode = "hard",
+    ),
+    skip(self,query,options)
+    )]
+    pub async fn delete_many(
+        &self,
+        query: Document,
+        options: impl Into<Option<DeleteOptions>>,
+    ) -> Result<DeleteResult> {
+        self.inner
+            .run_delete_many(self.scope(query.clone()), Some(&query), options.into())
+            .await
+    }
+}
+
+impl<T> TenantCollection<T>
+where
+    T: DeserializeOwned + Unpin + Send + Sync,
+{
+    pub async fn find_one(
+        &self,
+        filter: impl Into<Option<Document>>,
+        options: impl Into<Option<FindOneOptions>>,
+    ) -> Result<Option<T>> {
+        self.inner.find_one(self.scope(filter), options).await
+    }
+}
+
+impl<T> TenantCollection<T>
+where
+    T: Serialize,
+{
+    pub async fn insert_one(
+        &self,
+        doc: impl Borrow<T>,
+        options: impl Into<Option<InsertOneOptions>>,
+    ) -> Result<InsertOneResult> {
+        let document = self.stamp(to_document(doc.borrow())?)?;
+        self.inner
+            .clone_with_type::<Document>()
+            .insert_one(document, options)
+            .await
+    }
+
+    pub async fn insert_many(
+        &self,
+        docs: impl IntoIterator<Item = impl Borrow<T>>,
+        options: impl Into<Option<InsertManyOptions>>,
+    ) -> Result<InsertManyResult> {
+        let documents = docs
+            .into_iter()
+            .map(|doc| self.stamp(to_document(doc.borrow())?))
+            .collect::<Result<Vec<Document>>>()?;
+        self.inner
+            .clone_with_type::<Document>()
+            .insert_many(documents, options)
+            .await
+    }
+
+    pub async fn replace_one(
+        &self,
+        query: Document,
+        replacement: impl Borrow<T>,
+        options: impl Into<Option<ReplaceOptions>>,
+    ) -> Result<UpdateResult> {
+        let replacement = self.stamp(to_document(replacement.borrow())?)?;
+        self.inner
+            .clone_with_type::<Document>()
+            .replace_one(self.scope(query), replacement, options)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::test_database;
+
+    fn orders(field: &str) -> TenantCollection<Document> {
+        test_database("app")
+            .collection::<Document>("orders")
+            .for_tenant("acme")
+            .tenant_field(field)
+    }
+
+    fn reason(result: Result<impl std::fmt::Debug>) -> Option<&'static str> {
+        match result {
+            Ok(_) => None,
+            Err(error) => match Error::from_mongo(&error) {
+                Some(Error::TenantViolation { reason, .. }) => Some(*reason),
+                _ => panic!("unexpected error: {error}"),
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn stamp_sets_and_checks_the_tenant() {
+        let orders = orders("tenantId");
+        assert_eq!(
+            orders.stamp(doc! { "total": 3 }).unwrap(),
+            doc! { "total": 3, "tenantId": "acme" }
+        );
+        assert_eq!(
+            orders.stamp(doc! { "tenantId": "acme" }).unwrap(),
+            doc! { "tenantId": "acme" }
+        );
+        assert_eq!(
+            reason(orders.stamp(doc! { "tenantId": "other" })),
+            Some("document belongs to another tenant")
+        );
+    }
+
+    #[tokio::test]
+    async fn stamp_resolves_dotted_fields() {
+        let orders = orders("org.id");
+        assert_eq!(
+            orders.stamp(doc! { "total": 3 }).unwrap(),
+            doc! { "total": 3, "org": { "id": "acme" } }
+        );
+        assert_eq!(
+            orders.stamp(doc! { "org": { "name": "Acme" } }).unwrap(),
+            doc! { "org": { "name": "Acme", "id": "acme" } }
+        );
+        assert_eq!(
+            reason(orders.stamp(doc! { "org": { "id": "other" } })),
+            Some("document belongs to another tenant")
+        );
+        assert_eq!(
+            reason(orders.stamp(doc! { "org": "acme" })),
+            Some("tenant field is inside a non-document value")
+        );
+        let stamped = orders.stamp(doc! {}).unwrap();
+        let filter = orders.scope(None);
+        assert_eq!(
+            stamped.get_document("org").unwrap().get("id"),
+            filter.get("org.id")
+        );
+    }
+
+    #[tokio::test]
+    async fn updates_may_not_change_the_tenant() {
+        let orders = orders("tenantId");
+        let cases: [(UpdateModifications, Option<&str>); 9] = [
+            (doc! { "$set": { "status": "paid" } }.into(), None),
+            (doc! { "$set": { "tenantId": "acme" } }.into(), None),
+            (
+                doc! { "$set": { "tenantId": "other" } }.into(),
+                Some("update modifies the tenant field"),
+            ),
+            (
+                doc! { "$unset": { "tenantId": "" } }.into(),
+                Some("update modifies the tenant field"),
+            ),
+            (
+                doc! { "$rename": { "tenantId": "previousTenant" } }.into(),
+                Some("update modifies the tenant field"),
+            ),
+            (
+                doc! { "$rename": { "owner": "tenantId" } }.into(),
+                Some("update modifies the tenant field"),
+            ),
+            (
+                vec![doc! { "$set": { "tenantId.name": "x" } }].into(),
+                Some("update modifies the tenant field"),
+            ),
+            (
+                vec![doc! { "$unset": ["status", "tenantId"] }].into(),
+                Some("update modifies the tenant field"),
+            ),
+            (
+                vec![doc! { "$replaceWith": { "total": 0 } }].into(),
+                Some("update modifies the tenant field"),
+            ),
+        ];
+        for (update, expected) in cases {
+            assert_eq!(reason(orders.check_update(&update)), expected, "{update:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn pipelines_may_not_reach_other_collections() {
+        let orders = orders("tenantId");
+        let cases = [
+            (vec![doc! { "$match": { "status": "paid" } }], None),
+            (
+                vec![doc! { "$lookup": { "from": "customers", "as": "c", "pipeline": [] } }],
+                Some("pipeline reaches another collection"),
+            ),
+            (
+                vec![doc! { "$unionWith": { "coll": "archive", "pipeline": [] } }],
+                Some("pipeline reaches another collection"),
+            ),
+            (
+                vec![doc! { "$facet": { "a": [{ "$unionWith": "archive" }] } }],
+                Some("pipeline reaches another collection"),
+            ),
+            (
+                vec![doc! { "$facet": { "a": [{ "$facet": { "b": [{ "$out": "c" }] } }] } }],
+                Some("pipeline reaches another collection"),
+            ),
+        ];
+        for (pipeline, expected) in cases {
+            assert_eq!(reason(orders.check_pipeline(&pipeline)), expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn pipelines_are_scoped_to_the_tenant() {
+        let orders = orders("tenantId");
+        let tenant = doc! { "$match": { "tenantId": "acme" } };
+        let search = doc! { "$search": { "text": { "query": "a", "path": "b" } } };
+        assert_eq!(
+            orders
+                .scope_pipeline(vec![doc! { "$sort": { "total": 1 } }])
+                .unwrap(),
+            vec![tenant.clone(), doc! { "$sort": { "total": 1 } }]
+        );
+        assert_eq!(
+            orders
+                .scope_pipeline(vec![search.clone(), doc! { "$limit": 5 }])
+                .unwrap(),
+            vec![search, tenant, doc! { "$limit": 5 }]
+        );
+        assert_eq!(
+            orders
+                .scope_pipeline(vec![doc! { "$geoNear": {
+                    "near": [0, 0],
+                    "distanceField": "d",
+                } }])
+                .unwrap(),
+            vec![doc! { "$geoNear": {
+                "near": [0, 0],
+                "distanceField": "d",
+                "query": { "tenantId": "acme" },
+            } }]
+        );
+        assert_eq!(
+            reason(orders.scope_pipeline(vec![doc! { "$collStats": { "count": {} } }])),
+            Some("pipeline reports on the whole collection")
+        );
+        assert_eq!(
+            reason(orders.scope_pipeline(vec![
+                doc! { "$match": {} },
+                doc! { "$lookup": { "from": "customers", "as": "c" } },
+            ])),
+            Some("pipeline reaches another collection")
+        );
+    }
+}
@@ -0,0 +1,98 @@
+//! Helpers shared by the unit tests of the modules.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use mongodb::options::ClientOptions;
+use mongodb::Client;
+
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Metadata, Subscriber};
+use tracing_core::span::Current;
+
+use crate::database::InstrumentedDatabase;
+
+/// The database `name` of a client that has not connected; it only reaches for the server when
+/// an operation is sent, which the tests using it never do. Must be called inside a runtime.
+pub(crate) fn test_database(name: &str) -> InstrumentedDatabase {
+    let client = Client::with_options(ClientOptions::default()).unwrap();
+    client.database(name).into()
+}
+
+pub(crate) type Fields = BTreeMap<&'static str, Option<String>>;
+
+/// The declared fields of every span, with the values set at creation or recorded later, and
+/// the spans entered.
+#[derive(Clone, Default)]
+pub(crate) struct Spans {
+    pub(crate) spans: Arc<Mutex<Vec<(&'static Metadata<'static>, Fields)>>>,
+    pub(crate) entered: Arc<Mutex<Vec<Id>>>,
+}
+
+struct Values<'a>(&'a mut Fields);
+
+impl Visit for Values<'_> {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0.insert(field.name(), Some(format!("{:?}", value)));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name(), Some(value.to_string()));
+    }
+}
+
+impl Subscriber for Spans {
+    fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, span: &Attributes<'_>) -> Id {
+        let mut fields: BTreeMap<_, _> = span
+            .metadata()
+            .fields()
+            .iter()
+            .map(|field| (field.name(), None))
+            .collect();
+        span.record(&mut Values(&mut fields));
+        let mut spans = self.spans.lock().unwrap();
+        spans.push((span.metadata(), fields));
+        Id::from_u64(spans.len() as u64)
+    }
+
+    fn record(&self, span: &Id, values: &Record<'_>) {
+        let mut spans = self.spans.lock().unwrap();
+        let (_, fields) = &mut spans[span.into_u64() as usize - 1];
+        // Like a real subscriber, only keep the fields the span declared.
+        let mut recorded = BTreeMap::new();
+        values.record(&mut Values(&mut recorded));
+        for (name, value) in recorded {
+            if let Some(field) = fields.get_mut(name) {
+                *field = value;
+            }
+        }
+    }
+
+    fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+    fn event(&self, _event: &Event<'_>) {}
+
+    fn enter(&self, span: &Id) {
+        self.entered.lock().unwrap().push(span.clone());
+    }
+
+    fn exit(&self, _span: &Id) {
+        self.entered.lock().unwrap().pop();
+    }
+
+    fn current_span(&self) -> Current {
+        match self.entered.lock().unwrap().last() {
+            Some(span) => {
+                let (metadata, _) = self.spans.lock().unwrap()[span.into_u64() as usize - 1];
+                Current::new(span.clone(), metadata)
+            }
+            None => Current::none(),
+        }
+    }
+}
@@ -0,0 +1,380 @@
+use std::marker::PhantomData;
+
+use mongodb::bson::{doc, to_bson, Bson, Document};
+use mongodb::error::Result;
+use mongodb::options::UpdateModifications;
+use serde::Serialize;
+
+#[cfg(feature = "derive")]
+pub use mongo_tracing_derive::Fields;
+
+/// Types whose field paths are known at compile time, usually through `#[derive(Fields)]`.
+pub trait Fields {
+    /// The accessors of the field paths, for documents of type `R`.
+    type Paths<R>;
+
+    /// The paths of the fields, each prefixed with `prefix`.
+    fn paths<R>(prefix: &str) -> Self::Paths<R>;
+
+    /// The paths of the fields of `Self` as the root document.
+    fn fields() -> Self::Paths<Self>
+    where
+        Self: Sized,
+    {
+        Self::paths("")
+    }
+}
+
+/// Nested paths of an optional document are those of the document.
+impl<T: Fields> Fields for Option<T> {
+    type Paths<R> = T::Paths<R>;
+
+    fn paths<R>(prefix: &str) -> T::Paths<R> {
+        T::paths(prefix)
+    }
+}
+
+/// Nested paths of an array of documents match its elements.
+impl<T: Fields> Fields for Vec<T> {
+    type Paths<R> = T::Paths<R>;
+
+    fn paths<R>(prefix: &str) -> T::Paths<R> {
+        T::paths(prefix)
+    }
+}
+
+/// The dotted path of a field holding a `V` in documents of type `R`.
+pub struct Field<R, V> {
+    path: String,
+    types: PhantomData<fn() -> (R, V)>,
+}
+
+impl<R, V> Field<R, V> {
+    pub fn new(path: impl Into<String>) -> Self {
+        Field {
+            path: path.into(),
+            types: PhantomData,
+        }
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+}
+
+impl<R, V> Clone for Field<R, V> {
+    fn clone(&self) -> Self {
+        Field::new(self.path.clone())
+    }
+}
+
+impl<R, V> std::fmt::Debug for Field<R, V> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Field").field(&self.path).finish()
+    }
+}
+
+/// Builds a filter on documents of type `R` from typed fields.
+///
+/// Values are serialized as they are added; the first failure is returned by
+/// [`Filter::into_document`].
+pub struct Filter<R> {
+    document: Document,
+    error: Option<mongodb::error::Error>,
+    root: PhantomData<fn() -> R>,
+}
+
+impl<R> Default for Filter<R> {
+    fn default() -> Self {
+        Filter {
+            document: Document::new(),
+            error: None,
+            root: PhantomData,
+        }
+    }
+}
+
+impl<R> Filter<R> {
+    pub fn new() -> Self {
+        Filter::default()
+    }
+
+    /// The field equals `value`: a plain `{ path: value }` the first time the path is used,
+    /// `$eq` alongside the conditions already on it otherwise.
+    pub fn eq<V: Serialize>(mut self, field: Field<R, V>, value: impl Into<V>) -> Self {
+        if let Some(value) = self.value(&value.into()) {
+            if self.document.contains_key(&field.path) {
+                self.operator(field.path, "$eq", value);
+            } else {
+                self.document.insert(field.path, value);
+            }
+        }
+        self
+    }
+
+    pub fn ne<V: Serialize>(self, field: Field<R, V>, value: impl Into<V>) -> Self {
+        self.compare(field, "$ne", value.into())
+    }
+
+    pub fn gt<V: Serialize>(self, field: Field<R, V>, value: impl Into<V>) -> Self {
+        self.compare(field, "$gt", value.into())
+    }
+
+    pub fn gte<V: Serialize>(self, field: Field<R, V>, value: impl Into<V>) -> Self {
+        self.compare(field, "$gte", value.into())
+    }
+
+    pub fn lt<V: Serialize>(self, field: Field<R, V>, value: impl Into<V>) -> Self {
+        self.compare(field, "$lt", value.into())
+    }
+
+    pub fn lte<V: Serialize>(self, field: Field<R, V>, value: impl Into<V>) -> Self {
+        self.compare(field, "$lte", value.into())
+    }
+
+    /// `$in`: the field equals one of `values`.
+    pub fn is_in<V: Serialize>(
+        self,
+        field: Field<R, V>,
+        values: impl IntoIterator<Item = impl Into<V>>,
+    ) -> Self {
+        let values: Vec<V> = values.into_iter().map(Into::into).collect();
+        self.compare(field, "$in", values)
+    }
+
+    pub fn exists<V>(mut self, field: Field<R, V>, exists: bool) -> Self {
+        self.operator(field.path, "$exists", Bson::Boolean(exists));
+        self
+    }
+
+    pub fn into_document(self) -> Result<Document> {
+        match self.error {
+            Some(error) => Err(error),
+            None => Ok(self.document),
+        }
+    }
+
+    fn compare<V, W: Serialize>(mut self, field: Field<R, V>, operator: &str, value: W) -> Self {
+        if let Some(value) = self.value(&value) {
+            self.operator(field.path, operator, value);
+        }
+        self
+    }
+
+    /// Adds `operator` to the conditions already applied to `path`: an equality becomes `$eq`,
+    /// and an operator the path already has is added as a separate `$and` clause.
+    fn operator(&mut self, path: String, operator: &str, value: Bson) {
+        match self.document.get_mut(&path) {
+            None => {
+                self.document.insert(path, doc! { operator: value });
+            }
+            Some(Bson::Document(operators)) if is_operators(operators) => {
+                if operators.contains_key(operator) {
+                    let clause = Bson::Document(doc! { path: { operator: value } });
+                    match self.document.get_mut("$and") {
+                        Some(Bson::Array(clauses)) => clauses.push(clause),
+                        _ => {
+                            self.document.insert("$and", vec![clause]);
+                        }
+                    }
+                } else {
+                    operators.insert(operator, value);
+                }
+            }
+            Some(equal) => {
+                let equal = std::mem::replace(equal, Bson::Null);
+                self.document.insert(path.clone(), doc! { "$eq": equal });
+                self.operator(path, operator, value);
+            }
+        }
+    }
+
+    fn value(&mut self, value: &impl Serialize) -> Option<Bson> {
+        serialize(value, &mut self.error)
+    }
+}
+
+/// Builds an update of documents of type `R` from typed fields.
+///
+/// Values are serialized as they are added; the first failure is returned by
+/// [`Update::into_document`] and [`Update::into_modifications`].
+pub struct Update<R> {
+    document: Document,
+    error: Option<mongodb::error::Error>,
+    root: PhantomData<fn() -> R>,
+}
+
+impl<R> Default for Update<R> {
+    fn default() -> Self {
+        Update {
+            document: Document::new(),
+            error: None,
+            root: PhantomData,
+        }
+    }
+}
+
+impl<R> Update<R> {
+    pub fn new() -> Self {
+        Update::default()
+    }
+
+    pub fn set<V: Serialize>(self, field: Field<R, V>, value: impl Into<V>) -> Self {
+        self.operator("$set", field.path, &value.into())
+    }
+
+    pub fn inc<V: Serialize>(self, field: Field<R, V>, by: impl Into<V>) -> Self {
+        self.operator("$inc", field.path, &by.into())
+    }
+
+    pub fn push<E: Serialize>(self, field: Field<R, Vec<E>>, value: impl Into<E>) -> Self {
+        self.operator("$push", field.path, &value.into())
+    }
+
+    pub fn unset<V>(self, field: Field<R, V>) -> Self {
+        self.operator("$unset", field.path, &"")
+    }
+
+    pub fn into_document(self) -> Result<Document> {
+        match self.error {
+            Some(error) => Err(error),
+            None => Ok(self.document),
+        }
+    }
+
+    pub fn into_modifications(self) -> Result<UpdateModifications> {
+        self.into_document().map(UpdateModifications::Document)
+    }
+
+    fn operator(mut self, operator: &str, path: String, value: &impl Serialize) -> Self {
+        let Some(value) = serialize(value, &mut self.error) else {
+            return self;
+        };
+        match self.document.get_mut(operator) {
+            Some(Bson::Document(fields)) => {
+                fields.insert(path, value);
+            }
+            _ => {
+                let mut fields = Document::new();
+                fields.insert(path, value);
+                self.document.insert(operator, fields);
+            }
+        }
+        self
+    }
+}
+
+fn is_operators(document: &Document) -> bool {
+    !document.is_empty() && document.keys().all(|key| key.starts_with('$'))
+}
+
+/// Serializes `value`, keeping the first error in `error`.
+fn serialize(value: &impl Serialize, error: &mut Option<mongodb::error::Error>) -> Option<Bson> {
+    match to_bson(value) {
+        Ok(value) => Some(value),
+        Err(failure) => {
+            error.get_or_insert_with(|| failure.into());
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct User;
+
+    fn age() -> Field<User, i32> {
+        Field::new("age")
+    }
+
+    fn name() -> Field<User, String> {
+        Field::new("name")
+    }
+
+    fn tags() -> Field<User, Vec<String>> {
+        Field::new("tags")
+    }
+
+    #[test]
+    fn filters_combine_conditions_on_a_path() {
+        let cases = [
+            (Filter::new().eq(age(), 3), doc! { "age": 3 }),
+            (
+                Filter::new().gte(age(), 18).lt(age(), 65),
+                doc! { "age": { "$gte": 18, "$lt": 65 } },
+            ),
+            (
+                Filter::new().eq(age(), 3).gt(age(), 1),
+                doc! { "age": { "$eq": 3, "$gt": 1 } },
+            ),
+            (
+                Filter::new().gt(age(), 1).eq(age(), 3),
+                doc! { "age": { "$gt": 1, "$eq": 3 } },
+            ),
+            (
+                Filter::new().ne(age(), 1).ne(age(), 2),
+                doc! { "age": { "$ne": 1 }, "$and": [{ "age": { "$ne": 2 } }] },
+            ),
+            (
+                Filter::new().eq(age(), 1).eq(age(), 2),
+                doc! { "age": { "$eq": 1 }, "$and": [{ "age": { "$eq": 2 } }] },
+            ),
+            (
+                Filter::new()
+                    .eq(name(), "ada")
+                    .is_in(age(), [1, 2])
+                    .exists(tags(), true),
+                doc! { "name": "ada", "age": { "$in": [1, 2] }, "tags": { "$exists": true } },
+            ),
+        ];
+        for (filter, expected) in cases {
+            assert_eq!(filter.into_document().unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn equal_documents_are_not_taken_for_operators() {
+        let address: Field<User, Document> = Field::new("address");
+        let filter = Filter::new()
+            .eq(address.clone(), doc! { "city": "paris" })
+            .ne(address, doc! {});
+        assert_eq!(
+            filter.into_document().unwrap(),
+            doc! { "address": { "$eq": { "city": "paris" }, "$ne": {} } }
+        );
+    }
+
+    #[test]
+    fn updates_group_fields_by_operator() {
+        let update = Update::new()
+            .set(name(), "ada")
+            .inc(age(), 1)
+            .push(tags(), "new")
+            .unset(Field::<User, bool>::new("deleted"))
+            .set(Field::<User, i32>::new("address.zip"), 75001);
+        assert_eq!(
+            update.into_document().unwrap(),
+            doc! {
+                "$set": { "name": "ada", "address.zip": 75001 },
+                "$inc": { "age": 1 },
+                "$push": { "tags": "new" },
+                "$unset": { "deleted": "" },
+            }
+        );
+    }
+
+    #[test]
+    fn serialization_failures_are_kept() {
+        let field: Field<User, u64> = Field::new("big");
+        assert!(Filter::new()
+            .eq(field.clone(), u64::MAX)
+            .into_document()
+            .is_err());
+        assert!(Update::new()
+            .set(field, u64::MAX)
+            .into_modifications()
+            .is_err());
+    }
+}
@@ -0,0 +1,309 @@
+use std::borrow::Borrow;
+
+use mongodb::bson::{doc, to_document, Bson, Document};
+use mongodb::error::Result;
+use mongodb::options::{ReplaceOptions, UpdateModifications, UpdateOptions};
+use mongodb::results::UpdateResult;
+use serde::Serialize;
+use tracing::instrument;
+
+use crate::error::{is_duplicate_key, Error};
+use crate::mongo_tracing::InstrumentedCollection;
+use crate::query;
+
+/// A view of an [`InstrumentedCollection`] whose writes only apply to the version of the
+/// document they were computed from.
+///
+/// Each write takes the version the caller read; it adds `version == expected` to the filter
+/// and moves the document to the next version. When no document matches, the write fails with
+/// [`Error::VersionConflict`], which is recorded on the span as `db.version.conflict` and
+/// counted in `db.version.conflicts`; so does an upsert at a stale version, whose insert
+/// collides with the document's `_id`. Documents without the field are at version 0.
+pub struct VersionedCollection<T> {
+    inner: InstrumentedCollection<T>,
+    field: String,
+}
+
+impl<T> InstrumentedCollection<T> {
+    /// Versions documents through the `version` field unless changed with
+    /// [`VersionedCollection::version_field`].
+    pub fn versioned(&self) -> VersionedCollection<T> {
+        VersionedCollection {
+            inner: self.clone(),
+            field: "version".to_string(),
+        }
+    }
+}
+
+impl<T> Clone for VersionedCollection<T> {
+    fn clone(&self) -> Self {
+        VersionedCollection {
+            inner: self.inner.clone(),
+            field: self.field.clone(),
+        }
+    }
+}
+
+impl<T> VersionedCollection<T> {
+    pub fn version_field(mut self, field: impl Into<String>) -> Self {
+        self.field = field.into();
+        self
+    }
+
+    /// The underlying collection, without concurrency checks.
+    pub fn collection(&self) -> &InstrumentedCollection<T> {
+        &self.inner
+    }
+
+    fn at_version(&self, query: Document, expected: i64) -> Document {
+        let predicate = match expected {
+            0 => doc! { &self.field: { "$in": [0_i64, Bson::Null] } },
+            _ => doc! { &self.field: expected },
+        };
+        query::and(Some(query), predicate)
+    }
+
+    fn bump(&self, update: UpdateModifications) -> UpdateModifications {
+        match update {
+            UpdateModifications::Document(mut update) => {
+                match update.get_mut("$inc") {
+                    Some(Bson::Document(increments)) => {
+                        increments.insert(&self.field, 1_i64);
+                    }
+                    // Left for the server to reject rather than dropping the caller's operator.
+                    Some(_) => {}
+                    None => {
+                        update.insert("$inc", doc! { &self.field: 1_i64 });
+                    }
+                }
+                UpdateModifications::Document(update)
+            }
+            UpdateModifications::Pipeline(mut pipeline) => {
+                let current = format!("${}", self.field);
+                pipeline.push(doc! {
+                    "$set": { &self.field: { "$add": [{ "$ifNull": [current, 0_i64] }, 1_i64] } }
+                });
+                UpdateModifications::Pipeline(pipeline)
+            }
+            other => other,
+        }
+    }
+
+    /// The outcome of a write at version `expected`: a conflict when it found no document, or
+    /// when, as an upsert missing the document at a stale version, it collided with its `_id`.
+    fn checked(
+        &self,
+        result: Result<UpdateResult>,
+        expected: i64,
+        upsert: bool,
+    ) -> Result<UpdateResult> {
+        match result {
+            Ok(result) if applied(result.matched_count, result.upserted_id.as_ref()) => Ok(result),
+            Ok(_) => Err(self.conflict(expected)),
+            Err(error) if upsert && is_duplicate_key(&error) => Err(self.conflict(expected)),
+            Err(error) => Err(error),
+        }
+    }
+
+    fn conflict(&self, expected: i64) -> mongodb::error::Error {
+        tracing::Span::current().record("db.version.conflict", true);
+        tracing::warn!(
+            monotonic_counter.db.version.conflicts = 1_u64,
+            "version {} of the document is no longer current",
+            expected
+        );
+        Error::VersionConflict {
+            field: self.field.clone(),
+            expected,
+        }
+        .into()
+    }
+
+    #[instrument(
+    fields(
+    db.name = % self.inner.info.database_name ,
+    db.system = "mongodb",
+    db.collection = % self.inner.name(),
+    otel.kind = "client",
+    tenant.id = self.inner.info.tenant.as_deref(),
+    policy.violation = tracing::field::Empty,
+    db.request.size = tracing::field::Empty,
+    db.response.size = tracing::field::Empty,
+    db.version.expected = expected,
+    db.version.conflict = tracing::field::Empty,
+    ),
+    skip(self,query,update,options)
+    )]
+    pub async fn update_one(
+        &self,
+        query: Document,
+        update: impl Into<UpdateModifications>,
+        expected: i64,
+        options: impl Into<Option<UpdateOptions>>,
+    ) -> Result<UpdateResult> {
+        let options = options.into();
+        let upsert = options.as_ref().and_then(|options| options.upsert) == Some(true);
+        let result = self
+            .inner
+            .run_update_one(
+                self.at_version(query.clone(), expected),
+                Some(&query),
+                self.bump(update.into()),
+                options,
+            )
+            .await;
+        self.checked(result, expected, upsert)
+    }
+}
+
+/// Whether a versioned write found the document at the expected version, or inserted it as an
+/// upsert.
+fn applied(matched_count: u64, upserted_id: Option<&Bson>) -> bool {
+    matched_count > 0 || upserted_id.is_some()
+}
+
+impl<T> VersionedCollection<T>
+where
+    T: Serialize,
+{
+    /// Replaces the document, storing `replacement` at version `expected + 1`.
+    #[instrument(
+    fields(
+    db.name = % self.inner.info.database_name ,
+    db.system = "mongodb",
+    db.collection = % self.inner.name(),
+    otel.kind = "client",
+    tenant.id = self.inner.info.tenant.as_deref(),
+    policy.violation = tracing::field::Empty,
+    db.request.size = tracing::field::Empty,
+    db.response.size = tracing::field::Empty,
+    db.version.expected = expected,
+    db.version.conflict = tracing::field::Empty,
+    ),
+    skip(self,query,replacement,options)
+    )]
+    pub async fn replace_one(
+        &self,
+        query: Document,
+        replacement: impl Borrow<T>,
+        expected: i64,
+        options: impl Into<Option<ReplaceOptions>>,
+    ) -> Result<UpdateResult> {
+        let mut replacement = to_document(replacement.borrow())?;
+        replacement.insert(&self.field, expected + 1);
+        let options = options.into();
+        let upsert = options.as_ref().and_then(|options| options.upsert) == Some(true);
+        let result = self
+            .inner
+            .clone_with_type::<Document>()
+            .run_replace_one(self.at_version(query, expected), &replacement, options)
+            .await;
+        self.checked(result, expected, upsert)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::duplicate_key;
+    use crate::testing::test_database;
+
+    fn orders() -> VersionedCollection<Document> {
+        test_database("app")
+            .collection::<Document>("orders")
+            .versioned()
+            .version_field("rev")
+    }
+
+    fn bumped(update: UpdateModifications) -> UpdateModifications {
+        orders().bump(update)
+    }
+
+    #[tokio::test]
+    async fn bumps_join_an_existing_inc() {
+        let update = doc! { "$inc": { "total": 5 }, "$set": { "status": "paid" } };
+        match bumped(UpdateModifications::Document(update)) {
+            UpdateModifications::Document(update) => assert_eq!(
+                update,
+                doc! { "$inc": { "total": 5, "rev": 1_i64 }, "$set": { "status": "paid" } }
+            ),
+            other => panic!("expected an update document, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn bumps_add_an_inc() {
+        let update = doc! { "$set": { "status": "paid" } };
+        match bumped(UpdateModifications::Document(update)) {
+            UpdateModifications::Document(update) => assert_eq!(
+                update,
+                doc! { "$set": { "status": "paid" }, "$inc": { "rev": 1_i64 } }
+            ),
+            other => panic!("expected an update document, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn bumps_leave_a_malformed_inc_to_the_server() {
+        let update = doc! { "$inc": "total" };
+        match bumped(UpdateModifications::Document(update.clone())) {
+            UpdateModifications::Document(bumped) => assert_eq!(bumped, update),
+            other => panic!("expected an update document, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn bumps_end_pipelines_with_a_set() {
+        let pipeline = vec![doc! { "$set": { "status": "paid" } }];
+        match bumped(UpdateModifications::Pipeline(pipeline)) {
+            UpdateModifications::Pipeline(pipeline) => assert_eq!(
+                pipeline,
+                vec![
+                    doc! { "$set": { "status": "paid" } },
+                    doc! { "$set": { "rev": { "$add": [{ "$ifNull": ["$rev", 0_i64] }, 1_i64] } } },
+                ]
+            ),
+            other => panic!("expected a pipeline, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn filters_select_the_expected_version() {
+        let orders = orders();
+        assert_eq!(
+            orders.at_version(doc! { "_id": 1 }, 3),
+            doc! { "$and": [{ "_id": 1 }, { "rev": 3_i64 }] }
+        );
+        assert_eq!(
+            orders.at_version(doc! { "_id": 1 }, 0),
+            doc! { "$and": [{ "_id": 1 }, { "rev": { "$in": [0_i64, Bson::Null] } }] }
+        );
+        assert_eq!(orders.at_version(doc! {}, 2), doc! { "rev": 2_i64 });
+    }
+
+    #[tokio::test]
+    async fn stale_upserts_colliding_with_the_document_are_conflicts() {
+        let orders = orders();
+        let collided = || Err(duplicate_key(&Bson::Int32(1)));
+        let error = orders.checked(collided(), 3, true).unwrap_err();
+        assert_eq!(
+            Error::from_mongo(&error),
+            Some(&Error::VersionConflict {
+                field: "rev".to_string(),
+                expected: 3,
+            })
+        );
+        // Without upsert the duplicate comes from another unique index.
+        let error = orders.checked(collided(), 3, false).unwrap_err();
+        assert!(is_duplicate_key(&error));
+    }
+
+    #[test]
+    fn upserts_are_not_conflicts() {
+        let id = Bson::Int32(1);
+        let cases = [(1, None, true), (0, Some(&id), true), (0, None, false)];
+        for (matched_count, upserted_id, expected) in cases {
+            assert_eq!(applied(matched_count, upserted_id), expected);
+        }
+    }
+}
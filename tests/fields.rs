@@ -0,0 +1,70 @@
+#![cfg(feature = "derive")]
+
+use mongo_tracing::typed::{Fields, Filter, Update};
+use mongodb::bson::doc;
+use serde::{Deserialize, Serialize};
+
+#[allow(dead_code)]
+#[derive(Serialize, Deserialize, Fields)]
+#[serde(rename_all = "camelCase")]
+struct User {
+    #[serde(rename = "_id")]
+    id: i32,
+    first_name: String,
+    #[serde(skip)]
+    session: Option<String>,
+    #[fields(nested)]
+    home_address: Address,
+    #[serde(flatten)]
+    audit: Audit,
+    r#type: String,
+}
+
+#[allow(dead_code)]
+#[derive(Serialize, Deserialize, Fields)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+struct Address {
+    zip_code: String,
+}
+
+#[allow(dead_code)]
+#[derive(Serialize, Deserialize, Fields)]
+struct Audit {
+    created_by: String,
+}
+
+#[test]
+fn paths_follow_serde_attributes() {
+    let user = User::fields();
+    assert_eq!(user.id().path(), "_id");
+    assert_eq!(user.first_name().path(), "firstName");
+    assert_eq!(
+        user.home_address().zip_code().path(),
+        "homeAddress.ZIP_CODE"
+    );
+    assert_eq!(user.audit().created_by().path(), "created_by");
+    assert_eq!(user.r#type().path(), "type");
+}
+
+#[test]
+fn typed_paths_build_filters_and_updates() {
+    let user = User::fields();
+    let filter = Filter::new()
+        .eq(user.first_name(), "Ada")
+        .ne(user.first_name(), "Bob")
+        .is_in(user.home_address().zip_code(), ["75001"])
+        .into_document()
+        .unwrap();
+    assert_eq!(
+        filter,
+        doc! {
+            "firstName": { "$eq": "Ada", "$ne": "Bob" },
+            "homeAddress.ZIP_CODE": { "$in": ["75001"] },
+        }
+    );
+    let update = Update::new()
+        .set(user.audit().created_by(), "admin")
+        .into_document()
+        .unwrap();
+    assert_eq!(update, doc! { "$set": { "created_by": "admin" } });
+}